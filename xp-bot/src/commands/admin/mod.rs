@@ -1,6 +1,7 @@
 pub mod add;
 pub mod remove;
 pub mod reset;
+pub mod restore;
 pub mod set;
 pub mod setlevel;
 pub mod setstreak;
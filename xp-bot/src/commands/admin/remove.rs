@@ -10,14 +10,16 @@ use serenity::{
     },
     prelude::Context,
 };
-use xp_db_connector::{guild::Guild, guild_member::GuildMember};
+use xp_db_connector::{
+    guild::Guild,
+    guild_member::{GuildMember, XpReason},
+};
 
 use crate::{
-    commands::XpCommand,
+    commands::{pipeline::Requirements, XpCommand},
     utils::{
-        colors,
-        math::calculate_level,
-        utils::{format_number, handle_level_roles},
+        batcher, colors, db,
+        utils::{db_error_embed, format_number, handle_level_roles},
     },
 };
 
@@ -54,11 +56,17 @@ impl XpCommand for RemoveCommand {
             .default_member_permissions(Permissions::MANAGE_GUILD)
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::admin()
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = db::client(ctx).await;
+
         let user = command
             .data
             .options
@@ -82,14 +90,47 @@ impl XpCommand for RemoveCommand {
 
         let guild_id = command.guild_id.unwrap().0;
 
-        let guild_member = GuildMember::from_id(guild_id, user).await?;
+        let guild = Guild::from_id(&db, guild_id).await?;
 
-        let new_amount = guild_member.xp - amount;
+        // applied on the store side, so xp the member earns at the same time is kept
+        let increment = match GuildMember::add_xp(
+            &db,
+            guild_id,
+            user,
+            -(amount as i64),
+            XpReason::Remove,
+            None,
+            Some(command.user.id.0),
+        )
+        .await
+        {
+            Ok(increment) => increment,
+            Err(why) => {
+                log::error!("Could not remove xp from {}: {}", user, why);
 
-        let guild = Guild::from_id(guild_id).await?;
-        let new_level = calculate_level(&new_amount);
+                command
+                    .create_interaction_response(&ctx.http, |response| {
+                        response
+                            .kind(InteractionResponseType::ChannelMessageWithSource)
+                            .interaction_response_data(|message| {
+                                message
+                                    .embed(|embed| {
+                                        db_error_embed(
+                                            embed,
+                                            &format!("Could not remove xp from <@{}>.", user),
+                                            &why,
+                                        )
+                                    })
+                                    .ephemeral(true);
+                                message
+                            })
+                    })
+                    .await?;
 
-        let _ = GuildMember::set_xp(guild_id, user, &new_amount, &guild_member).await?;
+                return Ok(());
+            }
+        };
+        batcher::get(ctx).await.forget(guild_id, user).await;
 
         command
             .create_interaction_response(&ctx.http, |response| {
@@ -115,7 +156,7 @@ impl XpCommand for RemoveCommand {
         handle_level_roles(
             &guild,
             &user,
-            &new_level,
+            &increment.newLevel,
             &ctx,
             command.guild_id.clone().unwrap().0,
         )
@@ -2,17 +2,33 @@ use serenity::{
     async_trait,
     builder::CreateApplicationCommand,
     model::{
-        self,
         prelude::{
             application_command::ApplicationCommandInteraction, command::CommandOptionType,
-            InteractionResponseType,
+            component::InputTextStyle, modal::ModalSubmitInteraction, InteractionResponseType,
         },
         Permissions,
     },
     prelude::Context,
 };
+use xp_db_connector::{
+    guild::Guild,
+    guild_member::{GuildMember, XpReason},
+};
 
-use crate::commands::XpCommand;
+use crate::{
+    commands::{
+        components::{input_value, CustomId},
+        pipeline::Requirements,
+        XpCommand,
+    },
+    utils::{
+        batcher, colors, db,
+        utils::{conform_xpc, db_error_embed, send_backup},
+    },
+};
+
+// the text input of the confirmation modals
+const CONFIRM_INPUT: &str = "confirm";
 
 pub struct ResetCommand;
 
@@ -60,113 +76,241 @@ impl XpCommand for ResetCommand {
             .default_member_permissions(Permissions::MANAGE_GUILD)
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::admin()
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let first_option = command.data.options.first().unwrap();
+        let guild_id = command.guild_id.unwrap().0;
+        let subcommand = command.data.options.first().unwrap();
+        let value = subcommand
+            .options
+            .first()
+            .and_then(|option| option.value.as_ref())
+            .and_then(|value| value.as_str())
+            .unwrap_or_default();
 
         /*
-           events are handled in /events/handler.rs
+            The reset itself happens once the modal is confirmed, in `modal` below.
+            > The ids are signed and carry the guild, so they can't be pointed at another member
+            > or community.
         */
+        let (title, id, label) = match (subcommand.name.as_str(), value) {
+            ("user", user_id) => (
+                "Reset user xp",
+                CustomId::new("reset", "user").arg(guild_id).arg(user_id),
+                "This action is irreversible.",
+            ),
+            ("community", "settings") => (
+                "Reset community settings",
+                CustomId::new("reset", "settings").arg(guild_id),
+                "A backup is sent to your dms before the reset.",
+            ),
+            ("community", "xp") => (
+                "Reset community xp",
+                CustomId::new("reset", "xp").arg(guild_id),
+                "A backup is sent to your dms before the reset.",
+            ),
+            _ => return Ok(()),
+        };
 
-        match first_option.kind {
-            CommandOptionType::SubCommand => match first_option.name.as_str() {
-                "user" => {
-                    let second_option = first_option.options.first().unwrap();
-
-                    match second_option.name.as_str() {
-                        "user" => {
-                            command
-                                .create_interaction_response(&ctx.http, |response| {
-                                    response
-                                        .kind(InteractionResponseType::Modal)
-                                        .interaction_response_data(|message| {
-                                            message.title("Reset user xp");
-                                            message.custom_id("reset_user_xp");
-                                            message.components(|components| {
-                                                components.create_action_row(|action_row| {
-                                                    action_row.create_input_text(|input_text| {
-                                                        input_text
-                                                            .placeholder("Type 'reset' to confirm.")
-                                                            .style(model::application::component::InputTextStyle::Short)
-                                                            .label("This action is irreversible.")
-                                                            .custom_id(
-                                                               format!("reset_user_xp_input_{}", command.data.options.first().unwrap().options.first().unwrap().value.as_ref().unwrap().as_str().unwrap())
-                                                            )
-                                                    })
-                                                })
-                                            })
-                                        })
-                                })
-                                .await?;
-                        }
-                        _ => {}
-                    }
-                }
-                "community" => {
-                    let second_option = first_option.options.first().unwrap();
-
-                    match second_option.value.as_ref().unwrap().as_str().unwrap() {
-                        "settings" => {
-                            command
-                                .create_interaction_response(&ctx.http, |response| {
-                                    response
-                                        .kind(InteractionResponseType::Modal)
-                                        .interaction_response_data(|message| {
-                                            message.title("Reset community settings");
-                                            message.custom_id("reset_community_settings");
-                                            message.components(|components| {
-                                                components.create_action_row(|action_row| {
-                                                    action_row.create_input_text(|input_text| {
-                                                        input_text
-                                                            .placeholder("Type 'reset' to confirm.")
-                                                            .style(model::application::component::InputTextStyle::Short)
-                                                            .label("This action is irreversible.")
-                                                            .custom_id(
-                                                               format!("reset_community_settings_input")
-                                                            )
-                                                    })
-                                                })
-                                            })
-                                        })
-                                })
-                                .await?;
-                        }
-                        "xp" => {
-                            command
-                                .create_interaction_response(&ctx.http, |response| {
-                                    response
-                                        .kind(InteractionResponseType::Modal)
-                                        .interaction_response_data(|message| {
-                                            message.title("Reset community xp");
-                                            message.custom_id("reset_community_xp");
-                                            message.components(|components| {
-                                                components.create_action_row(|action_row| {
-                                                    action_row.create_input_text(|input_text| {
-                                                        input_text
-                                                            .placeholder("Type 'reset' to confirm.")
-                                                            .style(model::application::component::InputTextStyle::Short)
-                                                            .label("This action is irreversible.")
-                                                            .custom_id(
-                                                               format!("reset_community_xp_input")
-                                                            )
-                                                    })
-                                                })
-                                            })
-                                        })
+        command
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::Modal)
+                    .interaction_response_data(|message| {
+                        message.title(title);
+                        message.custom_id(id.signed());
+                        message.components(|components| {
+                            components.create_action_row(|action_row| {
+                                action_row.create_input_text(|input_text| {
+                                    input_text
+                                        .placeholder("Type 'reset' to confirm.")
+                                        .style(InputTextStyle::Short)
+                                        .label(label)
+                                        .custom_id(CONFIRM_INPUT)
                                 })
-                                .await?;
-                        }
-                        _ => {}
-                    }
-                }
-                _ => {}
-            },
-            _ => {}
-        }
+                            })
+                        })
+                    })
+            })
+            .await?;
 
         Ok(())
     }
+
+    async fn modal(
+        &self,
+        ctx: &Context,
+        modal: &ModalSubmitInteraction,
+        id: CustomId,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let guild_id = match modal.guild_id {
+            Some(guild_id) if id.signed && id.get::<u64>(0) == Some(guild_id.0) => guild_id.0,
+            _ => return Ok(()),
+        };
+
+        let confirmed = input_value(modal, CONFIRM_INPUT)
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("reset"));
+        if !confirmed {
+            modal
+                .create_interaction_response(&ctx.http, |response| {
+                    response
+                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|message| {
+                            message.embed(|embed| {
+                                embed.description("Nothing was reset, type `reset` to confirm.");
+                                embed.color(colors::red())
+                            });
+                            message.ephemeral(true)
+                        })
+                })
+                .await?;
+
+            return Ok(());
+        }
+
+        match (id.action.as_str(), id.get::<u64>(1)) {
+            ("user", Some(user_id)) => reset_user(ctx, modal, guild_id, user_id).await,
+            ("settings", _) | ("xp", _) => reset_community(ctx, modal, guild_id, &id.action).await,
+            _ => Ok(()),
+        }
+    }
+}
+
+async fn reset_user(
+    ctx: &Context,
+    modal: &ModalSubmitInteraction,
+    guild_id: u64,
+    user_id: u64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db = db::client(ctx).await;
+
+    let action = match GuildMember::from_id(&db, guild_id, user_id).await {
+        Ok(guild_member) => {
+            let guild_member = conform_xpc(guild_member, ctx, &guild_id, &user_id).await;
+            GuildMember::set_xp(
+                &db,
+                guild_id,
+                user_id,
+                &0,
+                &guild_member,
+                XpReason::Reset,
+                Some(modal.user.id.0),
+            )
+            .await
+        }
+        Err(why) => Err(why),
+    };
+    batcher::get(ctx).await.discard(guild_id, user_id).await;
+
+    modal
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    match &action {
+                        Ok(_) => message.embed(|embed| {
+                            embed.description("Successfully reset user xp.");
+                            embed.color(colors::green())
+                        }),
+                        Err(why) => {
+                            log::error!("Could not reset user xp: {}", why);
+                            message.embed(|embed| {
+                                db_error_embed(embed, "Could not reset user xp.", why)
+                            })
+                        }
+                    };
+                    message.ephemeral(true)
+                })
+        })
+        .await?;
+
+    Ok(())
+}
+
+// `kind` is either "settings" or "xp"
+async fn reset_community(
+    ctx: &Context,
+    modal: &ModalSubmitInteraction,
+    guild_id: u64,
+    kind: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db = db::client(ctx).await;
+    let label = format!("community {}", kind);
+
+    // taking the backup can take a while on large communities
+    modal
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|message| message.ephemeral(true))
+        })
+        .await?;
+
+    // no reset without a backup in the member's hands to undo it, queued xp included
+    let batcher = batcher::get(ctx).await;
+    batcher.flush().await;
+
+    let before = format!("a reset of the {}", label);
+    if let Err(why) = send_backup(ctx, &db, guild_id, modal.user.id, &before).await {
+        log::error!("Could not back up community before reset: {}", why);
+
+        modal
+            .create_followup_message(&ctx.http, |message| {
+                message
+                    .embed(|embed| {
+                        embed.description(format!("{}\nNothing was reset.", why));
+                        embed.color(colors::red())
+                    })
+                    .ephemeral(true)
+            })
+            .await?;
+        return Ok(());
+    }
+
+    let action = if kind == "settings" {
+        let action = Guild::delete(&db, &guild_id).await;
+        db.invalidate_guild(guild_id);
+        action
+    } else {
+        let action = Guild::delete_xp(&db, &guild_id, Some(modal.user.id.0)).await;
+        batcher.discard_guild(guild_id).await;
+        action
+    };
+
+    if let Err(why) = action {
+        log::error!("Could not reset {}: {}", label, why);
+
+        modal
+            .create_followup_message(&ctx.http, |message| {
+                message
+                    .embed(|embed| {
+                        db_error_embed(embed, &format!("Could not reset {}.", label), &why)
+                    })
+                    .ephemeral(true)
+            })
+            .await?;
+        return Ok(());
+    }
+
+    modal
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .embed(|embed| {
+                    embed.description(format!(
+                        "Successfully reset {}. The backup in your dms can be loaded again with `/restore`.",
+                        label
+                    ));
+                    embed.color(colors::green())
+                })
+                .ephemeral(true)
+        })
+        .await?;
+
+    Ok(())
 }
@@ -0,0 +1,198 @@
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::{
+        prelude::{
+            application_command::ApplicationCommandInteraction, command::CommandOptionType,
+            AttachmentId, InteractionResponseType,
+        },
+        Permissions,
+    },
+    prelude::Context,
+};
+use xp_db_connector::{
+    snapshot::{GuildSnapshot, MAX_SNAPSHOT_SIZE},
+    DbError,
+};
+
+use crate::{
+    commands::{pipeline::Requirements, XpCommand},
+    utils::{
+        batcher, colors, db,
+        utils::{db_error_embed, send_backup},
+    },
+};
+
+pub struct RestoreCommand;
+
+#[async_trait]
+impl XpCommand for RestoreCommand {
+    fn name(&self) -> &'static str {
+        "restore"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .name("restore")
+            .description("Restore the settings and xp of this community from a backup.")
+            .create_option(|option| {
+                option
+                    .name("snapshot")
+                    .description("The backup file that was sent along with a reset.")
+                    .kind(CommandOptionType::Attachment)
+                    .required(true)
+            })
+            .default_member_permissions(Permissions::MANAGE_GUILD)
+    }
+
+    fn requirements(&self) -> Requirements {
+        Requirements::admin()
+    }
+
+    async fn exec(
+        &self,
+        ctx: &Context,
+        command: &ApplicationCommandInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = db::client(ctx).await;
+        let guild_id = command.guild_id.unwrap();
+
+        let attachment = command
+            .data
+            .options
+            .first()
+            .and_then(|option| option.value.as_ref())
+            .and_then(|value| value.as_str())
+            .and_then(|id| id.parse::<u64>().ok())
+            .and_then(|id| command.data.resolved.attachments.get(&AttachmentId(id)));
+
+        let attachment = match attachment {
+            Some(attachment) if attachment.size <= MAX_SNAPSHOT_SIZE as u64 => attachment,
+            _ => {
+                return reply_error(
+                    ctx,
+                    command,
+                    "Could not read the backup.",
+                    &DbError::Invalid("the file is missing or too large".to_string()),
+                )
+                .await;
+            }
+        };
+
+        let snapshot = match GuildSnapshot::parse(&attachment.download().await?, guild_id.0) {
+            Ok(snapshot) => snapshot,
+            Err(why) => {
+                return reply_error(ctx, command, "This backup can not be restored.", &why).await;
+            }
+        };
+
+        // capturing and importing can take a while on large communities
+        command
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                    .interaction_response_data(|message| message.ephemeral(true))
+            })
+            .await?;
+
+        // the state that is overwritten, in case the wrong backup was picked, queued xp included
+        let batcher = batcher::get(ctx).await;
+        batcher.flush().await;
+
+        if let Err(why) = send_backup(
+            ctx,
+            &db,
+            guild_id.0,
+            command.user.id,
+            "a restore from a backup",
+        )
+        .await
+        {
+            log::error!("Could not back up community before restore: {}", why);
+
+            command
+                .create_followup_message(&ctx.http, |message| {
+                    message
+                        .embed(|embed| {
+                            embed.description(format!("{}\nNothing was restored.", why));
+                            embed.color(colors::red())
+                        })
+                        .ephemeral(true)
+                })
+                .await?;
+            return Ok(());
+        }
+
+        let action = snapshot
+            .restore(&db, guild_id.0, Some(command.user.id.0))
+            .await;
+        db.invalidate_guild(guild_id.0);
+        batcher.discard_guild(guild_id.0).await;
+
+        if let Err(why) = action {
+            return followup_error(ctx, command, "Could not restore the backup.", &why).await;
+        }
+
+        command
+            .create_followup_message(&ctx.http, |message| {
+                message
+                    .embed(|embed| {
+                        embed.description(format!(
+                            "Restored the settings and the xp of **{}** members from <t:{}:f>.\nThe backup in your dms holds the state from before the restore.",
+                            snapshot.members.len(),
+                            snapshot.createdAt
+                        ));
+                        embed.color(colors::green());
+                        embed
+                    })
+                    .ephemeral(true)
+            })
+            .await?;
+
+        Ok(())
+    }
+}
+
+async fn reply_error(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    action: &str,
+    why: &DbError,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .embed(|embed| db_error_embed(embed, action, why))
+                        .ephemeral(true);
+                    message
+                })
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn followup_error(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    action: &str,
+    why: &DbError,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    log::error!("{} ({}): {}", action, command.guild_id.unwrap().0, why);
+
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .embed(|embed| db_error_embed(embed, action, why))
+                .ephemeral(true)
+        })
+        .await?;
+
+    Ok(())
+}
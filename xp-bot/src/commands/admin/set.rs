@@ -10,14 +10,17 @@ use serenity::{
     },
     prelude::Context,
 };
-use xp_db_connector::{guild::Guild, guild_member::GuildMember};
+use xp_db_connector::{
+    guild::Guild,
+    guild_member::{GuildMember, XpReason},
+};
 
 use crate::{
-    commands::XpCommand,
+    commands::{pipeline::Requirements, XpCommand},
     utils::{
-        colors,
+        batcher, colors, db,
         math::calculate_level,
-        utils::{format_number, handle_level_roles},
+        utils::{db_error_embed, format_number, handle_level_roles},
     },
 };
 
@@ -54,11 +57,17 @@ impl XpCommand for SetCommand {
             .default_member_permissions(Permissions::MANAGE_GUILD)
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::admin()
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = db::client(ctx).await;
+
         let user = command
             .data
             .options
@@ -83,12 +92,50 @@ impl XpCommand for SetCommand {
 
         let guild_id = command.guild_id.unwrap().0;
 
-        let guild_member = GuildMember::from_id(guild_id, user).await?;
+        // write queued message xp first, the new value is based on what the api has
+        let batcher = batcher::get(ctx).await;
+        batcher.flush().await;
 
-        let guild = Guild::from_id(guild_id).await?;
+        let guild_member = GuildMember::from_id(&db, guild_id, user).await?;
+
+        let guild = Guild::from_id(&db, guild_id).await?;
         let new_level = calculate_level(&amount);
 
-        let _ = GuildMember::set_xp(guild_id, user, &amount, &guild_member).await?;
+        if let Err(why) = GuildMember::set_xp(
+            &db,
+            guild_id,
+            user,
+            &amount,
+            &guild_member,
+            XpReason::Set,
+            Some(command.user.id.0),
+        )
+        .await
+        {
+            log::error!("Could not set xp of {}: {}", user, why);
+
+            command
+                .create_interaction_response(&ctx.http, |response| {
+                    response
+                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|message| {
+                            message
+                                .embed(|embed| {
+                                    db_error_embed(
+                                        embed,
+                                        &format!("Could not set xp of <@{}>.", user),
+                                        &why,
+                                    )
+                                })
+                                .ephemeral(true);
+                            message
+                        })
+                })
+                .await?;
+
+            return Ok(());
+        }
+        batcher.forget(guild_id, user).await;
 
         command
             .create_interaction_response(&ctx.http, |response| {
@@ -3,19 +3,27 @@ use serenity::{
     builder::CreateApplicationCommand,
     model::{
         prelude::{
-            application_command::ApplicationCommandInteraction, command::CommandOptionType,
+            application_command::ApplicationCommandInteraction,
+            autocomplete::AutocompleteInteraction, command::CommandOptionType,
             InteractionResponseType,
         },
         Permissions,
     },
     prelude::Context,
 };
-use xp_db_connector::{guild::Guild, guild_member::GuildMember};
+use xp_db_connector::{
+    guild::Guild,
+    guild_member::{GuildMember, XpReason},
+};
 
 use crate::{
-    commands::XpCommand,
+    commands::{
+        autocomplete::{self, MAX_CHOICES},
+        pipeline::Requirements,
+        XpCommand,
+    },
     utils::{
-        colors,
+        batcher, colors, db,
         math::{calculate_level, get_required_xp},
         utils::handle_level_roles,
     },
@@ -50,15 +58,22 @@ impl XpCommand for SetLevelCommand {
                     .kind(CommandOptionType::Integer)
                     .required(true)
                     .min_int_value(1)
+                    .set_autocomplete(true)
             })
             .default_member_permissions(Permissions::MANAGE_GUILD)
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::admin()
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = db::client(ctx).await;
+
         let user_id = command
             .data
             .options
@@ -85,18 +100,27 @@ impl XpCommand for SetLevelCommand {
 
         let required_xp = get_required_xp(level as i32);
 
-        let guild_member = GuildMember::from_id(command.guild_id.unwrap().into(), user_id).await?;
+        // write queued message xp first, the new value is based on what the api has
+        let batcher = batcher::get(ctx).await;
+        batcher.flush().await;
 
-        let guild = Guild::from_id(command.guild_id.unwrap().into()).await?;
+        let guild_member =
+            GuildMember::from_id(&db, command.guild_id.unwrap().into(), user_id).await?;
+
+        let guild = Guild::from_id(&db, command.guild_id.unwrap().into()).await?;
         let new_level = calculate_level(&(required_xp as u64));
 
-        let _ = GuildMember::set_xp(
+        GuildMember::set_xp(
+            &db,
             command.guild_id.unwrap().into(),
             user_id,
             &(required_xp as u64),
             &guild_member,
+            XpReason::SetLevel,
+            Some(command.user.id.0),
         )
         .await?;
+        batcher.forget(command.guild_id.unwrap().0, user_id).await;
 
         let _ = command
             .create_interaction_response(&ctx.http, |response| {
@@ -128,4 +152,54 @@ impl XpCommand for SetLevelCommand {
 
         Ok(())
     }
+
+    // levels that hand out a level role, so they are easy to hit
+    async fn autocomplete(
+        &self,
+        ctx: &Context,
+        interaction: &AutocompleteInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let guild_id = match interaction.guild_id {
+            Some(guild_id) => guild_id,
+            None => return Ok(()),
+        };
+        let typed = autocomplete::focused(interaction)
+            .map(autocomplete::typed)
+            .unwrap_or_default();
+
+        let db = db::client(ctx).await;
+        let mut levelroles = Guild::from_id(&db, guild_id.0).await?.levelroles;
+        levelroles.sort_by_key(|levelrole| levelrole.level);
+
+        let choices = levelroles
+            .iter()
+            .filter(|levelrole| levelrole.level >= 1)
+            .filter(|levelrole| levelrole.level.to_string().starts_with(&typed))
+            .take(MAX_CHOICES)
+            .map(|levelrole| {
+                let role = levelrole
+                    .id
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|role_id| ctx.cache.role(guild_id, role_id))
+                    .map(|role| role.name)
+                    .unwrap_or_else(|| levelrole.id.clone());
+
+                // choice names can be 100 characters long, role names as well
+                let name = format!("Level {} · {}", levelrole.level, role);
+                (name.chars().take(100).collect(), levelrole.level as i64)
+            })
+            .collect::<Vec<(String, i64)>>();
+
+        interaction
+            .create_autocomplete_response(&ctx.http, |response| {
+                for (name, level) in choices {
+                    response.add_int_choice(name, level);
+                }
+                response
+            })
+            .await?;
+
+        Ok(())
+    }
 }
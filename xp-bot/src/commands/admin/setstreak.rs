@@ -9,7 +9,10 @@ use serenity::{
 };
 use xp_db_connector::guild_member::GuildMember;
 
-use crate::{commands::XpCommand, utils::colors};
+use crate::{
+    commands::{pipeline::Requirements, XpCommand},
+    utils::{colors, db},
+};
 
 pub struct SetStreakCommand;
 
@@ -53,11 +56,17 @@ impl XpCommand for SetStreakCommand {
             .default_member_permissions(Permissions::MANAGE_GUILD)
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::admin()
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = db::client(ctx).await;
+
         let user_id = command
             .data
             .options
@@ -72,7 +81,7 @@ impl XpCommand for SetStreakCommand {
             .unwrap();
 
         let mut guild_member: GuildMember =
-            GuildMember::from_id(command.guild_id.unwrap().0, user_id).await?;
+            GuildMember::from_id(&db, command.guild_id.unwrap().0, user_id).await?;
 
         let streak_type = command
             .data
@@ -102,7 +111,7 @@ impl XpCommand for SetStreakCommand {
             _ => {}
         }
 
-        let _ = GuildMember::set_guild_member(command.guild_id.unwrap().0, user_id, guild_member)
+        GuildMember::set_guild_member(&db, command.guild_id.unwrap().0, user_id, guild_member)
             .await?;
 
         command
@@ -0,0 +1,61 @@
+use std::error::Error;
+
+use serenity::{
+    model::prelude::{
+        application_command::ApplicationCommandInteractionDataOption,
+        autocomplete::AutocompleteInteraction,
+    },
+    prelude::Context,
+};
+
+use super::find;
+
+// discord shows at most this many suggestions
+pub const MAX_CHOICES: usize = 25;
+
+// the option that is being typed, also inside of subcommands
+pub fn focused(
+    interaction: &AutocompleteInteraction,
+) -> Option<&ApplicationCommandInteractionDataOption> {
+    fn search(
+        options: &[ApplicationCommandInteractionDataOption],
+    ) -> Option<&ApplicationCommandInteractionDataOption> {
+        options.iter().find_map(|option| {
+            if option.focused {
+                Some(option)
+            } else {
+                search(&option.options)
+            }
+        })
+    }
+
+    search(&interaction.data.options)
+}
+
+// what has been typed into the focused option so far, lowercased for matching
+pub fn typed(option: &ApplicationCommandInteractionDataOption) -> String {
+    match &option.value {
+        Some(value) => match value.as_str() {
+            Some(value) => value.to_lowercase(),
+            None => value.to_string(),
+        },
+        None => String::new(),
+    }
+}
+
+// hands the interaction to the command that is being typed
+pub async fn route(
+    ctx: &Context,
+    interaction: &AutocompleteInteraction,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match find(&interaction.data.name) {
+        Some(command) => command.autocomplete(ctx, interaction).await,
+        None => {
+            log::warn!(
+                "Received autocomplete of unknown command: {:?}",
+                interaction.data.name
+            );
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::{env, error::Error, fmt, str::FromStr, sync::OnceLock};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::{hmac, rand::SystemRandom};
+use serenity::{
+    model::prelude::{
+        component::ActionRowComponent, message_component::MessageComponentInteraction,
+        modal::ModalSubmitInteraction,
+    },
+    prelude::Context,
+};
+
+use super::find;
+
+// discord rejects longer custom ids
+pub const MAX_CUSTOM_ID_LENGTH: usize = 100;
+
+const SEPARATOR: char = ':';
+const SIGNATURE_SEPARATOR: char = '~';
+
+/*
+    Custom id of a button, select menu or modal, written as `command:action:arg:...`.
+    > `command` is the name of the command that owns the component, interactions are routed
+    > to its `component` or `modal` handler.
+    > Signed ids end in `~` and an hmac of everything before it, ids that fail the check
+    > are not routed at all. Use them for anything that carries ids into a destructive action.
+    > Arguments can't contain `:` or `~`.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CustomId {
+    pub command: String,
+    pub action: String,
+    pub args: Vec<String>,
+    pub signed: bool,
+}
+
+impl CustomId {
+    pub fn new(command: &str, action: &str) -> Self {
+        CustomId {
+            command: command.to_string(),
+            action: action.to_string(),
+            args: Vec::new(),
+            signed: false,
+        }
+    }
+
+    pub fn arg(mut self, arg: impl ToString) -> Self {
+        let arg = arg.to_string();
+        debug_assert!(!arg.contains([SEPARATOR, SIGNATURE_SEPARATOR]));
+
+        self.args.push(arg);
+        self
+    }
+
+    pub fn signed(mut self) -> Self {
+        self.signed = true;
+        self
+    }
+
+    // the argument at `index`, None if it is missing or doesn't parse
+    pub fn get<T: FromStr>(&self, index: usize) -> Option<T> {
+        self.args.get(index)?.parse::<T>().ok()
+    }
+
+    // None for ids that are not in this format or whose signature doesn't match
+    pub fn parse(custom_id: &str) -> Option<CustomId> {
+        let (payload, signed) = match custom_id.rsplit_once(SIGNATURE_SEPARATOR) {
+            Some((payload, signature)) => {
+                let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+                hmac::verify(key(), payload.as_bytes(), &signature).ok()?;
+                (payload, true)
+            }
+            None => (custom_id, false),
+        };
+
+        let mut parts = payload.split(SEPARATOR);
+        let command = parts.next().filter(|command| !command.is_empty())?;
+        let action = parts.next()?;
+
+        Some(CustomId {
+            command: command.to_string(),
+            action: action.to_string(),
+            args: parts.map(str::to_string).collect(),
+            signed,
+        })
+    }
+}
+
+impl fmt::Display for CustomId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut payload = format!("{}{}{}", self.command, SEPARATOR, self.action);
+        for arg in &self.args {
+            payload.push(SEPARATOR);
+            payload.push_str(arg);
+        }
+
+        if self.signed {
+            let signature = hmac::sign(key(), payload.as_bytes());
+            payload.push(SIGNATURE_SEPARATOR);
+            payload.push_str(&URL_SAFE_NO_PAD.encode(signature.as_ref()));
+        }
+
+        debug_assert!(payload.len() <= MAX_CUSTOM_ID_LENGTH);
+        f.write_str(&payload)
+    }
+}
+
+// signing key from CUSTOM_ID_SECRET, without it ids signed before a restart stop verifying
+fn key() -> &'static hmac::Key {
+    static KEY: OnceLock<hmac::Key> = OnceLock::new();
+
+    KEY.get_or_init(|| match env::var("CUSTOM_ID_SECRET") {
+        Ok(secret) if !secret.is_empty() => hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+        _ => hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+            .expect("Could not generate a custom id key"),
+    })
+}
+
+// the value of the text input `custom_id` in a submitted modal
+pub fn input_value<'a>(modal: &'a ModalSubmitInteraction, custom_id: &str) -> Option<&'a str> {
+    modal
+        .data
+        .components
+        .iter()
+        .flat_map(|row| row.components.iter())
+        .find_map(|component| match component {
+            ActionRowComponent::InputText(input) if input.custom_id == custom_id => {
+                Some(input.value.as_str())
+            }
+            _ => None,
+        })
+}
+
+// hands a button or select menu to the command that owns it
+pub async fn route_component(
+    ctx: &Context,
+    interaction: &MessageComponentInteraction,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let id = match CustomId::parse(&interaction.data.custom_id) {
+        Some(id) => id,
+        None => {
+            log::warn!(
+                "Received unknown component: {:?}",
+                interaction.data.custom_id
+            );
+            return Ok(());
+        }
+    };
+
+    match find(&id.command) {
+        Some(command) => command.component(ctx, interaction, id).await,
+        None => {
+            log::warn!("Received component of unknown command: {:?}", id.command);
+            Ok(())
+        }
+    }
+}
+
+pub async fn route_modal(
+    ctx: &Context,
+    modal: &ModalSubmitInteraction,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let id = match CustomId::parse(&modal.data.custom_id) {
+        Some(id) => id,
+        None => {
+            log::warn!("Received unknown modal: {:?}", modal.data.custom_id);
+            return Ok(());
+        }
+    };
+
+    match find(&id.command) {
+        Some(command) => command.modal(ctx, modal, id).await,
+        None => {
+            log::warn!("Received modal of unknown command: {:?}", id.command);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CustomId, MAX_CUSTOM_ID_LENGTH};
+
+    const GUILD: u64 = 1_096_503_587_296_956_436;
+    const USER: u64 = 265_897_345_023_492_096;
+
+    fn reset_user() -> CustomId {
+        CustomId::new("reset", "user").arg(GUILD).arg(USER)
+    }
+
+    #[test]
+    fn unsigned_ids_round_trip() {
+        let id = reset_user();
+        let parsed = CustomId::parse(&id.to_string()).unwrap();
+
+        assert_eq!(parsed, id);
+        assert!(!parsed.signed);
+        assert_eq!(parsed.get::<u64>(0), Some(GUILD));
+        assert_eq!(parsed.get::<u64>(1), Some(USER));
+        assert_eq!(parsed.get::<u64>(2), None);
+    }
+
+    #[test]
+    fn signed_ids_round_trip() {
+        let id = reset_user().signed();
+        let custom_id = id.to_string();
+
+        assert!(custom_id.len() <= MAX_CUSTOM_ID_LENGTH);
+        assert_eq!(CustomId::parse(&custom_id), Some(id));
+    }
+
+    #[test]
+    fn tampered_arguments_are_rejected() {
+        let custom_id = reset_user().signed().to_string();
+        let forged = custom_id.replace(&USER.to_string(), &(USER + 1).to_string());
+
+        assert_ne!(forged, custom_id);
+        assert_eq!(CustomId::parse(&forged), None);
+    }
+
+    #[test]
+    fn tampered_signatures_are_rejected() {
+        let custom_id = reset_user().signed().to_string();
+        let (payload, _) = custom_id.rsplit_once('~').unwrap();
+
+        assert_eq!(CustomId::parse(&format!("{}~", payload)), None);
+        assert_eq!(
+            CustomId::parse(&format!("{}~not-a-signature", payload)),
+            None
+        );
+
+        let other = CustomId::new("reset", "xp").arg(GUILD).signed().to_string();
+        let (_, signature) = other.rsplit_once('~').unwrap();
+        assert_eq!(CustomId::parse(&format!("{}~{}", payload, signature)), None);
+    }
+
+    #[test]
+    fn stripped_signatures_parse_as_unsigned() {
+        let custom_id = reset_user().signed().to_string();
+        let (payload, _) = custom_id.rsplit_once('~').unwrap();
+
+        assert!(!CustomId::parse(payload).unwrap().signed);
+    }
+
+    #[test]
+    fn ids_in_other_formats_are_rejected() {
+        assert_eq!(CustomId::parse(""), None);
+        assert_eq!(CustomId::parse("reset"), None);
+        assert_eq!(CustomId::parse(":user"), None);
+    }
+}
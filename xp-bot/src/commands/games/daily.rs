@@ -4,14 +4,19 @@ use serenity::{
     model::prelude::{application_command::ApplicationCommandInteraction, InteractionResponseType},
     prelude::Context,
 };
-use xp_db_connector::{guild::Guild, guild_member::GuildMember};
+use xp_db_connector::{
+    guild::Guild,
+    guild_member::{
+        GuildMember, GuildMemberPatch, GuildMemberStreaks, GuildMemberTimestamps, XpReason,
+    },
+};
 
 use crate::{
-    commands::XpCommand,
-    utils::{
-        colors,
-        utils::{eligibility_helper, format_number, is_cooldowned},
+    commands::{
+        pipeline::{Cooldown, Module, Requirements},
+        XpCommand,
     },
+    utils::{batcher, colors, db, utils::format_number},
 };
 
 pub struct DailyCommand;
@@ -31,93 +36,23 @@ impl XpCommand for DailyCommand {
             .description("Claim a certain amount of xp every day!")
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::game(Module::Games, Some(Cooldown::Daily))
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let guild = Guild::from_id(command.guild_id.unwrap().0).await?;
-
-        if !guild.modules.games {
-            command
-                .create_interaction_response(ctx, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message.embed(|embed| {
-                                embed.title("Games module disabled");
-                                embed.description(
-                                    format!("This module is disabled on this server. \
-                                    An administrator can enable it [here](https://xp-bot.net/me/servers/{}/modules).", command.guild_id.unwrap().0).as_str(),
-                                );
-                                embed.color(colors::red())
-                            }).ephemeral(true);
-                            message
-                        })
-                })
-                .await?;
-
-            return Ok(());
-        }
-
-        if !eligibility_helper(command.user.id.0, &command.guild_id.unwrap().0).await {
-            command
-                .create_interaction_response(ctx, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message.embed(|embed| {
-                                embed.title("Vote required");
-                                embed.description(
-                                    "You need to vote for the bot to use this command. \
-                                    You can vote [here](https://top.gg/bot/706935674800177193/vote).",
-                                );
-                                embed.field("Don't want to vote?", "You can also get premium [here](https://xp-bot.net/premium).", false);
-                                embed.color(colors::red())
-                            }).ephemeral(true);
-                            message
-                        })
-                })
-                .await?;
+        let db = db::client(ctx).await;
 
-            return Ok(());
-        }
+        let guild = Guild::from_id(&db, command.guild_id.unwrap().0).await?;
 
-        let mut guild_member =
-            GuildMember::from_id(command.guild_id.unwrap().0, command.user.id.0).await?;
+        let guild_member =
+            GuildMember::from_id(&db, command.guild_id.unwrap().0, command.user.id.0).await?;
 
         let time_now = chrono::Utc::now().timestamp() * 1000;
-        let timestamp = guild_member.timestamps.game_daily.unwrap_or(0);
-        let cooldown: i64 = 86400 * 1000;
-
-        if is_cooldowned(time_now as u64, timestamp as u64, cooldown as u64) {
-            let difference = time_now - timestamp as i64;
-            let time_left = cooldown as i64 - difference;
-
-            command
-                .create_interaction_response(ctx, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message
-                                .embed(|embed| {
-                                    embed.description(format!(
-                                        "You already claimed your daily xp. \
-                                        You can claim it again **{}**.",
-                                        chrono_humanize::HumanTime::from(
-                                            chrono::Duration::milliseconds(time_left)
-                                        )
-                                    ));
-                                    embed.color(colors::red())
-                                })
-                                .ephemeral(true);
-                            message
-                        })
-                })
-                .await?;
-
-            return Ok(());
-        }
 
         let daily_xp = 250;
 
@@ -141,17 +76,38 @@ impl XpCommand for DailyCommand {
         } else {
             member_xp
         };
-        guild_member.xp += xp_to_add;
-
-        guild_member.timestamps.game_daily = Some(time_now as u64);
-        guild_member.streaks.game_daily = Some(streak);
 
-        let _ = GuildMember::set_guild_member(
+        GuildMember::patch(
+            &db,
+            command.guild_id.unwrap().0,
+            command.user.id.0,
+            GuildMemberPatch {
+                timestamps: Some(GuildMemberTimestamps {
+                    game_daily: Some(time_now as u64),
+                    ..Default::default()
+                }),
+                streaks: Some(GuildMemberStreaks {
+                    game_daily: Some(streak),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        )
+        .await?;
+        GuildMember::add_xp(
+            &db,
             command.guild_id.unwrap().0,
             command.user.id.0,
-            guild_member,
+            xp_to_add as i64,
+            XpReason::Daily,
+            None,
+            None,
         )
         .await?;
+        batcher::get(ctx)
+            .await
+            .forget(command.guild_id.unwrap().0, command.user.id.0)
+            .await;
 
         command
             .create_interaction_response(ctx, |response| {
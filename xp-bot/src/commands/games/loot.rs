@@ -4,13 +4,19 @@ use serenity::{
     model::prelude::{application_command::ApplicationCommandInteraction, InteractionResponseType},
     prelude::Context,
 };
-use xp_db_connector::{guild::Guild, guild_member::GuildMember};
+use xp_db_connector::{
+    guild::Guild,
+    guild_member::{GuildMember, GuildMemberPatch, GuildMemberTimestamps, XpReason},
+};
 
 use crate::{
-    commands::XpCommand,
+    commands::{
+        pipeline::{Cooldown, Module, Requirements},
+        XpCommand,
+    },
     utils::{
-        colors,
-        utils::{eligibility_helper, format_number, game_loot, is_cooldowned},
+        batcher, colors, db,
+        utils::{format_number, game_loot},
     },
 };
 
@@ -29,107 +35,51 @@ impl XpCommand for LootCommand {
         command.name("loot").description("Loot random crates!")
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::game(Module::Games, Some(Cooldown::Loot))
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let guild = Guild::from_id(command.guild_id.unwrap().0).await?;
-
-        if !guild.modules.games {
-            command
-                .create_interaction_response(ctx, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message.embed(|embed| {
-                                embed.title("Games module disabled");
-                                embed.description(
-                                    format!("This module is disabled on this server. \
-                                    An administrator can enable it [here](https://xp-bot.net/me/servers/{}/modules).", command.guild_id.unwrap().0).as_str(),
-                                );
-                                embed.color(colors::red())
-                            }).ephemeral(true);
-                            message
-                        })
-                })
-                .await?;
+        let db = db::client(ctx).await;
 
-            return Ok(());
-        }
+        let guild = Guild::from_id(&db, command.guild_id.unwrap().0).await?;
 
-        if !eligibility_helper(command.user.id.0, &command.guild_id.unwrap().0).await {
-            command
-                .create_interaction_response(ctx, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message.embed(|embed| {
-                                embed.title("Vote required");
-                                embed.description(
-                                    "You need to vote for the bot to use this command. \
-                                    You can vote [here](https://top.gg/bot/706935674800177193/vote).",
-                                );
-                                embed.field("Don't want to vote?", "You can also get premium [here](https://xp-bot.net/premium).", false);
-                                embed.color(colors::red())
-                            }).ephemeral(true);
-                            message
-                        })
-                })
-                .await?;
-
-            return Ok(());
-        }
-
-        let mut guild_member =
-            GuildMember::from_id(command.guild_id.unwrap().0, command.user.id.0).await?;
-
-        // check cooldowns
         let time_now = chrono::Utc::now().timestamp() * 1000;
 
-        let timestamp = guild_member.timestamps.game_loot.unwrap_or(0);
-
-        let cooldown = guild.values.gamecooldown * 1000;
-
-        if is_cooldowned(time_now as u64, timestamp as u64, cooldown as u64) {
-            let difference = time_now - timestamp as i64;
-            let time_left = cooldown as i64 - difference;
-
-            command
-                .create_interaction_response(ctx, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message
-                                .embed(|embed| {
-                                    embed.description(format!(
-                                        "You need to wait **{} seconds** before you can loot again.",
-                                        time_left / 1000
-                                    ));
-                                    embed.color(colors::red())
-                                })
-                                .ephemeral(true);
-                            message
-                        })
-                })
-                .await?;
-
-            return Ok(());
-        }
-
         let game_result = game_loot(guild.values.lootXP as i64);
 
-        // assign xp
-        guild_member.xp += game_result.xp as u64;
-
         // set new cooldown
-        guild_member.timestamps.game_loot = Some(time_now as u64);
-        let _ = GuildMember::set_guild_member(
+        GuildMember::patch(
+            &db,
+            command.guild_id.unwrap().0,
+            command.user.id.0,
+            GuildMemberPatch {
+                timestamps: Some(GuildMemberTimestamps {
+                    game_loot: Some(time_now as u64),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        )
+        .await?;
+        GuildMember::add_xp(
+            &db,
             command.guild_id.unwrap().0,
             command.user.id.0,
-            guild_member,
+            game_result.xp,
+            XpReason::Loot,
+            None,
+            None,
         )
         .await?;
+        batcher::get(ctx)
+            .await
+            .forget(command.guild_id.unwrap().0, command.user.id.0)
+            .await;
 
         command
             .create_interaction_response(ctx, |response| {
@@ -13,13 +13,20 @@ use serenity::{
     },
     prelude::Context,
 };
-use xp_db_connector::{guild::Guild, guild_member::GuildMember};
+use xp_db_connector::{
+    guild::Guild,
+    guild_member::{GuildMember, XpReason},
+};
 
 use crate::{
-    commands::XpCommand,
+    commands::{
+        components::CustomId,
+        pipeline::{Module, Requirements},
+        XpCommand,
+    },
     utils::{
-        colors,
-        utils::{calc_games_bulk, eligibility_helper, GameResult},
+        batcher, colors, db,
+        utils::{calc_games_bulk, GameResult},
     },
 };
 
@@ -40,57 +47,16 @@ impl XpCommand for PartyCommand {
             .description("Play games with a group of people.")
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::game(Module::Games, None)
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let guild = Guild::from_id(command.guild_id.unwrap().0).await?;
-
-        if !guild.modules.games {
-            command
-                .create_interaction_response(ctx, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message.embed(|embed| {
-                                embed.title("Games module disabled");
-                                embed.description(
-                                    format!("This module is disabled on this server. \
-                                    An administrator can enable it [here](https://xp-bot.net/me/servers/{}/modules).", command.guild_id.unwrap().0).as_str(),
-                                );
-                                embed.color(colors::red())
-                            }).ephemeral(true);
-                            message
-                        })
-                })
-                .await?;
-
-            return Ok(());
-        }
-
-        if !eligibility_helper(command.user.id.0, &command.guild_id.unwrap().0).await {
-            command
-                .create_interaction_response(ctx, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message.embed(|embed| {
-                                embed.title("Vote required");
-                                embed.description(
-                                    "You need to vote for the bot to use this command. \
-                                    You can vote [here](https://top.gg/bot/706935674800177193/vote).",
-                                );
-                                embed.field("Don't want to vote?", "You can also get premium [here](https://xp-bot.net/premium).", false);
-                                embed.color(colors::red())
-                            }).ephemeral(true);
-                            message
-                        })
-                })
-                .await?;
-
-            return Ok(());
-        }
+        let db = db::client(ctx).await;
 
         command
             .create_interaction_response(ctx, |response| {
@@ -112,7 +78,7 @@ impl XpCommand for PartyCommand {
                                     button
                                         .label("Join")
                                         .style(model::application::component::ButtonStyle::Primary)
-                                        .custom_id("join_party")
+                                        .custom_id(CustomId::new("party", "join"))
                                 });
                                 action_row
                             })
@@ -170,7 +136,7 @@ impl XpCommand for PartyCommand {
             return Ok(());
         }
 
-        let guild = Guild::from_id(command.guild_id.unwrap().0).await?;
+        let guild = Guild::from_id(&db, command.guild_id.unwrap().0).await?;
 
         // calculate game xp for every participant
         let mut games: Vec<GameResult> = Vec::new();
@@ -180,18 +146,26 @@ impl XpCommand for PartyCommand {
             // cache users for result message
             users.push(ctx.http.get_user(user_id.0).await?);
 
-            let mut member = GuildMember::from_id(command.guild_id.unwrap().0, user_id.0).await?;
-
             let result = calc_games_bulk(
                 guild.values.rollXP,
                 guild.values.fishXP,
                 guild.values.lootXP,
             );
 
-            member.xp += result.roll as u64 + result.fish as u64 + result.loot as u64;
-
-            let _ = GuildMember::set_guild_member(command.guild_id.unwrap().0, user_id.0, member)
-                .await?;
+            GuildMember::add_xp(
+                &db,
+                command.guild_id.unwrap().0,
+                user_id.0,
+                result.roll + result.fish + result.loot,
+                XpReason::Party,
+                None,
+                None,
+            )
+            .await?;
+            batcher::get(ctx)
+                .await
+                .forget(command.guild_id.unwrap().0, user_id.0)
+                .await;
 
             games.push(result);
         }
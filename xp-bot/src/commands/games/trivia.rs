@@ -6,18 +6,31 @@ use serenity::{
     builder::CreateApplicationCommand,
     model::{
         self,
-        prelude::{application_command::ApplicationCommandInteraction, InteractionResponseType},
+        prelude::{
+            application_command::ApplicationCommandInteraction,
+            autocomplete::AutocompleteInteraction, command::CommandOptionType,
+            message_component::MessageComponentInteraction, InteractionResponseType,
+        },
     },
     prelude::Context,
 };
-use xp_db_connector::{guild::Guild, guild_member::GuildMember};
+use xp_db_connector::{
+    guild::Guild,
+    guild_member::{
+        GuildMember, GuildMemberPatch, GuildMemberStreaks, GuildMemberTimestamps, XpReason,
+    },
+};
 
 use crate::{
-    commands::XpCommand,
+    commands::{
+        autocomplete::{self, MAX_CHOICES},
+        components::CustomId,
+        pipeline::{Cooldown, Module, Requirements},
+        XpCommand,
+    },
     utils::{
-        colors,
-        opentdb::OpenTriviaDB,
-        utils::{eligibility_helper, is_cooldowned},
+        batcher, colors, db,
+        opentdb::{OTDBCategory, OpenTriviaDB},
     },
 };
 
@@ -36,6 +49,18 @@ impl XpCommand for TriviaCommand {
         command
             .name("trivia")
             .description("Test your knowledge and earn xp!")
+            .create_option(|option| {
+                option
+                    .name("category")
+                    .description("The category of the question.")
+                    .kind(CommandOptionType::String)
+                    .required(false)
+                    .set_autocomplete(true)
+            })
+    }
+
+    fn requirements(&self) -> Requirements {
+        Requirements::game(Module::Trivia, Some(Cooldown::Trivia))
     }
 
     async fn exec(
@@ -43,90 +68,34 @@ impl XpCommand for TriviaCommand {
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let guild = Guild::from_id(command.guild_id.unwrap().0).await?;
-
-        if !guild.modules.trivia {
-            command
-                .create_interaction_response(ctx, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message.embed(|embed| {
-                                embed.title("Trivia module disabled");
-                                embed.description(
-                                    format!("This module is disabled on this server. \
-                                    An administrator can enable it [here](https://xp-bot.net/me/servers/{}/modules).", command.guild_id.unwrap().0).as_str(),
-                                );
-                                embed.color(colors::red())
-                            }).ephemeral(true);
-                            message
-                        })
-                })
-                .await?;
+        let db = db::client(ctx).await;
 
-            return Ok(());
-        }
+        let guild = Guild::from_id(&db, command.guild_id.unwrap().0).await?;
 
-        if !eligibility_helper(command.user.id.0, &command.guild_id.unwrap().0).await {
-            command
-                .create_interaction_response(ctx, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message.embed(|embed| {
-                                embed.title("Vote required");
-                                embed.description(
-                                    "You need to vote for the bot to use this command. \
-                                    You can vote [here](https://top.gg/bot/706935674800177193/vote).",
-                                );
-                                embed.field("Don't want to vote?", "You can also get premium [here](https://xp-bot.net/premium).", false);
-                                embed.color(colors::red())
-                            }).ephemeral(true);
-                            message
-                        })
-                })
-                .await?;
-
-            return Ok(());
-        }
-
-        let mut guild_member =
-            GuildMember::from_id(command.guild_id.unwrap().0, command.user.id.0).await?;
+        let guild_member =
+            GuildMember::from_id(&db, command.guild_id.unwrap().0, command.user.id.0).await?;
 
         let time_now = chrono::Utc::now().timestamp() * 1000;
-        let timestamp = guild_member.timestamps.game_trivia.unwrap_or(0);
-        let cooldown = guild.values.triviacooldown * 1000;
-
-        if is_cooldowned(time_now as u64, timestamp as u64, cooldown as u64) {
-            let difference = time_now - timestamp as i64;
-            let time_left = cooldown as i64 - difference;
-
-            command
-                .create_interaction_response(ctx, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message
-                                .embed(|embed| {
-                                    embed.description(
-                                        format!(
-                                            "You need to wait **{}** seconds before using this command again.",
-                                            time_left / 1000
-                                        )
-                                        .as_str(),
-                                    );
-                                    embed.color(colors::red())
-                                })
-                                .ephemeral(true);
-                            message
-                        })
-                })
-                .await?;
 
-            return Ok(());
-        }
+        let category = match command
+            .data
+            .options
+            .first()
+            .and_then(|option| option.value.as_ref())
+            .and_then(|value| value.as_str())
+        {
+            Some(value) => match resolve(OpenTriviaDB::get_categories().await?, value) {
+                Some(category) => Some(category),
+                None => return no_questions(ctx, command).await,
+            },
+            None => None,
+        };
+        let question = OpenTriviaDB::get_question(category).await?;
 
-        let question = OpenTriviaDB::get_question().await?;
+        // categories can run out of questions
+        if question.results.is_empty() {
+            return no_questions(ctx, command).await;
+        }
 
         let xp_multiplier = match question.results.first().unwrap().difficulty.as_str() {
             "easy" => 1,
@@ -177,25 +146,25 @@ impl XpCommand for TriviaCommand {
                                     button
                                         .label("1")
                                         .style(model::application::component::ButtonStyle::Primary)
-                                        .custom_id("1")
+                                        .custom_id(CustomId::new("trivia", "answer").arg(0))
                                 });
                                 action_row.create_button(|button| {
                                     button
                                         .label("2")
                                         .style(model::application::component::ButtonStyle::Primary)
-                                        .custom_id("2")
+                                        .custom_id(CustomId::new("trivia", "answer").arg(1))
                                 });
                                 action_row.create_button(|button| {
                                     button
                                         .label("3")
                                         .style(model::application::component::ButtonStyle::Primary)
-                                        .custom_id("3")
+                                        .custom_id(CustomId::new("trivia", "answer").arg(2))
                                 });
                                 action_row.create_button(|button| {
                                     button
                                         .label("4")
                                         .style(model::application::component::ButtonStyle::Primary)
-                                        .custom_id("4")
+                                        .custom_id(CustomId::new("trivia", "answer").arg(3))
                                 });
                                 action_row
                             })
@@ -220,23 +189,47 @@ impl XpCommand for TriviaCommand {
                 let mut correct = false;
 
                 if interaction.user.id == command.user.id {
-                    if interaction.data.custom_id == correct_answer_index.to_string() {
+                    let answer = CustomId::parse(&interaction.data.custom_id)
+                        .and_then(|id| id.get::<usize>(0));
+                    if answer == Some(correct_answer_index) {
                         correct = true;
                     }
                 }
 
                 if correct {
-                    guild_member.xp += xp as u64;
-                    guild_member.timestamps.game_trivia = Some(time_now as u64);
-                    guild_member.streaks.game_trivia =
-                        Some(guild_member.streaks.game_trivia.unwrap_or(0) + 1);
-
-                    let _ = GuildMember::set_guild_member(
+                    GuildMember::patch(
+                        &db,
+                        command.guild_id.unwrap().0,
+                        command.user.id.0,
+                        GuildMemberPatch {
+                            timestamps: Some(GuildMemberTimestamps {
+                                game_trivia: Some(time_now as u64),
+                                ..Default::default()
+                            }),
+                            streaks: Some(GuildMemberStreaks {
+                                game_trivia: Some(
+                                    guild_member.streaks.game_trivia.unwrap_or(0) + 1,
+                                ),
+                                ..Default::default()
+                            }),
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+                    GuildMember::add_xp(
+                        &db,
                         command.guild_id.unwrap().0,
                         command.user.id.0,
-                        guild_member,
+                        xp,
+                        XpReason::Trivia,
+                        None,
+                        None,
                     )
                     .await?;
+                    batcher::get(ctx)
+                        .await
+                        .forget(command.guild_id.unwrap().0, command.user.id.0)
+                        .await;
 
                     command
                         .create_followup_message(&ctx.http, |response| {
@@ -280,4 +273,164 @@ impl XpCommand for TriviaCommand {
             }
         }
     }
+
+    async fn autocomplete(
+        &self,
+        ctx: &Context,
+        interaction: &AutocompleteInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let typed = autocomplete::focused(interaction)
+            .map(autocomplete::typed)
+            .unwrap_or_default();
+
+        let categories = OpenTriviaDB::get_categories().await?;
+
+        interaction
+            .create_autocomplete_response(&ctx.http, |response| {
+                for category in matching(categories, &typed).take(MAX_CHOICES) {
+                    response.add_string_choice(&category.name, category.id);
+                }
+                response
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    // the collector in `exec` only takes answers of whoever asked, everyone else is told here
+    async fn component(
+        &self,
+        ctx: &Context,
+        interaction: &MessageComponentInteraction,
+        _id: CustomId,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let asked_by = interaction
+            .message
+            .interaction
+            .as_ref()
+            .map(|asked| asked.user.id);
+        if asked_by == Some(interaction.user.id) {
+            return Ok(());
+        }
+
+        interaction
+            .create_interaction_response(ctx, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message
+                            .embed(|embed| {
+                                embed.description(
+                                    "This question is not yours, use `/trivia` to get your own.",
+                                );
+                                embed.color(colors::red())
+                            })
+                            .ephemeral(true);
+                        message
+                    })
+            })
+            .await?;
+
+        Ok(())
+    }
+}
+
+async fn no_questions(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    command
+        .create_interaction_response(ctx, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .embed(|embed| {
+                            embed.description(
+                                "There are no questions in this category, pick one of the suggestions.",
+                            );
+                            embed.color(colors::red())
+                        })
+                        .ephemeral(true);
+                    message
+                })
+        })
+        .await?;
+
+    Ok(())
+}
+
+// categories whose name contains `typed` or whose id starts with it, `typed` is lowercase
+fn matching<'a>(
+    categories: &'a [OTDBCategory],
+    typed: &'a str,
+) -> impl Iterator<Item = &'a OTDBCategory> {
+    categories.iter().filter(move |category| {
+        category.name.to_lowercase().contains(typed) || category.id.to_string().starts_with(typed)
+    })
+}
+
+// the id of a picked suggestion, or the name of a category typed out without picking one
+fn resolve(categories: &[OTDBCategory], value: &str) -> Option<u64> {
+    let value = value.trim();
+
+    categories
+        .iter()
+        .find(|category| {
+            category.id.to_string() == value || category.name.eq_ignore_ascii_case(value)
+        })
+        .map(|category| category.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{matching, resolve};
+    use crate::utils::opentdb::OTDBCategory;
+
+    fn categories() -> Vec<OTDBCategory> {
+        [
+            (9, "General Knowledge"),
+            (17, "Science & Nature"),
+            (18, "Science: Computers"),
+            (23, "History"),
+        ]
+        .into_iter()
+        .map(|(id, name)| OTDBCategory {
+            id,
+            name: name.to_string(),
+        })
+        .collect()
+    }
+
+    fn ids(categories: &[OTDBCategory], typed: &str) -> Vec<u64> {
+        matching(categories, typed)
+            .map(|category| category.id)
+            .collect()
+    }
+
+    #[test]
+    fn nothing_typed_suggests_everything() {
+        assert_eq!(ids(&categories(), ""), vec![9, 17, 18, 23]);
+    }
+
+    #[test]
+    fn names_match_anywhere() {
+        assert_eq!(ids(&categories(), "science"), vec![17, 18]);
+        assert_eq!(ids(&categories(), "computer"), vec![18]);
+        assert!(ids(&categories(), "sports").is_empty());
+    }
+
+    #[test]
+    fn ids_match_by_prefix() {
+        assert_eq!(ids(&categories(), "1"), vec![17, 18]);
+        assert_eq!(ids(&categories(), "23"), vec![23]);
+    }
+
+    #[test]
+    fn picked_ids_and_typed_names_resolve() {
+        assert_eq!(resolve(&categories(), "18"), Some(18));
+        assert_eq!(resolve(&categories(), " history "), Some(23));
+        assert_eq!(resolve(&categories(), "42"), None);
+        assert_eq!(resolve(&categories(), "science"), None);
+    }
 }
@@ -52,32 +52,36 @@ impl XpCommand for AboutCommand {
     
                     ).components(
                         |components| components
-                            .create_action_row(|action_row| action_row
-                                .create_button(|button| button
-                                    .label("Server Dashboard")
-                                    .style(ButtonStyle::Link)
-                                    .emoji(ReactionType::Unicode("🛠️".to_string()))
-                                    .url(format!("https://xp-bot.net/servers/{}", &command.guild_id.unwrap().to_string()))
-                                )
-                                .create_button(|button| button
-                                    .label("Account Settings")
-                                    .style(ButtonStyle::Link)
-                                    .emoji(ReactionType::Unicode("🙋".to_string()))
-                                    .url("https://xp-bot.net/me")
-                                )
-                                .create_button(|button| button
-                                    .label("Premium")
-                                    .style(ButtonStyle::Link)
-                                    .emoji(ReactionType::Unicode("👑".to_string()))
-                                    .url("https://xp-bot.net/premium")
-                                )
-                                .create_button(|button| button
-                                    .label("Privacy Policy")
-                                    .style(ButtonStyle::Link)
-                                    .emoji(ReactionType::Unicode("🔖".to_string()))
-                                    .url("https://xp-bot.net/legal/privacy")
-                                )
-                            )
+                            .create_action_row(|action_row| {
+                                // there is no server dashboard to link to in dms
+                                if let Some(guild_id) = command.guild_id {
+                                    action_row.create_button(|button| button
+                                        .label("Server Dashboard")
+                                        .style(ButtonStyle::Link)
+                                        .emoji(ReactionType::Unicode("🛠️".to_string()))
+                                        .url(format!("https://xp-bot.net/servers/{}", guild_id))
+                                    );
+                                }
+                                action_row
+                                    .create_button(|button| button
+                                        .label("Account Settings")
+                                        .style(ButtonStyle::Link)
+                                        .emoji(ReactionType::Unicode("🙋".to_string()))
+                                        .url("https://xp-bot.net/me")
+                                    )
+                                    .create_button(|button| button
+                                        .label("Premium")
+                                        .style(ButtonStyle::Link)
+                                        .emoji(ReactionType::Unicode("👑".to_string()))
+                                        .url("https://xp-bot.net/premium")
+                                    )
+                                    .create_button(|button| button
+                                        .label("Privacy Policy")
+                                        .style(ButtonStyle::Link)
+                                        .emoji(ReactionType::Unicode("🔖".to_string()))
+                                        .url("https://xp-bot.net/legal/privacy")
+                                    )
+                            })
                     )
                 })
         }).await?;
@@ -0,0 +1,424 @@
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::{
+        application_command::ApplicationCommandInteraction, command::CommandOptionType,
+        AttachmentId, InteractionResponseType,
+    },
+    prelude::Context,
+    utils::Color,
+};
+use xp_db_connector::{
+    user::User,
+    user_background::{BackgroundUpload, UserBackground, MAX_BACKGROUND_SIZE},
+    DbError, XpStore,
+};
+
+use crate::{
+    commands::XpCommand,
+    utils::{colors, db, utils::db_error_embed},
+};
+
+pub struct BackgroundCommand;
+
+#[async_trait]
+impl XpCommand for BackgroundCommand {
+    fn name(&self) -> &'static str {
+        "background"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .name("background")
+            .description("Manage the background of your rank card.")
+            .create_option(|option| {
+                option
+                    .name("view")
+                    .description("Show the background you are currently using.")
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("list")
+                    .description("List the backgrounds you have uploaded.")
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("upload")
+                    .description("Upload a new background and use it right away.")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("image")
+                            .description("A png, jpeg, webp or gif image.")
+                            .kind(CommandOptionType::Attachment)
+                            .required(true)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("select")
+                    .description("Use one of your backgrounds.")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("id")
+                            .description("The id of the background, see /background list.")
+                            .kind(CommandOptionType::Integer)
+                            .required(true)
+                            .min_int_value(0)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("delete")
+                    .description("Delete one of your backgrounds.")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("id")
+                            .description("The id of the background, see /background list.")
+                            .kind(CommandOptionType::Integer)
+                            .required(true)
+                            .min_int_value(0)
+                    })
+            })
+    }
+
+    async fn exec(
+        &self,
+        ctx: &Context,
+        command: &ApplicationCommandInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = db::client(ctx).await;
+
+        let user_id = command.user.id.0;
+        let subcommand = command.data.options.first().unwrap();
+
+        // the id option of select and delete
+        let background_id = subcommand
+            .options
+            .first()
+            .and_then(|option| option.value.as_ref())
+            .and_then(|value| value.as_i64())
+            .unwrap_or(0) as i32;
+
+        match subcommand.name.as_str() {
+            "view" => match UserBackground::current(&db, user_id).await {
+                Ok(Some(background)) => {
+                    let description = format!("You are using background `#{}`.", background.id);
+                    command
+                        .create_interaction_response(&ctx.http, |response| {
+                            response
+                                .kind(InteractionResponseType::ChannelMessageWithSource)
+                                .interaction_response_data(|message| {
+                                    message
+                                        .embed(|embed| {
+                                            embed.description(description);
+                                            if let Some(url) = &background.url {
+                                                embed.image(url);
+                                            }
+                                            embed.color(colors::blue());
+                                            embed
+                                        })
+                                        .ephemeral(true);
+                                    message
+                                })
+                        })
+                        .await?;
+                }
+                Ok(None) => {
+                    reply(
+                        ctx,
+                        command,
+                        "You are using the default background.",
+                        colors::blue(),
+                    )
+                    .await?
+                }
+                Err(why) => {
+                    reply_error(ctx, command, "Could not get your background.", &why).await?
+                }
+            },
+            "list" => {
+                let backgrounds = match UserBackground::list(&db, user_id).await {
+                    Ok(backgrounds) => backgrounds,
+                    Err(why) => {
+                        return reply_error(ctx, command, "Could not get your backgrounds.", &why)
+                            .await;
+                    }
+                };
+
+                let description = if backgrounds.is_empty() {
+                    "You have not uploaded any backgrounds yet, use `/background upload`."
+                        .to_string()
+                } else {
+                    backgrounds
+                        .iter()
+                        .map(|background| match &background.url {
+                            Some(url) => format!("`#{}` [preview]({})", background.id, url),
+                            None => format!("`#{}`", background.id),
+                        })
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                };
+
+                reply(ctx, command, &description, colors::blue()).await?;
+            }
+            "upload" => {
+                let attachment = subcommand
+                    .options
+                    .first()
+                    .and_then(|option| option.value.as_ref())
+                    .and_then(|value| value.as_str())
+                    .and_then(|id| id.parse::<u64>().ok())
+                    .and_then(|id| command.data.resolved.attachments.get(&AttachmentId(id)));
+
+                let attachment = match attachment {
+                    Some(attachment) => attachment,
+                    None => {
+                        return reply(ctx, command, "Could not find the image.", colors::red())
+                            .await;
+                    }
+                };
+
+                // skip the download if discord already tells us it is too large
+                if attachment.size as usize > MAX_BACKGROUND_SIZE {
+                    return reply(
+                        ctx,
+                        command,
+                        &format!(
+                            "Backgrounds can be at most **{}MB** large.",
+                            MAX_BACKGROUND_SIZE / 1024 / 1024
+                        ),
+                        colors::red(),
+                    )
+                    .await;
+                }
+
+                // downloading, checking and uploading the image can take longer than discord
+                // waits for a response
+                command
+                    .create_interaction_response(&ctx.http, |response| {
+                        response
+                            .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                            .interaction_response_data(|message| message.ephemeral(true))
+                    })
+                    .await?;
+
+                let image = match attachment.download().await {
+                    Ok(image) => image,
+                    Err(why) => {
+                        log::error!("Could not download background ({}): {}", user_id, why);
+                        return followup(
+                            ctx,
+                            command,
+                            "Could not download the image.",
+                            colors::red(),
+                        )
+                        .await;
+                    }
+                };
+
+                let upload = match BackgroundUpload::new(image) {
+                    Ok(upload) => upload,
+                    Err(why) => {
+                        return followup_error(ctx, command, "This image can not be used.", &why)
+                            .await;
+                    }
+                };
+
+                let background = match UserBackground::upload(&db, user_id, upload).await {
+                    Ok(background) => background,
+                    Err(why) => {
+                        return followup_error(
+                            ctx,
+                            command,
+                            "Could not upload your background.",
+                            &why,
+                        )
+                        .await;
+                    }
+                };
+
+                if let Err(why) = use_background(&db, user_id, Some(background.id)).await {
+                    return followup_error(ctx, command, "Could not select your background.", &why)
+                        .await;
+                }
+
+                followup(
+                    ctx,
+                    command,
+                    &format!(
+                        "Your background has been uploaded as `#{}` and is now in use.",
+                        background.id
+                    ),
+                    colors::green(),
+                )
+                .await?;
+            }
+            "select" => {
+                match UserBackground::from_id(&db, user_id, background_id).await {
+                    Ok(_) => {}
+                    Err(why) if why.is_not_found() => {
+                        return reply(
+                            ctx,
+                            command,
+                            &format!("You don't have a background `#{}`.", background_id),
+                            colors::red(),
+                        )
+                        .await;
+                    }
+                    Err(why) => {
+                        return reply_error(ctx, command, "Could not get the background.", &why)
+                            .await;
+                    }
+                }
+
+                if let Err(why) = use_background(&db, user_id, Some(background_id)).await {
+                    return reply_error(ctx, command, "Could not select your background.", &why)
+                        .await;
+                }
+
+                reply(
+                    ctx,
+                    command,
+                    &format!("You are now using background `#{}`.", background_id),
+                    colors::green(),
+                )
+                .await?;
+            }
+            "delete" => {
+                if let Err(why) = UserBackground::delete(&db, user_id, background_id).await {
+                    return reply_error(ctx, command, "Could not delete the background.", &why)
+                        .await;
+                }
+
+                // fall back to the default background if the deleted one was in use
+                let user = User::from_id(&db, user_id).await?;
+                if user.settings.background.bg == Some(background_id) {
+                    use_background(&db, user_id, None).await?;
+                }
+
+                reply(
+                    ctx,
+                    command,
+                    &format!("Background `#{}` has been deleted.", background_id),
+                    colors::green(),
+                )
+                .await?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+async fn use_background(
+    db: &dyn XpStore,
+    user_id: u64,
+    background_id: Option<i32>,
+) -> Result<(), DbError> {
+    let mut user = User::from_id(db, user_id).await?;
+
+    user.settings.background.bg = background_id;
+    user.settings.background.custom = background_id.is_some();
+
+    User::set(db, user_id, user).await
+}
+
+async fn reply(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    description: &str,
+    color: Color,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .embed(|embed| {
+                            embed.description(description);
+                            embed.color(color);
+                            embed
+                        })
+                        .ephemeral(true);
+                    message
+                })
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn reply_error(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    action: &str,
+    why: &DbError,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    log::error!("{} ({}): {}", action, command.user.id.0, why);
+
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .embed(|embed| db_error_embed(embed, action, why))
+                        .ephemeral(true);
+                    message
+                })
+        })
+        .await?;
+
+    Ok(())
+}
+
+// for responses that were deferred
+async fn followup(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    description: &str,
+    color: Color,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .embed(|embed| {
+                    embed.description(description);
+                    embed.color(color);
+                    embed
+                })
+                .ephemeral(true)
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn followup_error(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    action: &str,
+    why: &DbError,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    log::error!("{} ({}): {}", action, command.user.id.0, why);
+
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .embed(|embed| db_error_embed(embed, action, why))
+                .ephemeral(true)
+        })
+        .await?;
+
+    Ok(())
+}
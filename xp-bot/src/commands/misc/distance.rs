@@ -8,7 +8,10 @@ use serenity::{
 };
 use xp_db_connector::guild_member::GuildMember;
 
-use crate::commands::XpCommand;
+use crate::{
+    commands::{pipeline::Requirements, XpCommand},
+    utils::db,
+};
 
 pub struct DistanceCommand;
 
@@ -34,13 +37,19 @@ impl XpCommand for DistanceCommand {
             })
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::guild()
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = db::client(ctx).await;
+
         let guild_member =
-            GuildMember::from_id(command.guild_id.unwrap().0, command.user.id.0).await;
+            GuildMember::from_id(&db, command.guild_id.unwrap().0, command.user.id.0).await;
 
         if guild_member.is_err() {
             log::error!("Could not get guild member: {:?}", command.user.id.0);
@@ -58,7 +67,8 @@ impl XpCommand for DistanceCommand {
             .parse::<u64>()
             .unwrap();
 
-        let other_guild_member = GuildMember::from_id(command.guild_id.unwrap().0, user_id).await;
+        let other_guild_member =
+            GuildMember::from_id(&db, command.guild_id.unwrap().0, user_id).await;
 
         if other_guild_member.is_err() {
             log::error!("Could not get guild member: {:?}", user_id);
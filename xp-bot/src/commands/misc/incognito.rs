@@ -6,7 +6,10 @@ use serenity::{
 };
 use xp_db_connector::guild_member::GuildMember;
 
-use crate::{commands::XpCommand, utils::colors};
+use crate::{
+    commands::{pipeline::Requirements, XpCommand},
+    utils::{colors, db},
+};
 
 pub struct IncognitoCommand;
 
@@ -34,11 +37,17 @@ impl XpCommand for IncognitoCommand {
             })
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::guild()
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = db::client(ctx).await;
+
         let enabled = command
             .data
             .options
@@ -52,11 +61,11 @@ impl XpCommand for IncognitoCommand {
 
         let guild_id = command.guild_id.unwrap().0;
 
-        let mut guild_member = GuildMember::from_id(guild_id, command.user.id.0).await?;
+        let mut guild_member = GuildMember::from_id(&db, guild_id, command.user.id.0).await?;
 
         guild_member.settings.incognito = Some(enabled);
 
-        let _ = GuildMember::set_guild_member(guild_id, command.user.id.0, guild_member).await?;
+        GuildMember::set_guild_member(&db, guild_id, command.user.id.0, guild_member).await?;
 
         command
             .create_interaction_response(&ctx.http, |response| {
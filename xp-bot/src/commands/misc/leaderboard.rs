@@ -8,7 +8,7 @@ use serenity::{
     prelude::Context,
 };
 
-use crate::commands::XpCommand;
+use crate::commands::{pipeline::Requirements, XpCommand};
 
 pub struct LeaderboardCommand;
 
@@ -27,6 +27,10 @@ impl XpCommand for LeaderboardCommand {
             .description("Check who's most active on this server.")
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::guild()
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
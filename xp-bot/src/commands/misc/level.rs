@@ -10,8 +10,8 @@ use serenity::{
 };
 
 use crate::{
-    commands::XpCommand,
-    utils::{colors, math::get_required_xp},
+    commands::{pipeline::Requirements, XpCommand},
+    utils::{colors, db, math::get_required_xp},
 };
 use xp_db_connector::guild_member::GuildMember;
 
@@ -39,13 +39,19 @@ impl XpCommand for LevelCommand {
             })
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::guild()
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = db::client(ctx).await;
+
         let guild_member =
-            GuildMember::from_id(command.guild_id.unwrap().0, command.user.id.0).await;
+            GuildMember::from_id(&db, command.guild_id.unwrap().0, command.user.id.0).await;
 
         if guild_member.is_err() {
             error!("Could not get guild member: {:?}", command.user.id.0);
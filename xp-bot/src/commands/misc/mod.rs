@@ -1,4 +1,5 @@
 pub mod about;
+pub mod background;
 pub mod incognito;
 pub mod leaderboard;
 pub mod level;
@@ -6,3 +7,4 @@ pub mod rank;
 pub mod settings;
 pub mod voicetime;
 pub mod distance;
+pub mod xp;
@@ -6,7 +6,10 @@ use serenity::{
 };
 use xp_db_connector::guild_member::GuildMember;
 
-use crate::{commands::XpCommand, utils::colors};
+use crate::{
+    commands::{pipeline::Requirements, XpCommand},
+    utils::{colors, db},
+};
 
 pub struct RankCommand;
 
@@ -32,11 +35,17 @@ impl XpCommand for RankCommand {
             })
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::guild()
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = db::client(ctx).await;
+
         let mut user_id = command.user.id.0;
 
         match command.data.options.first() {
@@ -48,7 +57,7 @@ impl XpCommand for RankCommand {
             None => {}
         }
 
-        let user = GuildMember::from_id(command.guild_id.unwrap().0, user_id.clone())
+        let user = GuildMember::from_id(&db, command.guild_id.unwrap().0, user_id.clone())
             .await
             .unwrap();
 
@@ -4,9 +4,12 @@ use serenity::{
     model::prelude::{application_command::ApplicationCommandInteraction, InteractionResponseType},
     prelude::Context,
 };
-use xp_db_connector::{guild::Guild, guild_premium::GuildPremium};
+use xp_db_connector::guild::Guild;
 
-use crate::{commands::XpCommand, utils::colors};
+use crate::{
+    commands::{pipeline::Requirements, XpCommand},
+    utils::{colors, db, entitlements},
+};
 
 pub struct SettingsCommand;
 
@@ -37,22 +40,28 @@ impl XpCommand for SettingsCommand {
             })
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::guild()
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = db::client(ctx).await;
+
         let guild_id = command.guild_id.unwrap();
         let option = command.data.options[0].clone();
 
-        let mut guild = match Guild::from_id(guild_id.into()).await {
+        let mut guild = match Guild::from_id(&db, guild_id.into()).await {
             Ok(guild) => guild,
             Err(why) => {
                 log::error!("Could not get guild: {:?}", why);
                 return Ok(());
             }
         };
-        let guild_premium = GuildPremium::from_id(guild_id.into()).await?;
+        let guild_premium = entitlements::get(ctx).await.guild(guild_id.0).await;
 
         let mut fields: Vec<(String, String, bool)> = Vec::new();
 
@@ -80,6 +89,23 @@ impl XpCommand for SettingsCommand {
                     "Disable xp in AFK Channels.".to_string(),
                     true,
                 ));
+                fields.push((
+                    format!(
+                        "{} Ignore Deafened",
+                        tick_helper(guild.modules.voiceignoredeafened)
+                    ),
+                    "Disable voice xp while a user is deafened.".to_string(),
+                    true,
+                ));
+                fields.push((
+                    format!(
+                        "{} Ignore Server Muted",
+                        tick_helper(guild.modules.voiceignoremuted)
+                    ),
+                    "Disable voice xp while a user is muted or deafened by a moderator."
+                        .to_string(),
+                    true,
+                ));
                 fields.push((
                     format!("{} Autonick", tick_helper(guild.modules.autonick)),
                     "Automatically show the level of each user in their nicknames.".to_string(),
@@ -198,6 +224,24 @@ impl XpCommand for SettingsCommand {
                         .to_string(),
                     true,
                 ));
+                fields.push((
+                    format!(
+                        "Voice Min Members: {}",
+                        crate::utils::utils::format_number(guild.values.voiceminmembers as i64)
+                    ),
+                    "The amount of unmuted users, bots not included, a voicechat needs to earn xp."
+                        .to_string(),
+                    true,
+                ));
+                fields.push((
+                    format!(
+                        "Voice Mute AFK: {}",
+                        crate::utils::utils::format_number(guild.values.voicemuteafk as i64)
+                    ),
+                    "The time in minutes a user can stay muted before counting as AFK. 0 disables it."
+                        .to_string(),
+                    true,
+                ));
                 fields.push((
                     format!(
                         "Reaction xp: {}",
@@ -328,21 +372,13 @@ impl XpCommand for SettingsCommand {
                 });
 
                 let mut boostcategories_string = String::new();
-                if guild.boosts.categories.is_some() {
-                    guild
-                        .boosts
-                        .categories
-                        .as_ref()
-                        .unwrap()
-                        .iter()
-                        .for_each(|boostcategory| {
-                            boostcategories_string.push_str(&format!(
-                                "<#{}> with {}% boost\n",
-                                boostcategory.id, boostcategory.percentage
-                            ));
-                            boostcategories = boostcategories_string.to_owned();
-                        });
-                }
+                guild.boosts.categories.iter().for_each(|boostcategory| {
+                    boostcategories_string.push_str(&format!(
+                        "<#{}> with {}% boost\n",
+                        boostcategory.id, boostcategory.percentage
+                    ));
+                    boostcategories = boostcategories_string.to_owned();
+                });
 
                 fields.push(("Boosted roles".to_string(), boostroles, false));
                 fields.push(("Boosted channels".to_string(), boostchannels, false));
@@ -363,36 +399,66 @@ impl XpCommand for SettingsCommand {
                 });
 
                 let mut ignoredchannels_string = String::new();
-                if guild.ignored.channels.is_some() {
-                    guild
-                        .ignored
-                        .channels
-                        .as_ref()
-                        .unwrap()
-                        .iter()
-                        .for_each(|ignoredchannel| {
-                            ignoredchannels_string.push_str(&format!("<#{}>\n", ignoredchannel));
-                            ignoredchannels = ignoredchannels_string.to_owned();
-                        });
-                }
+                guild.ignored.channels.iter().for_each(|ignoredchannel| {
+                    ignoredchannels_string.push_str(&format!("<#{}>\n", ignoredchannel));
+                    ignoredchannels = ignoredchannels_string.to_owned();
+                });
 
                 let mut ignoredcategories_string = String::new();
-                if guild.ignored.categories.is_some() {
-                    guild
-                        .ignored
-                        .categories
-                        .as_ref()
-                        .unwrap()
-                        .iter()
-                        .for_each(|ignoredcategory| {
-                            ignoredcategories_string.push_str(&format!("<#{}>\n", ignoredcategory));
-                            ignoredcategories = ignoredcategories_string.to_owned();
-                        });
-                }
+                guild.ignored.categories.iter().for_each(|ignoredcategory| {
+                    ignoredcategories_string.push_str(&format!("<#{}>\n", ignoredcategory));
+                    ignoredcategories = ignoredcategories_string.to_owned();
+                });
 
                 fields.push(("Ignored roles".to_string(), ignoredroles, false));
                 fields.push(("Ignored channels".to_string(), ignoredchannels, false));
                 fields.push(("Ignored categories".to_string(), ignoredcategories, false));
+
+                // an empty allow list doesn't restrict anything, say so instead of listing nothing
+                let allowed = &guild.ignored.allowed;
+                let mode = if !guild.ignored.allowlist {
+                    "Off, members earn xp everywhere that isn't ignored."
+                } else if allowed.roles.is_empty()
+                    && allowed.channels.is_empty()
+                    && allowed.categories.is_empty()
+                {
+                    "On, but nothing is allowed yet, so members still earn xp everywhere that isn't ignored."
+                } else {
+                    "On, members only earn xp with an allowed role, in an allowed channel or category. \
+                    Ignores still apply."
+                };
+                fields.push((
+                    format!("{} Allow-list", tick_helper(guild.ignored.allowlist)),
+                    mode.to_string(),
+                    false,
+                ));
+
+                if guild.ignored.allowlist {
+                    let allowed_list = |ids: &[String], mention: &str, everything: &str| {
+                        if ids.is_empty() {
+                            return format!("None, {} earn xp.", everything);
+                        }
+                        ids.iter()
+                            .map(|id| format!("<{}{}>\n", mention, id))
+                            .collect::<String>()
+                    };
+
+                    fields.push((
+                        "Allowed roles".to_string(),
+                        allowed_list(&allowed.roles, "@&", "all roles"),
+                        false,
+                    ));
+                    fields.push((
+                        "Allowed channels".to_string(),
+                        allowed_list(&allowed.channels, "#", "all channels"),
+                        false,
+                    ));
+                    fields.push((
+                        "Allowed categories".to_string(),
+                        allowed_list(&allowed.categories, "#", "all categories"),
+                        false,
+                    ));
+                }
             }
             _ => {}
         }
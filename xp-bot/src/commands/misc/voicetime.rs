@@ -3,14 +3,17 @@ use serenity::{
     model::prelude::{application_command::ApplicationCommandInteraction, InteractionResponseType},
     prelude::Context,
 };
-use xp_db_connector::{guild::Guild, guild_member::GuildMember, user::User};
+use xp_db_connector::{guild::Guild, guild_member::GuildMember};
 
 use crate::{
-    commands::XpCommand,
+    commands::{pipeline::Requirements, XpCommand},
     utils::{
         colors,
-        math::{calculate_level, calculate_xp_from_voice_time},
+        db,
+        math::calculate_level,
+        rules::{self, XpSource},
         utils,
+        voice::{self, VoiceRules},
     },
 };
 
@@ -38,11 +41,17 @@ impl XpCommand for VoicetimeCommand {
             })
     }
 
+    fn requirements(&self) -> Requirements {
+        Requirements::guild()
+    }
+
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = db::client(ctx).await;
+
         log::info!("voicetime command used by {}: 1", command.user.id.0);
 
         let mut user_id = command.user.id.0;
@@ -58,53 +67,58 @@ impl XpCommand for VoicetimeCommand {
 
         log::info!("voicetime command used by {}: 2", user_id);
 
-        let user = User::from_id(user_id).await?;
+        let guild_id = command.guild_id.unwrap();
 
-        let guild_member = GuildMember::from_id(command.guild_id.unwrap().0, user_id)
+        let guild_member = GuildMember::from_id(&db, guild_id.0, user_id)
             .await
             .unwrap();
 
         log::info!("voicetime command used by {}: 3", user_id);
 
-        let last_timestamp = user.timestamps.join_voicechat;
-
-        let last_timestamp = match last_timestamp {
-            Some(timestamp) => timestamp,
+        // the session in this guild, being in voice on another server doesn't count here
+        let session = match voice::get(ctx).await.get(guild_id.0, user_id).await {
+            Some(session) => session,
             None => {
                 command
                     .create_interaction_response(&ctx.http, |response| {
                         response
                             .kind(InteractionResponseType::ChannelMessageWithSource)
                             .interaction_response_data(|message| {
-                                message.content("This user has not joined a voice channel yet.");
+                                message.content("This user is not currently in a voice channel.");
                                 message.ephemeral(true)
                             })
                     })
                     .await
                     .unwrap();
-
                 return Ok(());
             }
         };
 
         log::info!("voicetime command used by {}: 4", user_id);
 
-        // check if the user is currently in a voice channel
-        let state = match ctx
-            .cache
-            .guild(command.guild_id.unwrap())
-            .unwrap()
-            .voice_states
-            .get(&ctx.http.get_user(user_id).await?.id)
+        let guild = Guild::from_id(&db, guild_id.0).await?;
+
+        // check if the user is in a voicechannel that's ignored
+        let roles = voice::member_roles(ctx, guild_id.0, user_id);
+        let location = match rules::earns(
+            ctx,
+            &guild,
+            guild_id.0,
+            XpSource::Voice,
+            session.channel_id,
+            &roles,
+        )
+        .await
         {
-            Some(state) => Some(state.clone()),
+            Some(location) => location,
             None => {
                 command
                     .create_interaction_response(&ctx.http, |response| {
                         response
                             .kind(InteractionResponseType::ChannelMessageWithSource)
                             .interaction_response_data(|message| {
-                                message.content("This user is not currently in a voice channel.");
+                                message
+                                    .content("This user does not earn xp in this voice channel.");
                                 message.ephemeral(true)
                             })
                     })
@@ -116,70 +130,26 @@ impl XpCommand for VoicetimeCommand {
 
         log::info!("voicetime command used by {}: 5", user_id);
 
-        let guild = Guild::from_id(command.guild_id.unwrap().0).await?;
-
-        // check if the user is in a voicechannel that's ignored
-        if guild.clone().ignored.channels.unwrap().contains(&state.unwrap().channel_id.unwrap().0.to_string()) {
-            command
-                .create_interaction_response(&ctx.http, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message.content("This voice channel is ignored.");
-                            message.ephemeral(true)
-                        })
-                })
-                .await
-                .unwrap();
-            return Ok(());
-        }
-
-        log::info!("voicetime command used by {}: 6", user_id);
-
         let current_timestamp = chrono::Utc::now().timestamp() * 1000;
 
-        let boost_percentage = utils::calculate_total_boost_percentage(
+        // the boost of the member that is looked up, not of whoever asked
+        let boost_percentage = utils::calculate_total_boost_percentage_by_ids(
             guild.clone(),
-            &command.member.as_ref().unwrap().roles,
-            ctx.cache
-                .guild(command.guild_id.unwrap())
-                .unwrap()
-                .voice_states
-                .get(&ctx.http.get_user(user_id).await?.id)
-                .unwrap()
-                .channel_id
-                .unwrap()
-                .0,
-            ctx.cache
-                .guild_channel(
-                    ctx.cache
-                        .guild(command.guild_id.unwrap())
-                        .unwrap()
-                        .voice_states
-                        .get(&ctx.http.get_user(user_id).await?.id)
-                        .unwrap()
-                        .channel_id
-                        .unwrap()
-                        .0,
-                )
-                .unwrap()
-                .parent_id,
+            roles,
+            location.channel_id,
+            location.category_id,
         );
 
-        log::info!("voicetime command used by {}: 7", user_id);
-
-        let voice_xp = calculate_xp_from_voice_time(
-            last_timestamp,
-            current_timestamp,
-            guild.values.voicexp,
-            guild.values.voicejoincooldown,
-            boost_percentage,
-        );
+        log::info!("voicetime command used by {}: 6", user_id);
 
-        let current_level = calculate_level(&guild_member.xp);
-        let new_level = calculate_level(&(guild_member.xp + voice_xp as u64));
+        // full minutes have been paid out already, the rest is added when the session ends
+        let unpaid = session.unpaid(current_timestamp as u64, &VoiceRules::new(&guild));
+        let unpaid_xp = voice::voice_xp(&guild, unpaid, boost_percentage);
+        let voice_xp = session.xp + unpaid_xp;
+        let current_level = calculate_level(&guild_member.xp.saturating_sub(session.xp));
+        let new_level = calculate_level(&(guild_member.xp + unpaid_xp));
         let level_difference = new_level - current_level;
-        let voice_time = (current_timestamp - last_timestamp as i64) / 1000;
+        let voice_time = session.duration(current_timestamp as u64) as i64;
 
         let username = ctx.http.get_user(user_id).await?.name;
 
@@ -205,7 +175,7 @@ impl XpCommand for VoicetimeCommand {
             format!("**{}** seconds", seconds)
         };
 
-        log::info!("voicetime command used by {}: 8", user_id);
+        log::info!("voicetime command used by {}: 7", user_id);
 
         let _ = command
             .create_interaction_response(&ctx.http, |response| {
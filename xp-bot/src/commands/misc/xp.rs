@@ -0,0 +1,205 @@
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::{
+        application_command::ApplicationCommandInteraction, command::CommandOptionType,
+        InteractionResponseType,
+    },
+    prelude::Context,
+};
+use xp_db_connector::{
+    guild_member::{GuildMember, XpReason},
+    ledger::LedgerEntry,
+};
+
+use crate::{
+    commands::{pipeline::Requirements, XpCommand},
+    utils::{
+        batcher, colors, db,
+        utils::{db_error_embed, format_number},
+    },
+};
+
+// entries shown by /xp history
+const HISTORY_LENGTH: u32 = 15;
+
+pub struct XpHistoryCommand;
+
+#[async_trait]
+impl XpCommand for XpHistoryCommand {
+    fn name(&self) -> &'static str {
+        "xp"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .name("xp")
+            .description("Look into where xp came from.")
+            .create_option(|option| {
+                option
+                    .name("history")
+                    .description("Show the latest xp changes of a user.")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("user")
+                            .description("The user you want to check.")
+                            .kind(CommandOptionType::User)
+                            .required(false)
+                    })
+            })
+    }
+
+    fn requirements(&self) -> Requirements {
+        Requirements::guild()
+    }
+
+    async fn exec(
+        &self,
+        ctx: &Context,
+        command: &ApplicationCommandInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = db::client(ctx).await;
+
+        let guild_id = command.guild_id.unwrap().0;
+        let user_id = command
+            .data
+            .options
+            .first()
+            .and_then(|subcommand| subcommand.options.first())
+            .and_then(|option| option.value.as_ref())
+            .and_then(|value| value.as_str())
+            .and_then(|user| user.parse::<u64>().ok())
+            .unwrap_or(command.user.id.0);
+
+        // other users' history is hidden if they are incognito, unless an admin is asking
+        let is_admin = command
+            .member
+            .as_ref()
+            .and_then(|member| member.permissions)
+            .is_some_and(|permissions| permissions.manage_guild());
+
+        if user_id != command.user.id.0 && !is_admin {
+            let member = GuildMember::from_id(&db, guild_id, user_id).await?;
+
+            if member.settings.incognito.unwrap_or(false) {
+                command
+                    .create_interaction_response(&ctx.http, |response| {
+                        response
+                            .kind(InteractionResponseType::ChannelMessageWithSource)
+                            .interaction_response_data(|message| {
+                                message
+                                    .embed(|embed| {
+                                        embed.description("This user is incognito.");
+                                        embed.color(colors::red())
+                                    })
+                                    .ephemeral(true)
+                            })
+                    })
+                    .await?;
+                return Ok(());
+            }
+        }
+
+        // queued message xp would be missing from the history otherwise
+        batcher::get(ctx).await.flush().await;
+
+        let entries = match LedgerEntry::for_member(&db, guild_id, user_id, HISTORY_LENGTH).await {
+            Ok(entries) => entries,
+            Err(why) => {
+                log::error!("Could not get xp history of {}: {}", user_id, why);
+
+                command
+                    .create_interaction_response(&ctx.http, |response| {
+                        response
+                            .kind(InteractionResponseType::ChannelMessageWithSource)
+                            .interaction_response_data(|message| {
+                                message
+                                    .embed(|embed| {
+                                        db_error_embed(
+                                            embed,
+                                            &format!(
+                                                "Could not get the xp history of <@{}>.",
+                                                user_id
+                                            ),
+                                            &why,
+                                        )
+                                    })
+                                    .ephemeral(true)
+                            })
+                    })
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        let description = if entries.is_empty() {
+            format!("There are no xp changes recorded for <@{}> yet.", user_id)
+        } else {
+            entries
+                .iter()
+                .map(|entry| {
+                    let actor = match &entry.actor {
+                        Some(actor) => format!(" by <@{}>", actor),
+                        None => String::new(),
+                    };
+
+                    format!(
+                        "<t:{}:R> **{}{}** xp, {}{} → {}",
+                        entry.timestamp,
+                        if entry.delta >= 0 { "+" } else { "-" },
+                        format_number(entry.delta.abs()),
+                        reason_label(entry.reason),
+                        actor,
+                        format_number(entry.xp as i64)
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        };
+
+        command
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message
+                            .embed(|embed| {
+                                embed.title("Xp history");
+                                embed.description(format!("<@{}>\n\n{}", user_id, description));
+                                embed.color(colors::blue());
+                                embed
+                            })
+                            .ephemeral(true)
+                    })
+            })
+            .await?;
+
+        Ok(())
+    }
+}
+
+fn reason_label(reason: XpReason) -> &'static str {
+    match reason {
+        XpReason::Message => "message",
+        XpReason::Reaction => "reaction",
+        XpReason::Voice => "voice chat",
+        XpReason::Daily => "/daily",
+        XpReason::Fish => "/fish",
+        XpReason::Loot => "/loot",
+        XpReason::Roll => "/roll",
+        XpReason::Trivia => "/trivia",
+        XpReason::Party => "/party",
+        XpReason::Add => "/addxp",
+        XpReason::Remove => "/removexp",
+        XpReason::Set => "/setxp",
+        XpReason::SetLevel => "/setlevel",
+        XpReason::Reset => "reset",
+        XpReason::Leave => "left the server",
+        XpReason::Restore => "restored from a backup",
+        XpReason::Unknown => "unknown",
+    }
+}
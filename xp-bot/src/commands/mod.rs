@@ -1,13 +1,24 @@
-use std::error::Error;
+use std::{collections::HashMap, error::Error, sync::OnceLock};
 
 use serenity::{
-    async_trait, builder::CreateApplicationCommand,
-    model::prelude::application_command::ApplicationCommandInteraction, prelude::Context,
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::{
+        application_command::ApplicationCommandInteraction, autocomplete::AutocompleteInteraction,
+        message_component::MessageComponentInteraction, modal::ModalSubmitInteraction,
+    },
+    prelude::Context,
 };
 
 pub mod admin;
+pub mod autocomplete;
+pub mod components;
 pub mod games;
 pub mod misc;
+pub mod pipeline;
+
+use components::CustomId;
+use pipeline::Requirements;
 
 #[async_trait]
 pub trait XpCommand: Send + Sync {
@@ -16,11 +27,40 @@ pub trait XpCommand: Send + Sync {
         &self,
         command: &'a mut CreateApplicationCommand,
     ) -> &'a mut CreateApplicationCommand;
+    // checked by the pipeline before `exec` runs
+    fn requirements(&self) -> Requirements {
+        Requirements::default()
+    }
     async fn exec(
         &self,
         ctx: &Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    // suggestions for options registered with `set_autocomplete(true)`
+    async fn autocomplete(
+        &self,
+        _ctx: &Context,
+        _interaction: &AutocompleteInteraction,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+    // buttons and select menus whose custom id names this command
+    async fn component(
+        &self,
+        _ctx: &Context,
+        _interaction: &MessageComponentInteraction,
+        _id: CustomId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+    async fn modal(
+        &self,
+        _ctx: &Context,
+        _modal: &ModalSubmitInteraction,
+        _id: CustomId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
 }
 
 pub const COMMANDS: &[&dyn XpCommand] = &[
@@ -32,10 +72,13 @@ pub const COMMANDS: &[&dyn XpCommand] = &[
     &misc::voicetime::VoicetimeCommand,
     &misc::incognito::IncognitoCommand,
     &misc::distance::DistanceCommand,
+    &misc::background::BackgroundCommand,
+    &misc::xp::XpHistoryCommand,
     &admin::add::AddCommand,
     &admin::set::SetCommand,
     &admin::remove::RemoveCommand,
     &admin::reset::ResetCommand,
+    &admin::restore::RestoreCommand,
     &admin::setlevel::SetLevelCommand,
     &admin::setstreak::SetStreakCommand,
     &games::fish::FishCommand,
@@ -44,4 +87,21 @@ pub const COMMANDS: &[&dyn XpCommand] = &[
     &games::daily::DailyCommand,
     &games::trivia::TriviaCommand,
     &games::party::PartyCommand,
-];
\ No newline at end of file
+];
+static REGISTRY: OnceLock<HashMap<&'static str, &'static dyn XpCommand>> = OnceLock::new();
+
+// the command registered under `name`, the lookup map is built on first use
+pub fn find(name: &str) -> Option<&'static dyn XpCommand> {
+    REGISTRY
+        .get_or_init(|| {
+            let mut registry = HashMap::with_capacity(COMMANDS.len());
+            for command in COMMANDS {
+                if registry.insert(command.name(), *command).is_some() {
+                    panic!("Command {} is registered twice", command.name());
+                }
+            }
+            registry
+        })
+        .get(name)
+        .copied()
+}
@@ -0,0 +1,309 @@
+use std::error::Error;
+
+use serenity::{
+    builder::CreateEmbed,
+    model::{
+        prelude::{application_command::ApplicationCommandInteraction, InteractionResponseType},
+        Permissions,
+    },
+    prelude::Context,
+};
+use xp_db_connector::{
+    guild::Guild,
+    guild_member::{GuildMember, GuildMemberTimestamps},
+};
+
+use crate::utils::{
+    colors, db,
+    entitlements::{self, GuildEntitlement},
+    rules,
+    utils::is_cooldowned,
+};
+
+/*
+    What has to hold before a command runs.
+    > Declared by commands through `XpCommand::requirements` and enforced by `check`
+    > before `exec`, so commands only deal with their own logic.
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Requirements {
+    // commands that need a server opt in, everything else also works in dms
+    pub guild_only: bool,
+    pub module: Option<Module>,
+    pub access: Access,
+    pub cooldown: Option<Cooldown>,
+    // checked against the member's permissions in the channel
+    pub permissions: Permissions,
+}
+
+impl Default for Requirements {
+    fn default() -> Self {
+        Requirements {
+            guild_only: false,
+            module: None,
+            access: Access::Everyone,
+            cooldown: None,
+            permissions: Permissions::empty(),
+        }
+    }
+}
+
+impl Requirements {
+    pub fn guild() -> Self {
+        Requirements {
+            guild_only: true,
+            ..Default::default()
+        }
+    }
+
+    // what every game shares, the module has to be on and the member has to be able to play
+    pub fn game(module: Module, cooldown: Option<Cooldown>) -> Self {
+        Requirements {
+            module: Some(module),
+            access: Access::Vote,
+            cooldown,
+            ..Requirements::guild()
+        }
+    }
+
+    pub fn admin() -> Self {
+        Requirements {
+            permissions: Permissions::MANAGE_GUILD,
+            ..Requirements::guild()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Module {
+    Games,
+    Trivia,
+}
+
+impl Module {
+    fn enabled(&self, guild: &Guild) -> bool {
+        match self {
+            Module::Games => guild.modules.games,
+            Module::Trivia => guild.modules.trivia,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Module::Games => "Games",
+            Module::Trivia => "Trivia",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Everyone,
+    // a top.gg vote, skipped in vote-free guilds and for premium users
+    Vote,
+}
+
+// the member timestamp a command is rate limited by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cooldown {
+    Daily,
+    Fish,
+    Loot,
+    Roll,
+    Trivia,
+}
+
+impl Cooldown {
+    fn last_used(&self, timestamps: &GuildMemberTimestamps) -> u64 {
+        match self {
+            Cooldown::Daily => timestamps.game_daily,
+            Cooldown::Fish => timestamps.game_fish,
+            Cooldown::Loot => timestamps.game_loot,
+            Cooldown::Roll => timestamps.game_roll,
+            Cooldown::Trivia => timestamps.game_trivia,
+        }
+        .unwrap_or(0)
+    }
+
+    // in milliseconds, like the timestamps
+    fn length(&self, entitlement: &GuildEntitlement, guild: &Guild) -> u64 {
+        let seconds = match self {
+            Cooldown::Daily => 86400,
+            Cooldown::Fish | Cooldown::Loot | Cooldown::Roll => entitlement.game_cooldown(guild),
+            Cooldown::Trivia => entitlement.trivia_cooldown(guild),
+        };
+
+        seconds.max(0) as u64 * 1000
+    }
+
+    fn message(&self, time_left: i64) -> String {
+        let action = match self {
+            Cooldown::Daily => {
+                return format!(
+                    "You already claimed your daily xp. You can claim it again **{}**.",
+                    chrono_humanize::HumanTime::from(chrono::Duration::milliseconds(time_left))
+                );
+            }
+            Cooldown::Fish => "fish",
+            Cooldown::Loot => "loot",
+            Cooldown::Roll => "roll",
+            Cooldown::Trivia => "play trivia",
+        };
+
+        format!(
+            "You need to wait **{} seconds** before you can {} again.",
+            time_left / 1000,
+            action
+        )
+    }
+}
+
+// Ok(false) if a requirement is not met, the member has already been told why
+pub async fn check(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    requirements: Requirements,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let guild_id = match command.guild_id {
+        Some(guild_id) => guild_id.0,
+        None if requirements.guild_only => {
+            return deny(ctx, command, |embed| {
+                embed.description("This command can only be used in a server.")
+            })
+            .await;
+        }
+        None => return Ok(true),
+    };
+
+    if !requirements.permissions.is_empty() {
+        let permissions = command
+            .member
+            .as_ref()
+            .and_then(|member| member.permissions)
+            .unwrap_or_else(Permissions::empty);
+
+        if !permissions.contains(requirements.permissions) {
+            return deny(ctx, command, |embed| {
+                embed.title("Missing permissions");
+                embed.description(format!(
+                    "You need the **{}** permission to use this command.",
+                    requirements.permissions
+                ))
+            })
+            .await;
+        }
+    }
+
+    let in_thread = rules::guild_channel(ctx, guild_id, command.channel_id)
+        .await
+        .is_some_and(|channel| rules::is_thread(channel.kind));
+
+    let guild = if in_thread || requirements.module.is_some() || requirements.cooldown.is_some() {
+        Some(Guild::from_id(&db::client(ctx).await, guild_id).await?)
+    } else {
+        None
+    };
+
+    let entitlements = entitlements::get(ctx).await;
+
+    if let (true, Some(guild)) = (in_thread, &guild) {
+        if !guild.modules.enablecommandsinthreads {
+            return deny(ctx, command, |embed| {
+                embed.title("Commands in threads disabled");
+                embed.description(format!(
+                    "Commands can not be used in threads on this server. \
+                    An administrator can enable them [here](https://xp-bot.net/me/servers/{}/modules).",
+                    guild_id
+                ))
+            })
+            .await;
+        }
+
+        if !entitlements.premium(guild_id).await {
+            return deny(ctx, command, |embed| {
+                embed.title("Premium required");
+                embed.description(
+                    "Commands in threads are a premium feature. \
+                    You can get premium for this server [here](https://xp-bot.net/premium).",
+                )
+            })
+            .await;
+        }
+    }
+
+    if let (Some(module), Some(guild)) = (requirements.module, &guild) {
+        if !module.enabled(guild) {
+            return deny(ctx, command, |embed| {
+                embed.title(format!("{} module disabled", module.label()));
+                embed.description(format!(
+                    "This module is disabled on this server. \
+                    An administrator can enable it [here](https://xp-bot.net/me/servers/{}/modules).",
+                    guild_id
+                ))
+            })
+            .await;
+        }
+    }
+
+    if requirements.access == Access::Vote
+        && !entitlements.can_play(command.user.id.0, guild_id).await
+    {
+        return deny(ctx, command, |embed| {
+            embed.title("Vote required");
+            embed.description(
+                "You need to vote for the bot to use this command. \
+                You can vote [here](https://top.gg/bot/706935674800177193/vote).",
+            );
+            embed.field(
+                "Don't want to vote?",
+                "You can also get premium [here](https://xp-bot.net/premium).",
+                false,
+            )
+        })
+        .await;
+    }
+
+    if let (Some(cooldown), Some(guild)) = (requirements.cooldown, &guild) {
+        let member =
+            GuildMember::from_id(&db::client(ctx).await, guild_id, command.user.id.0).await?;
+
+        let time_now = chrono::Utc::now().timestamp() as u64 * 1000;
+        let last_used = cooldown.last_used(&member.timestamps);
+        let length = cooldown.length(&entitlements.guild(guild_id).await, guild);
+
+        if is_cooldowned(time_now, last_used, length) {
+            let time_left = length as i64 - (time_now as i64 - last_used as i64);
+
+            return deny(ctx, command, |embed| {
+                embed.description(cooldown.message(time_left))
+            })
+            .await;
+        }
+    }
+
+    Ok(true)
+}
+
+async fn deny<F>(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    f: F,
+) -> Result<bool, Box<dyn Error + Send + Sync>>
+where
+    F: FnOnce(&mut CreateEmbed) -> &mut CreateEmbed,
+{
+    command
+        .create_interaction_response(ctx, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .embed(|embed| f(embed).color(colors::red()))
+                        .ephemeral(true);
+                    message
+                })
+        })
+        .await?;
+
+    Ok(false)
+}
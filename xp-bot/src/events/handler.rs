@@ -1,12 +1,14 @@
+use std::time::Duration;
+
 use log::{error, info};
 use serenity::{
     async_trait,
     model::{prelude::{Activity, GuildId, Interaction, InteractionResponseType, Ready, Message, Reaction, ChannelId, component::ButtonStyle, ReactionType, Member, RoleId, GuildChannel, command::Command}, voice::VoiceState},
     prelude::{Context, EventHandler},
 };
-use xp_db_connector::{guild::Guild, guild_member::GuildMember, user::User};
+use xp_db_connector::{guild::Guild, guild_member::{GuildMember, GuildMemberData, XpReason}, journal::XpWrite};
 
-use crate::{commands::{self, COMMANDS}, utils::{colors, utils::{is_cooldowned, self, send_level_up, handle_level_roles, conform_xpc}, math::calculate_level}};
+use crate::{commands::{self, autocomplete, components, pipeline}, utils::{batcher::{self, XpGain}, colors, db, entitlements, journal, rules::{self, XpSource}, voice::{self, VoiceRules}, utils::{is_cooldowned, self, send_level_up, handle_level_roles}, math::calculate_level}};
 
 pub struct Handler;
 
@@ -54,9 +56,22 @@ impl EventHandler for Handler {
         ctx.set_activity(Activity::listening("xp-bot.net")).await;
     }
 
-    async fn cache_ready(&self, _ctx: Context, _guilds: Vec<GuildId>) {
+    async fn cache_ready(&self, ctx: Context, guilds: Vec<GuildId>) {
         info!("Cache is ready!");
 
+        // members that were in voice before the bot (re)started keep earning from now on
+        let mut recovered = 0;
+        for guild_id in guilds {
+            if let Some(states) = ctx.cache.guild_field(guild_id, |guild| guild.voice_states.clone()) {
+                recovered += voice::recover(&ctx, guild_id.0, &states).await;
+            }
+        }
+        info!("Recovered {} voice sessions", recovered);
+
+        if voice::get(&ctx).await.start_ticking() {
+            tokio::spawn(voice::run(ctx.clone(), Duration::from_secs(60)));
+        }
+
         // register slash commands
 
 // -> dev env slash registration
@@ -82,142 +97,66 @@ impl EventHandler for Handler {
         if let Interaction::ApplicationCommand(command) = interaction {
             let command_name = command.data.name.as_str();
 
-            for cmd in commands::COMMANDS {
-                if cmd.name() == command_name {
-                    let result = cmd.exec(&ctx, &command).await;
-
-                    match result {
-                        Ok(_) => {}
-                        Err(why) => {
-                            log::error!("Could not execute command: {:?}", why);
-
-                            let cmd = command
-                                .create_interaction_response(&ctx.http, |response| {
-                                    response
-                                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                                        .interaction_response_data(|message| {
-                                            message.embed(|embed| {
-                                                embed.title("Error");
-                                                embed.description(
-                                                    "An error occured while executing the command.\nIf this error persists, please join our [support server](https://discord.xp-bot.net).");
-                                                embed.color(colors::red());
-                                                embed
-                                            });
-                                            message.ephemeral(true)            
-                                        })
-                                })
-                                .await;
-
-                            if let Err(why) = cmd {
-                                error!("Could not execute command: {:?}", why);
-                            }
-                        }
-                    }
-                    return ();
+            let cmd = match commands::find(command_name) {
+                Some(cmd) => cmd,
+                None => {
+                    error!("Received unknown command: {:?}", command_name);
+                    return;
                 }
-            }
-
-            error!("Received unknown command: {:?}", command_name);
-            ()
-        } else if let Interaction::ModalSubmit(command) = interaction {
-            let modal_data = command.data.clone();
-
-            match modal_data.custom_id.as_str() {
-                "reset_community_settings" => {
-                    let guild_id = command.guild_id.unwrap();
-
-                    let action = Guild::delete(&guild_id.0).await;
+            };
 
-                    if action.is_err() {
-                        error!("Could not reset community settings: {:?}", action.err());
-                        return;
-                    }
-                    
-                    command.create_interaction_response(&ctx.http, |response| {
-                        response
-                            .kind(InteractionResponseType::ChannelMessageWithSource)
-                            .interaction_response_data(|message| {
-                                message.embed(|embed| {
-                                    embed.description(
-                                        "Successfully reset community settings.");
-                                    embed.color(colors::green());
-                                    embed
-                                });
-                                message.ephemeral(true)            
-                            })
-                    })
-                    .await.unwrap();
-                    
-                }
-                "reset_community_xp" => {
-                    let guild_id = command.guild_id.unwrap();
+            // exec only runs once the command's requirements hold
+            let result = match pipeline::check(&ctx, &command, cmd.requirements()).await {
+                Ok(true) => cmd.exec(&ctx, &command).await,
+                Ok(false) => Ok(()),
+                Err(why) => Err(why),
+            };
 
-                    let action = Guild::delete_xp(&guild_id.0).await;
+            if let Err(why) = result {
+                log::error!("Could not execute command: {:?}", why);
 
-                    if action.is_err() {
-                        error!("Could not reset community xp: {:?}", action.err());
-                        return;
-                    }
-                    
-                    command.create_interaction_response(&ctx.http, |response| {
+                let cmd = command
+                    .create_interaction_response(&ctx.http, |response| {
                         response
                             .kind(InteractionResponseType::ChannelMessageWithSource)
                             .interaction_response_data(|message| {
                                 message.embed(|embed| {
+                                    embed.title("Error");
                                     embed.description(
-                                        "Successfully reset community xp.");
-                                    embed.color(colors::green());
+                                        "An error occured while executing the command.\nIf this error persists, please join our [support server](https://discord.xp-bot.net).");
+                                    embed.color(colors::red());
                                     embed
                                 });
                                 message.ephemeral(true)            
                             })
                     })
-                    .await.unwrap();
-                }
-                "reset_user_xp" => {
-                    let experimental_extract = format!("{:?}", command
-                        .data
-                        .components
-                        .first()
-                        .unwrap()
-                        .components
-                        .first()
-                        .unwrap());
-
-                    // extract user id from experimental_extract
-                    let user_id = experimental_extract.split("custom_id: \"reset_user_xp_input_").collect::<Vec<&str>>()[1].split("\"").collect::<Vec<&str>>()[0].parse::<u64>().unwrap();
-                    
-                    let mut guild_member = GuildMember::from_id(command.guild_id.unwrap().0, user_id).await.unwrap();
-
-                    guild_member = conform_xpc(guild_member, &ctx, &command.guild_id.unwrap().0, &user_id).await;
-                    let res = GuildMember::set_xp(command.guild_id.unwrap().0, user_id, &0, &guild_member).await.unwrap();
-
-                    if res.is_err() {
-                        error!("Could not reset user xp: {:?}", res.err());
-                        return;
-                    }
+                    .await;
 
-                    command.create_interaction_response(&ctx.http, |response| {
-                        response
-                            .kind(InteractionResponseType::ChannelMessageWithSource)
-                            .interaction_response_data(|message| {
-                                message.embed(|embed| {
-                                    embed.description(
-                                        "Successfully reset user xp.");
-                                    embed.color(colors::green());
-                                    embed
-                                });
-                                message.ephemeral(true)            
-                            })
-                    }).await.unwrap();
+                if let Err(why) = cmd {
+                    error!("Could not execute command: {:?}", why);
                 }
-                _ => {}
-            };
+            }
+        } else if let Interaction::ModalSubmit(modal) = interaction {
+            // modals and components go to the command named in their custom id
+            if let Err(why) = components::route_modal(&ctx, &modal).await {
+                error!("Could not handle modal {:?}: {:?}", modal.data.custom_id, why);
+            }
+        } else if let Interaction::Autocomplete(autocomplete) = interaction {
+            if let Err(why) = autocomplete::route(&ctx, &autocomplete).await {
+                error!("Could not autocomplete {:?}: {:?}", autocomplete.data.name, why);
+            }
+        } else if let Interaction::MessageComponent(component) = interaction {
+            if let Err(why) = components::route_component(&ctx, &component).await {
+                error!("Could not handle component {:?}: {:?}", component.data.custom_id, why);
+            }
         }
     }
 
     // XP welcome message when it gets invited to a server
     async fn guild_create(&self, ctx: Context, guild: serenity::model::guild::Guild, is_new: bool) {
+        // sent again when a shard reconnects, voice changes in between were missed
+        voice::recover(&ctx, guild.id.0, &guild.voice_states).await;
+
         if !is_new {
             return ();
         }
@@ -289,7 +228,14 @@ impl EventHandler for Handler {
     }
 
     async fn guild_member_addition(&self, ctx: Context, mut new_member: Member) {
-        let guild = Guild::from_id(new_member.guild_id.0).await.unwrap();
+        let db = db::client(&ctx).await;
+        let guild = match Guild::from_id(&db, new_member.guild_id.0).await {
+            Ok(guild) => guild,
+            Err(why) => {
+                log::error!("Could not get guild ({}) from database: {}", new_member.guild_id.0, why);
+                return ();
+            }
+        };
 
         // get role that is assigned to level -1
         let autorole = guild.levelroles.iter().find(|role| role.level == -1);
@@ -318,17 +264,23 @@ impl EventHandler for Handler {
         }
 
         // get database data
-        let guild = match Guild::from_id(guild_id).await {
+        let db = db::client(&ctx).await;
+        let guild = match Guild::from_id(&db, guild_id).await {
             Ok(guild) => guild,
             Err(resp) => {
                 log::error!("Could not get guild ({}) from database: {:?}", guild_id, resp);
                 return ();
             }
         };
-        let mut member = match GuildMember::from_id(guild_id, user_id).await {
+        let batcher = batcher::get(&ctx).await;
+        let mut member = match batcher.member(guild_id, user_id).await {
             Ok(member) => member,
-            Err(resp) => {
-                log::error!("Could not get member ({}) of guild ({}) from database: {:?}", user_id, guild_id, resp);
+            Err(why) if why.is_not_found() => {
+                log::debug!("Member ({}) of guild ({}) does not exist yet: {}", user_id, guild_id, why);
+                return ();
+            }
+            Err(why) => {
+                log::error!("Could not get member ({}) of guild ({}) from database: {}", user_id, guild_id, why);
                 return ();
             }
         };
@@ -344,63 +296,51 @@ impl EventHandler for Handler {
             }
 
             // check for ignored roles, channels or categories
-            let channel_id = msg.channel_id.0;
-
-            let category_id = match msg.channel_id.to_channel(&ctx).await.unwrap() {
-                serenity::model::channel::Channel::Guild(channel) => {
-                    channel.parent_id.unwrap().0
-                }
-                _ => 0,
-            };
-
             let role_ids = msg
                 .member
-                .unwrap()
-                .roles
-                .iter()
-                .map(|role| role.0)
-                .collect::<Vec<u64>>();
-
-            for ignored_role in guild.ignored.roles {
-                if role_ids.contains(&ignored_role.parse::<u64>().unwrap().to_owned()) {
-                    return ();
-                }
-            }
-
-            if guild
-                .ignored
-                .channels
-                .unwrap()
-                .contains(&channel_id.to_owned().to_string())
-            {
-                return ();
-            }
+                .as_ref()
+                .map(|member| member.roles.iter().map(|role| role.0).collect::<Vec<u64>>())
+                .unwrap_or_default();
 
-            if guild
-                .ignored
-                .categories
-                .unwrap()
-                .contains(&category_id.to_owned().to_string())
-            {
-                return ();
-            }
+            let location = match rules::earns(&ctx, &guild, guild_id, XpSource::Message, msg.channel_id.0, &role_ids).await {
+                Some(location) => location,
+                None => return (),
+            };
 
             // calculate boost percentage
-            let guild = Guild::from_id(guild_id).await.unwrap();
             let boost_percentage = utils::calculate_total_boost_percentage_by_ids(
                 guild.clone(),
                 role_ids,
-                channel_id,
-                Some(category_id),
+                location.channel_id,
+                location.category_id,
             );
 
             // calculate xp
             let xp = (guild.values.messagexp as f32 * (boost_percentage + 1.0)) as u32;
 
-            // check if user leveled up, dont send if user is incognito
-            let current_level = calculate_level(&member.xp);
             let new_level = calculate_level(&(member.xp + xp as u64));
+            let xp = if !rules::capped(&guild, new_level) { xp } else { 0 };
+
+            // queue xp and new cooldown, the batcher writes them to the database later on
+            let gain = XpGain {
+                xp: xp as u64,
+                reason: XpReason::Message,
+                cooldown: Some((timestamp as u64, guild.values.messagecooldown as u64 * 1000)),
+                user_data: Some(GuildMemberData {
+                    username: Some(msg.author.name.clone()),
+                    avatar: Some(msg.author.avatar.clone().unwrap_or_default()),
+                    banner: msg.author.banner.clone(),
+                }),
+            };
+            let (current_level, new_level) = match batcher.gain(guild_id, user_id, gain).await {
+                Some((old_xp, new_xp)) => {
+                    member.xp = new_xp;
+                    (calculate_level(&old_xp), calculate_level(&new_xp))
+                }
+                None => return (),
+            };
 
+            // check if user leveled up, dont send if user is incognito
             if new_level > current_level {
                 handle_level_roles(&guild.clone(), &user_id, &new_level, &ctx, msg.guild_id.clone().unwrap().0).await;
                 
@@ -408,18 +348,6 @@ impl EventHandler for Handler {
                     send_level_up(guild.clone(), user_id, current_level, new_level, &ctx, msg.channel_id.0.clone(), &msg.author.name).await;
                 }
             }
-
-            if !guild.modules.maximumlevel && !(new_level as i64 >= guild.values.maximumlevel) {
-                member.xp += xp as u64;
-            }
-
-            // set new cooldown
-            member.timestamps.message_cooldown = Some(timestamp as u64);
-
-            member = conform_xpc(member, &ctx, &guild_id, &msg.author.id.0).await;
-
-            // update database
-            let _ = GuildMember::set_guild_member(guild_id, msg.author.id.0, member.clone()).await;
         }
 
         /*
@@ -437,9 +365,10 @@ impl EventHandler for Handler {
         // get users level
         let level = calculate_level(&member.xp);
 
-        // check for config modules
-        let use_prefix = guild.modules.autonickuseprefix;
-        let show_string = guild.modules.autonickshowstring;
+        // check for config modules, both are premium
+        let entitlement = entitlements::get(&ctx).await.guild(guild_id).await;
+        let use_prefix = guild.modules.autonickuseprefix && entitlement.premium;
+        let show_string = guild.modules.autonickshowstring && entitlement.premium;
 
         // create new nickname
         let regex = regex::Regex::new(r"\s*\[.*?\]\s*").unwrap();
@@ -509,73 +438,62 @@ impl EventHandler for Handler {
         let guild_id = add_reaction.guild_id.unwrap().0;
 
         // get database data
-        let guild = Guild::from_id(guild_id).await.unwrap();
-        let mut member = GuildMember::from_id(guild_id, user_id).await.unwrap();
-
-        // check if reactionxp module is enabled
-        if !guild.modules.reactionxp {
-            return ();
-        }
-
-        // check for ignored roles, channels or categories
-        let channel_id = add_reaction.channel_id.0;
-
-        let category_id = match add_reaction.channel_id.to_channel(&ctx).await.unwrap() {
-            serenity::model::channel::Channel::Guild(channel) => {
-                channel.parent_id.unwrap().0
+        let db = db::client(&ctx).await;
+        let guild = match Guild::from_id(&db, guild_id).await {
+            Ok(guild) => guild,
+            Err(why) => {
+                log::error!("Could not get guild ({}) from database: {}", guild_id, why);
+                return ();
             }
-            _ => 0,
         };
-
-        let role_ids = add_reaction
-            .member
-            .unwrap()
-            .roles
-            .iter()
-            .map(|role| role.0)
-            .collect::<Vec<u64>>();
-
-        for ignored_role in guild.ignored.roles {
-            if role_ids.contains(&ignored_role.parse::<u64>().unwrap().to_owned()) {
+        let batcher = batcher::get(&ctx).await;
+        let member = match batcher.member(guild_id, user_id).await {
+            Ok(member) => member,
+            Err(why) => {
+                log::error!("Could not get member ({}) of guild ({}) from database: {}", user_id, guild_id, why);
                 return ();
             }
-        }
+        };
 
-        if guild
-            .ignored
-            .channels
-            .unwrap()
-            .contains(&channel_id.to_owned().to_string())
-        {
-            return ();
-        }
+        // check for the module and ignored roles, channels or categories
+        let role_ids = add_reaction
+            .member
+            .as_ref()
+            .map(|member| member.roles.iter().map(|role| role.0).collect::<Vec<u64>>())
+            .unwrap_or_default();
 
-        if guild
-            .ignored
-            .categories
-            .unwrap()
-            .contains(&category_id.to_owned().to_string())
-        {
-            return ();
-        }
+        let location = match rules::earns(&ctx, &guild, guild_id, XpSource::Reaction, add_reaction.channel_id.0, &role_ids).await {
+            Some(location) => location,
+            None => return (),
+        };
 
         // calculate boost percentage
-        let guild = Guild::from_id(guild_id).await.unwrap();
-
         let boost_percentage = utils::calculate_total_boost_percentage_by_ids(
             guild.clone(),
             role_ids,
-            channel_id,
-            Some(category_id),
+            location.channel_id,
+            location.category_id,
         );
 
         // calculate xp
         let xp = (guild.values.reactionxp as f32 * (boost_percentage + 1.0)) as u32;
 
-        // check if user leveled up, dont send if user is incognito
-        let current_level = calculate_level(&member.xp);
         let new_level = calculate_level(&(member.xp + xp as u64));
+        let xp = if !rules::capped(&guild, new_level) { xp } else { 0 };
+
+        // queue xp, the batcher writes it to the database later on
+        let gain = XpGain {
+            xp: xp as u64,
+            reason: XpReason::Reaction,
+            cooldown: None,
+            user_data: None,
+        };
+        let (current_level, new_level) = match batcher.gain(guild_id, user_id, gain).await {
+            Some((old_xp, new_xp)) => (calculate_level(&old_xp), calculate_level(&new_xp)),
+            None => return (),
+        };
 
+        // check if user leveled up, dont send if user is incognito
         if new_level > current_level {
             let username = ctx
                 .http
@@ -599,186 +517,167 @@ impl EventHandler for Handler {
             }
         }
 
-        if !guild.modules.maximumlevel && !(new_level as i64 >= guild.values.maximumlevel) {
-            member.xp += xp as u64;
+    }
+
+    // the bot only has to be in threads it hands out message or reaction xp in
+    async fn thread_create(&self, ctx: Context, thread: GuildChannel) {
+        if thread.member.is_some() {
+            return;
         }
 
-        member = conform_xpc(member, &ctx, &guild_id, &add_reaction.user_id.unwrap().0).await;
+        let db = db::client(&ctx).await;
+        let guild = match Guild::from_id(&db, thread.guild_id.0).await {
+            Ok(guild) => guild,
+            Err(why) => {
+                log::warn!("Could not get guild ({}) for new thread ({}): {}", thread.guild_id, thread.id, why);
+                return;
+            }
+        };
 
-        // update database
-        let _ = GuildMember::set_xp(guild_id, user_id, &member.xp, &member).await;
-    }
+        if !guild.modules.messagexp && !guild.modules.reactionxp {
+            return;
+        }
 
-    async fn thread_create(&self, ctx: Context, thread: GuildChannel) {
-        thread.id.join_thread(ctx.http).await.unwrap();
+        // threads in ignored channels or categories would not earn anything either
+        let location = rules::Location::of(&ctx, thread.guild_id.0, &thread).await;
+        if rules::ignores_location(&guild, &location) {
+            return;
+        }
+
+        if let Err(why) = thread.id.join_thread(&ctx.http).await {
+            log::warn!("Could not join thread ({}) of guild ({}): {}", thread.id, thread.guild_id, why);
+        }
     }
 
     // reset xp of user when they leave the server if module is enabled
     async fn guild_member_removal(
         &self,
-        _ctx: Context,
+        ctx: Context,
         guild_id: GuildId,
         user: serenity::model::prelude::User,
         _member_data_if_available: Option<Member>,
     ) {
-        let guild = Guild::from_id(guild_id.0).await.unwrap();
+        let db = db::client(&ctx).await;
+        let guild = match Guild::from_id(&db, guild_id.0).await {
+            Ok(guild) => guild,
+            Err(why) => {
+                log::error!("Could not get guild ({}) from database: {}", guild_id.0, why);
+                return ();
+            }
+        };
 
         // check if user is a bot
         if user.bot {
             return ();
         }
 
-        if !guild.modules.resetonleave {
+        if !guild.modules.resetonleave || !entitlements::get(&ctx).await.premium(guild_id.0).await {
             return ();
         }
 
-        let mut member = GuildMember::from_id(guild_id.0, user.id.0).await.unwrap();
+        let mut member = match GuildMember::from_id(&db, guild_id.0, user.id.0).await {
+            Ok(member) => member,
+            Err(why) => {
+                log::error!("Could not get member ({}) of guild ({}) from database: {}", user.id.0, guild_id.0, why);
+                return ();
+            }
+        };
 
         // reset xp
         member.xp = 0;
 
-        // update database
-        let _ = GuildMember::set_xp(guild_id.0, user.id.0, &member.xp, &member).await;
+        // update database, journaled if the api is down
+        let write = XpWrite::SetXp {
+            guild_id: guild_id.0,
+            member_id: user.id.0,
+            xp: member.xp,
+            user_data: member.userData.clone(),
+            reason: XpReason::Leave,
+            actor: None,
+        };
+        if let Err(why) = journal::get(&ctx).await.write(&db, write).await {
+            log::error!("Could not reset xp of member ({}) of guild ({}): {}", user.id.0, guild_id.0, why);
+        }
+        batcher::get(&ctx).await.discard(guild_id.0, user.id.0).await;
     }
 
     async fn voice_state_update(&self, ctx: Context, old: Option<VoiceState>, new: VoiceState) {
-        // check if the event is a join, leave or move event
-        if old.is_none() && new.channel_id.is_some() {
-            Handler::voice_join(ctx, new.guild_id.unwrap(), &new).await;
-        } else if old.is_some() && new.channel_id.is_none() {
-            Handler::voice_leave(ctx, new.guild_id.unwrap(), old, new).await;
-        } else if old.is_some() && new.channel_id.is_some() {
-            // check if event is channel move event
-            if old.clone().unwrap().channel_id.is_none() {
-                Handler::voice_join(ctx, new.guild_id.unwrap(), &new).await;
-            } else if new.channel_id.unwrap() != old.clone().unwrap().channel_id.unwrap() {
-                let guild = Guild::from_id(new.guild_id.unwrap().0).await.unwrap();
-
-                // handle ignored channels
-                match new.channel_id {
-                    Some(channel_id) => {
-                        // check if channel is ignored
-                        if guild.clone().ignored.channels.unwrap().contains(&channel_id.0.to_string()) {
-                            Handler::voice_leave(ctx.clone(), new.clone().guild_id.unwrap(), old, new.clone()).await;
-                        }else if guild.modules.ignoreafk {
-                            // check if channel is afk channel
-                            if channel_id.0 == new.guild_id.unwrap().clone().to_guild_cached(ctx.cache.clone()).unwrap().afk_channel_id.unwrap().0 {
-                                Handler::voice_leave(ctx.clone(), new.clone().guild_id.unwrap(), old, new.clone()).await;
-                            }
-                        }
-                    }
-                    None => {}
-                }
+        let guild_id = match new.guild_id {
+            Some(guild_id) => guild_id,
+            None => {
+                log::error!("Could not get guild id of user {}", new.user_id.0);
+                return ();
+            }
+        };
+        let old_channel_id = old.as_ref().and_then(|old| old.channel_id);
 
-                let guild_id = match new.guild_id {
-                    Some(guild_id) => guild_id,
-                    None => {
-                        log::error!("Could not get guild id of user {}", new.user_id.0);
-                        return ();
-                    }
-                };
-                Handler::voice_join(ctx, guild_id, &new).await;
+        // a move ends the session in the old channel and starts one in the new channel
+        if old_channel_id != new.channel_id {
+            if let Some(channel_id) = old_channel_id {
+                Handler::voice_leave(ctx.clone(), guild_id, channel_id, &new).await;
+            }
+            if let Some(channel_id) = new.channel_id {
+                Handler::voice_join(ctx.clone(), guild_id, channel_id, &new).await;
             }
         }
+
+        // whoever is left in both channels may have started or stopped earning, mutes included
+        let db = db::client(&ctx).await;
+        let guild = match Guild::from_id(&db, guild_id.0).await {
+            Ok(guild) => guild,
+            Err(why) => {
+                log::error!("Could not get guild ({}) from database: {}", guild_id.0, why);
+                return ();
+            }
+        };
+        if let Some(channel_id) = old_channel_id.filter(|channel_id| Some(*channel_id) != new.channel_id) {
+            voice::evaluate(&ctx, &guild, guild_id.0, channel_id.0).await;
+        }
+        if let Some(channel_id) = new.channel_id {
+            voice::evaluate(&ctx, &guild, guild_id.0, channel_id.0).await;
+        }
     }
 }
 
 impl Handler {
-    pub async fn voice_join(_ctx: Context, _guild_id: GuildId, joined: &VoiceState) {
+    pub async fn voice_join(ctx: Context, guild_id: GuildId, channel_id: ChannelId, joined: &VoiceState) {
         let timestamp = chrono::Utc::now().timestamp() * 1000;
-        let mut user = User::from_id(joined.user_id.0).await.unwrap();
 
-        // set new timestamp
-        user.timestamps.join_voicechat = Some(timestamp as u64);
-
-        // update database
-        let _ = User::set(joined.user_id.0, user).await;
+        voice::get(&ctx).await.start(guild_id.0, joined.user_id.0, channel_id.0, timestamp as u64).await;
     }
 
-    pub async fn voice_leave(ctx: Context, guild_id: GuildId, old: Option<VoiceState>, left: VoiceState) {
-        let timestamp = chrono::Utc::now().timestamp() * 1000;
-        let mut user = User::from_id(left.user_id.0).await.unwrap();
-        let mut member = GuildMember::from_id(guild_id.0, left.user_id.0).await.unwrap();
-        let guild = Guild::from_id(guild_id.0).await.unwrap();
-        let log_channel_id = guild.clone().logs.voicetime;
-
-        // check if voice module is enabled
-        if !guild.modules.voicexp {
-            return ();
-        }
-
-        // handle ignored channels
-        match old.clone().unwrap().channel_id {
-            Some(channel_id) => {
-                // check if channel is ignored
-                if guild.clone().ignored.channels.unwrap().contains(&channel_id.0.to_string()) {
-                    return ();
-                }
+    // `channel_id` is the channel that was left, `left` the member's new voice state
+    pub async fn voice_leave(ctx: Context, guild_id: GuildId, channel_id: ChannelId, left: &VoiceState) {
+        let session = match voice::get(&ctx).await.end(guild_id.0, left.user_id.0, channel_id.0).await {
+            Some(session) => session,
+            // joined before the bot could see it, there is nothing to count
+            None => return (),
+        };
 
-                if guild.modules.ignoreafk {
-                    // check if channel is afk channel
-                    if channel_id.0 == guild_id.clone().to_guild_cached(ctx.cache.clone()).unwrap().afk_channel_id.unwrap().0 {
-                        return ();
-                    }
-                }
-            }
-            None => {
-                log::warn!("Could not get channel id of user {}", left.user_id.0);
+        let db = db::client(&ctx).await;
+        let timestamp = chrono::Utc::now().timestamp() * 1000;
+        let guild = match Guild::from_id(&db, guild_id.0).await {
+            Ok(guild) => guild,
+            Err(why) => {
+                log::error!("Could not get guild ({}) from database: {}", guild_id.0, why);
+                return ();
             }
-        }
-
-        // calculate time in voice chat
-        let last_timestamp = user.timestamps.join_voicechat.unwrap_or(0);
-        let time_in_voicechat = (timestamp - last_timestamp as i64 - guild.values.voicejoincooldown as i64 * 1000) / 1000;
-        let time_in_voicechat = if time_in_voicechat < 0 { 0 } else { time_in_voicechat };
-
-        let old = old.unwrap();
-        // calculate boost percentage 
-        let boost_percentage = utils::calculate_total_boost_percentage_by_ids(
-            guild.clone(),
-            left.member.unwrap().roles.iter().map(|role| role.0).collect::<Vec<u64>>(),
-            old.channel_id.unwrap().0,
-            Some(match old.channel_id.unwrap().to_channel(&ctx).await.unwrap() {
-                serenity::model::channel::Channel::Guild(channel) => {
-                    channel.parent_id.unwrap().0
-                }
-                _ => 0,
-            }),
-        );
-
-        // calculate xp
-        let xp = ((guild.values.voicexp as f32 * (time_in_voicechat as f32 / 60.)) * (boost_percentage + 1.0)) as u32;
-
-        // check if user leveled up, dont send if user is incognito
-        let current_level = calculate_level(&member.xp);
-        let new_level = calculate_level(&(member.xp + xp as u64));
-
-        if new_level > current_level {
-            let username = ctx
-                .http
-                .get_user(left.user_id.0)
-                .await
-                .unwrap()
-                .name
-                .to_owned();
-
-            handle_level_roles(&guild.clone(), &left.user_id.0, &new_level, &ctx, old.guild_id.unwrap().0).await;
+        };
+        let log_channel_id = guild.clone().logs.voicetime;
 
-            if !member.settings.incognito.unwrap_or(false) {
-                send_level_up(guild.clone(),
-                    left.user_id.0,
-                    current_level,
-                    new_level,
-                    &ctx,
-                    old.channel_id.unwrap().0,
-                    &username,
-                ).await;
-            }
-        }
+        // the full minutes have been paid out while the session was running, this is the rest
+        let seconds = session.unpaid(timestamp as u64, &VoiceRules::new(&guild));
+        let payout = voice::pay(&ctx, &guild, guild_id.0, left.user_id.0, channel_id.0, seconds).await;
+        let xp = session.xp + payout.map_or(0, |payout| payout.xp);
+        let (current_level, new_level) = match payout.map(|payout| voice::merge_levels(session.levels, payout)).or(session.levels) {
+            Some(levels) => levels,
+            // nothing was paid out, the channel doesn't earn xp
+            None => return (),
+        };
 
         // send summary of voice time
         if guild.logs.voicetime.clone().is_some() {
-            let voice_time = (timestamp - last_timestamp as i64) / 1000;
+            let voice_time = session.duration(timestamp as u64) as i64;
 
             // make it days, hours, minutes, seconds
             let days = voice_time / 86400;
@@ -803,8 +702,6 @@ impl Handler {
             }; 
 
             // calculate level difference
-            let current_level = calculate_level(&member.xp);
-            let new_level = calculate_level(&(member.xp + xp as u64));
             let level_difference = new_level - current_level;
 
             let requested_user = ctx.http.get_user(left.user_id.0).await.unwrap().name.clone();
@@ -836,18 +733,5 @@ impl Handler {
                 })
                 .await;
         }
-
-        if !guild.modules.maximumlevel && !(new_level as i64 >= guild.values.maximumlevel) {
-            member.xp += xp as u64;
-        }
-        
-        member = conform_xpc(member, &ctx, &guild_id.0, &left.user_id.0).await;
-
-        // update database
-        let _ = GuildMember::set_xp(guild_id.0, left.user_id.0, &member.xp, &member).await;
-
-        // invalidate timestamp
-        user.timestamps.join_voicechat = None;
-        let _ = User::set(left.user_id.0, user).await;
     }
 }
@@ -1,10 +1,12 @@
 use events::handler::Handler;
 use log::{error, info};
 use serenity::{prelude::GatewayIntents, Client, client::bridge::gateway::ShardId};
-use std::{env, time::Duration, collections::HashMap};
+use std::{env, time::Duration, collections::HashMap, sync::Arc};
 use tokio::time::sleep;
 
-use crate::utils::{topgg::post_bot_stats, ilum::send_shard_report};
+use xp_db_connector::journal::Journal;
+
+use crate::utils::{batcher::{Batcher, XpBatcher}, db::Database, entitlements::{EntitlementCache, Entitlements}, journal::WriteJournal, topgg::post_bot_stats, ilum::send_shard_report, voice::{Voice, VoiceSessions}};
 
 mod commands;
 mod events;
@@ -24,6 +26,27 @@ async fn main() {
     // client initialization
     let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
 
+    // storage backend (hosted api or local sqlite), shared by all shards
+    let store = xp_db_connector::store::from_env().expect("Could not create storage backend");
+
+    // xp writes that fail while the api is down are kept on disk and replayed once it is back
+    let journal_path = env::var("XP_JOURNAL_PATH").unwrap_or_else(|_| "xp-journal.jsonl".to_string());
+    let journal = Arc::new(Journal::open(&journal_path).expect("Could not open write journal"));
+    if !journal.is_empty() {
+        info!("Write journal backlog: {} writes waiting from the last run", journal.len());
+    }
+    tokio::spawn(utils::journal::run(journal.clone(), store.clone(), Duration::from_secs(15)));
+
+    // message and reaction xp is collected in memory and written in batches
+    let batcher = Arc::new(XpBatcher::new(store.clone(), journal.clone(), Duration::from_secs(10), 500));
+    tokio::spawn(batcher.clone().run());
+
+    // premium, vote-free guilds and top.gg votes, resolved once and kept for a few minutes
+    let entitlements = Arc::new(EntitlementCache::new(store.clone(), Duration::from_secs(300)));
+
+    // who is in voice where, voice xp is counted from these sessions
+    let voice_sessions = Arc::new(VoiceSessions::new());
+
     let mut client = Client::builder(
         &token,
         GatewayIntents::non_privileged()
@@ -34,6 +57,11 @@ async fn main() {
             | GatewayIntents::MESSAGE_CONTENT,
     )
     .event_handler(Handler)
+    .type_map_insert::<Database>(store)
+    .type_map_insert::<Batcher>(batcher.clone())
+    .type_map_insert::<WriteJournal>(journal)
+    .type_map_insert::<Entitlements>(entitlements)
+    .type_map_insert::<Voice>(voice_sessions)
     .await
     .expect("Err creating client");
 
@@ -82,7 +110,35 @@ async fn main() {
         }
     });
 
+    // shut down the shards on ctrl+c / docker stop, so the pending xp below can be flushed
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutting down");
+        shard_manager.lock().await.shutdown_all().await;
+    });
+
     if let Err(why) = client.start_autosharded().await {
         error!("Client error: {:?}", why);
     }
+
+    batcher.flush().await;
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Could not register SIGTERM handler");
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
 }
@@ -0,0 +1,385 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serenity::prelude::{Context, TypeMapKey};
+use tokio::sync::{Mutex, Notify, RwLock};
+use xp_db_connector::{
+    guild_member::{GuildMember, GuildMemberData, XpBatchEntry, XpReason},
+    journal::{Journal, XpWrite},
+    DbResult, XpStore,
+};
+
+use super::utils::is_cooldowned;
+
+pub struct Batcher;
+
+impl TypeMapKey for Batcher {
+    type Value = Arc<XpBatcher>;
+}
+
+pub async fn get(ctx: &Context) -> Arc<XpBatcher> {
+    let data = ctx.data.read().await;
+
+    data.get::<Batcher>()
+        .expect("Expected an XpBatcher in the client data")
+        .clone()
+}
+
+// a single xp gain, `cooldown` is (now, cooldown) for gains that are rate limited like messages
+pub struct XpGain {
+    pub xp: u64,
+    pub reason: XpReason,
+    pub cooldown: Option<(u64, u64)>,
+    pub user_data: Option<GuildMemberData>,
+}
+
+struct PendingXp {
+    delta: i64,
+    message_cooldown: Option<u64>,
+    user_data: Option<GuildMemberData>,
+}
+
+/*
+    Write-behind layer for message and reaction xp.
+    > Members are loaded once and kept in memory, gains are applied to that copy right away,
+    > so level ups can be announced immediately.
+    > The deltas are collected per member and sent to the api in batches, either every
+    > `flush_interval` or as soon as `max_pending` members are waiting.
+    > Batches the api can't take right now go to the write journal and are replayed from there.
+    > Members are only loaded from the api while no batch is in flight, so a load neither
+    > misses a batch that was taken out of `pending` nor counts one the api already applied.
+*/
+pub struct XpBatcher {
+    db: Arc<dyn XpStore>,
+    journal: Arc<Journal>,
+    known: Mutex<HashMap<(u64, u64), (Instant, GuildMember)>>,
+    pending: Mutex<HashMap<(u64, u64, XpReason), PendingXp>>,
+    // written by `flush` until the api acknowledged the batch, read by loads
+    in_flight: RwLock<()>,
+    flush_interval: Duration,
+    max_pending: usize,
+    known_ttl: Duration,
+    flush_now: Notify,
+}
+
+impl XpBatcher {
+    pub fn new(
+        db: Arc<dyn XpStore>,
+        journal: Arc<Journal>,
+        flush_interval: Duration,
+        max_pending: usize,
+    ) -> Self {
+        XpBatcher {
+            db,
+            journal,
+            known: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+            in_flight: RwLock::new(()),
+            flush_interval,
+            max_pending,
+            known_ttl: Duration::from_secs(60),
+            flush_now: Notify::new(),
+        }
+    }
+
+    // the member as the bot currently sees it, including gains that are not flushed yet
+    pub async fn member(&self, guild_id: u64, member_id: u64) -> DbResult<GuildMember> {
+        if let Some((loaded_at, member)) = self.known.lock().await.get(&(guild_id, member_id)) {
+            if loaded_at.elapsed() < self.known_ttl {
+                return Ok(member.clone());
+            }
+        }
+
+        let _in_flight = self.in_flight.read().await;
+        let mut member = GuildMember::from_id(&self.db, guild_id, member_id).await?;
+
+        for ((pending_guild, pending_member, _), pending) in self.pending.lock().await.iter() {
+            if *pending_guild != guild_id || *pending_member != member_id {
+                continue;
+            }
+
+            member.xp = (member.xp as i64 + pending.delta).max(0) as u64;
+            if pending.message_cooldown.is_some() {
+                member.timestamps.message_cooldown = pending.message_cooldown;
+            }
+        }
+
+        self.known
+            .lock()
+            .await
+            .insert((guild_id, member_id), (Instant::now(), member.clone()));
+
+        Ok(member)
+    }
+
+    // records a gain for a member loaded through `member`, returns the xp before and after it,
+    // or None if the member is still on cooldown or could not be loaded again
+    pub async fn gain(&self, guild_id: u64, member_id: u64, gain: XpGain) -> Option<(u64, u64)> {
+        // forgotten or flushed out since `member`, the gain still counts
+        if !self.known.lock().await.contains_key(&(guild_id, member_id)) {
+            if let Err(why) = self.member(guild_id, member_id).await {
+                log::warn!(
+                    "Could not load member ({}) of guild ({}) again, queueing xp without it: {}",
+                    member_id,
+                    guild_id,
+                    why
+                );
+            }
+        }
+
+        let mut known = self.known.lock().await;
+        let xp = match known.get_mut(&(guild_id, member_id)) {
+            Some((_, member)) => {
+                if let Some((now, cooldown)) = gain.cooldown {
+                    let last_timestamp = member.timestamps.message_cooldown.unwrap_or(0);
+                    if is_cooldowned(now, last_timestamp, cooldown) {
+                        return None;
+                    }
+                    member.timestamps.message_cooldown = Some(now);
+                }
+
+                let old_xp = member.xp;
+                member.xp += gain.xp;
+                Some((old_xp, member.xp))
+            }
+            None => None,
+        };
+        drop(known);
+
+        if gain.xp == 0 && gain.cooldown.is_none() && gain.user_data.is_none() {
+            return xp;
+        }
+
+        let mut pending = self.pending.lock().await;
+        let entry = pending
+            .entry((guild_id, member_id, gain.reason))
+            .or_insert(PendingXp {
+                delta: 0,
+                message_cooldown: None,
+                user_data: None,
+            });
+
+        entry.delta += gain.xp as i64;
+        if let Some((now, _)) = gain.cooldown {
+            entry.message_cooldown = Some(now);
+        }
+        if gain.user_data.is_some() {
+            entry.user_data = gain.user_data;
+        }
+
+        if pending.len() >= self.max_pending {
+            self.flush_now.notify_one();
+        }
+
+        xp
+    }
+
+    // drops the in-memory copy, for writes that went past the batcher
+    pub async fn forget(&self, guild_id: u64, member_id: u64) {
+        self.known.lock().await.remove(&(guild_id, member_id));
+    }
+
+    // drops the in-memory copy and all unflushed gains, for writes that set an absolute xp value
+    pub async fn discard(&self, guild_id: u64, member_id: u64) {
+        self.known.lock().await.remove(&(guild_id, member_id));
+        self.pending
+            .lock()
+            .await
+            .retain(|(pending_guild, pending_member, _), _| {
+                *pending_guild != guild_id || *pending_member != member_id
+            });
+    }
+
+    pub async fn discard_guild(&self, guild_id: u64) {
+        self.known
+            .lock()
+            .await
+            .retain(|(known_guild, _), _| *known_guild != guild_id);
+        self.pending
+            .lock()
+            .await
+            .retain(|(pending_guild, _, _), _| *pending_guild != guild_id);
+    }
+
+    pub async fn flush(&self) {
+        let _in_flight = self.in_flight.write().await;
+        let pending = std::mem::take(&mut *self.pending.lock().await);
+        if pending.is_empty() {
+            return;
+        }
+
+        // forget members that have not been seen for a while, so the map doesn't grow forever
+        self.known
+            .lock()
+            .await
+            .retain(|_, (loaded_at, _)| loaded_at.elapsed() < self.known_ttl);
+
+        let entries = pending
+            .iter()
+            .map(|((guild_id, member_id, reason), pending)| XpBatchEntry {
+                guildId: guild_id.to_string(),
+                memberId: member_id.to_string(),
+                delta: pending.delta,
+                reason: *reason,
+                messageCooldown: pending.message_cooldown,
+                userData: pending.user_data.clone(),
+            })
+            .collect::<Vec<XpBatchEntry>>();
+
+        let count = entries.len();
+        match self
+            .journal
+            .write(&self.db, XpWrite::AddXpBatch { entries })
+            .await
+        {
+            Ok(_) => log::debug!("Flushed xp of {} members", count),
+            Err(why) => {
+                log::error!(
+                    "Could not flush xp of {} members, dropping it: {}",
+                    count,
+                    why
+                );
+            }
+        }
+    }
+
+    // runs until the process exits, flushing on the interval or when too many members pile up
+    pub async fn run(self: Arc<Self>) {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(self.flush_interval) => {}
+                _ = self.flush_now.notified() => {}
+            }
+
+            self.flush().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use tempfile::TempDir;
+    use xp_db_connector::{
+        guild_member::{GuildMember, XpReason},
+        journal::Journal,
+        sqlite::SqliteStore,
+        XpStore,
+    };
+
+    use super::{XpBatcher, XpGain};
+
+    const GUILD: u64 = 1;
+    const MEMBER: u64 = 2;
+
+    // the directory holds the journal, it is removed once the test drops it
+    fn batcher() -> (TempDir, Arc<dyn XpStore>, XpBatcher) {
+        let db: Arc<dyn XpStore> = Arc::new(SqliteStore::open_in_memory().unwrap());
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Arc::new(Journal::open(dir.path().join("xp.journal")).unwrap());
+
+        (
+            dir,
+            db.clone(),
+            XpBatcher::new(db, journal, Duration::from_secs(60), 100),
+        )
+    }
+
+    fn gain(xp: u64) -> XpGain {
+        XpGain {
+            xp,
+            reason: XpReason::Message,
+            cooldown: None,
+            user_data: None,
+        }
+    }
+
+    async fn stored_xp(db: &Arc<dyn XpStore>) -> u64 {
+        GuildMember::from_id(db, GUILD, MEMBER).await.unwrap().xp
+    }
+
+    #[tokio::test]
+    async fn flush_writes_the_gains() {
+        let (_dir, db, batcher) = batcher();
+
+        batcher.member(GUILD, MEMBER).await.unwrap();
+        assert_eq!(batcher.gain(GUILD, MEMBER, gain(5)).await, Some((0, 5)));
+        assert_eq!(batcher.gain(GUILD, MEMBER, gain(5)).await, Some((5, 10)));
+        assert_eq!(stored_xp(&db).await, 0);
+
+        batcher.flush().await;
+        assert_eq!(stored_xp(&db).await, 10);
+    }
+
+    #[tokio::test]
+    async fn gain_after_forget_still_counts() {
+        let (_dir, db, batcher) = batcher();
+
+        batcher.member(GUILD, MEMBER).await.unwrap();
+        batcher.gain(GUILD, MEMBER, gain(5)).await;
+        batcher.forget(GUILD, MEMBER).await;
+
+        // loaded again with the unflushed 5 on top
+        assert_eq!(batcher.gain(GUILD, MEMBER, gain(5)).await, Some((5, 10)));
+
+        batcher.flush().await;
+        assert_eq!(stored_xp(&db).await, 10);
+    }
+
+    #[tokio::test]
+    async fn gain_after_discard_still_counts() {
+        let (_dir, db, batcher) = batcher();
+
+        batcher.member(GUILD, MEMBER).await.unwrap();
+        batcher.gain(GUILD, MEMBER, gain(5)).await;
+        batcher.discard(GUILD, MEMBER).await;
+
+        assert_eq!(batcher.gain(GUILD, MEMBER, gain(3)).await, Some((0, 3)));
+
+        batcher.flush().await;
+        assert_eq!(stored_xp(&db).await, 3);
+    }
+
+    #[tokio::test]
+    async fn flushed_gains_are_not_counted_twice() {
+        let (_dir, db, batcher) = batcher();
+
+        batcher.member(GUILD, MEMBER).await.unwrap();
+        batcher.gain(GUILD, MEMBER, gain(5)).await;
+        batcher.flush().await;
+        batcher.forget(GUILD, MEMBER).await;
+
+        assert_eq!(batcher.member(GUILD, MEMBER).await.unwrap().xp, 5);
+        assert_eq!(batcher.gain(GUILD, MEMBER, gain(5)).await, Some((5, 10)));
+
+        batcher.flush().await;
+        assert_eq!(stored_xp(&db).await, 10);
+    }
+
+    #[tokio::test]
+    async fn cooldown_holds_back_gains() {
+        let (_dir, db, batcher) = batcher();
+        let cooled = |now| XpGain {
+            cooldown: Some((now, 10_000)),
+            ..gain(5)
+        };
+
+        batcher.member(GUILD, MEMBER).await.unwrap();
+        assert_eq!(
+            batcher.gain(GUILD, MEMBER, cooled(100_000)).await,
+            Some((0, 5))
+        );
+        assert_eq!(batcher.gain(GUILD, MEMBER, cooled(105_000)).await, None);
+        assert_eq!(
+            batcher.gain(GUILD, MEMBER, cooled(110_000)).await,
+            Some((5, 10))
+        );
+
+        batcher.flush().await;
+        assert_eq!(stored_xp(&db).await, 10);
+    }
+}
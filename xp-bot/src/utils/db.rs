@@ -0,0 +1,18 @@
+use std::sync::Arc;
+
+use serenity::prelude::{Context, TypeMapKey};
+use xp_db_connector::XpStore;
+
+pub struct Database;
+
+impl TypeMapKey for Database {
+    type Value = Arc<dyn XpStore>;
+}
+
+pub async fn client(ctx: &Context) -> Arc<dyn XpStore> {
+    let data = ctx.data.read().await;
+
+    data.get::<Database>()
+        .expect("Expected an XpStore in the client data")
+        .clone()
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serenity::prelude::{Context, TypeMapKey};
+use tokio::sync::Mutex;
+use xp_db_connector::{
+    guild::{Guild, GuildValues},
+    XpStore,
+};
+
+use super::topgg;
+
+pub struct Entitlements;
+
+impl TypeMapKey for Entitlements {
+    type Value = Arc<EntitlementCache>;
+}
+
+pub async fn get(ctx: &Context) -> Arc<EntitlementCache> {
+    let data = ctx.data.read().await;
+
+    data.get::<Entitlements>()
+        .expect("Expected an EntitlementCache in the client data")
+        .clone()
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GuildEntitlement {
+    // the api only knows premium per guild, it unlocks everything labelled premium in `/settings`
+    pub premium: bool,
+    // members can play games without voting
+    pub vote_free: bool,
+}
+
+impl GuildEntitlement {
+    // the guild's game cooldown in seconds, guilds without premium are held to the default
+    pub fn game_cooldown(&self, guild: &Guild) -> i64 {
+        if self.premium {
+            guild.values.gamecooldown
+        } else {
+            GuildValues::default().gamecooldown
+        }
+    }
+
+    pub fn trivia_cooldown(&self, guild: &Guild) -> i64 {
+        if self.premium {
+            guild.values.triviacooldown
+        } else {
+            GuildValues::default().triviacooldown
+        }
+    }
+}
+
+/*
+    Single place the bot asks whether something is paid for.
+    > Guild premium, vote-free guilds, user premium and top.gg votes are resolved here and kept
+    > for `ttl`, so games don't hit the api and top.gg on every use.
+    > Lookups that fail count as not entitled and are not cached, the next call asks again.
+*/
+pub struct EntitlementCache {
+    db: Arc<dyn XpStore>,
+    ttl: Duration,
+    guilds: Mutex<HashMap<u64, (Instant, GuildEntitlement)>>,
+    users: Mutex<HashMap<u64, (Instant, bool)>>,
+    votes: Mutex<HashMap<u64, (Instant, bool)>>,
+}
+
+impl EntitlementCache {
+    pub fn new(db: Arc<dyn XpStore>, ttl: Duration) -> Self {
+        EntitlementCache {
+            db,
+            ttl,
+            guilds: Mutex::new(HashMap::new()),
+            users: Mutex::new(HashMap::new()),
+            votes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn guild(&self, guild_id: u64) -> GuildEntitlement {
+        if let Some(entitlement) = self.cached(&self.guilds, guild_id).await {
+            return entitlement;
+        }
+
+        match self.db.guild_premium(guild_id).await {
+            Ok(premium) => {
+                let entitlement = GuildEntitlement {
+                    premium: premium.premium,
+                    vote_free: premium.voteFree,
+                };
+                self.store(&self.guilds, guild_id, entitlement).await;
+                entitlement
+            }
+            Err(why) => {
+                log::warn!(
+                    "Could not get premium state of guild ({}): {}",
+                    guild_id,
+                    why
+                );
+                GuildEntitlement::default()
+            }
+        }
+    }
+
+    pub async fn premium(&self, guild_id: u64) -> bool {
+        self.guild(guild_id).await.premium
+    }
+
+    pub async fn user_premium(&self, user_id: u64) -> bool {
+        if let Some(premium) = self.cached(&self.users, user_id).await {
+            return premium;
+        }
+
+        match self.db.user_premium(user_id).await {
+            Ok(premium) => {
+                self.store(&self.users, user_id, premium.userPremium).await;
+                premium.userPremium
+            }
+            Err(why) => {
+                log::warn!("Could not get premium state of user ({}): {}", user_id, why);
+                false
+            }
+        }
+    }
+
+    pub async fn voted(&self, user_id: u64) -> bool {
+        if let Some(voted) = self.cached(&self.votes, user_id).await {
+            return voted;
+        }
+
+        match topgg::check_user_vote(&user_id).await {
+            Some(voted) => {
+                self.store(&self.votes, user_id, voted).await;
+                voted
+            }
+            None => false,
+        }
+    }
+
+    // games are free in vote-free guilds and for premium users, everyone else has to vote
+    pub async fn can_play(&self, user_id: u64, guild_id: u64) -> bool {
+        self.guild(guild_id).await.vote_free
+            || self.user_premium(user_id).await
+            || self.voted(user_id).await
+    }
+
+    async fn cached<V: Copy>(
+        &self,
+        cache: &Mutex<HashMap<u64, (Instant, V)>>,
+        id: u64,
+    ) -> Option<V> {
+        match cache.lock().await.get(&id) {
+            Some((loaded_at, value)) if loaded_at.elapsed() < self.ttl => Some(*value),
+            _ => None,
+        }
+    }
+
+    async fn store<V>(&self, cache: &Mutex<HashMap<u64, (Instant, V)>>, id: u64, value: V) {
+        let mut cache = cache.lock().await;
+
+        // drop expired entries while we are here, so the map doesn't grow forever
+        cache.retain(|_, (loaded_at, _)| loaded_at.elapsed() < self.ttl);
+        cache.insert(id, (Instant::now(), value));
+    }
+}
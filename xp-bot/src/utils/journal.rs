@@ -0,0 +1,53 @@
+use std::{sync::Arc, time::Duration};
+
+use serenity::prelude::{Context, TypeMapKey};
+use xp_db_connector::{journal::Journal, XpStore};
+
+pub struct WriteJournal;
+
+impl TypeMapKey for WriteJournal {
+    type Value = Arc<Journal>;
+}
+
+pub async fn get(ctx: &Context) -> Arc<Journal> {
+    let data = ctx.data.read().await;
+
+    data.get::<WriteJournal>()
+        .expect("Expected a Journal in the client data")
+        .clone()
+}
+
+// replays journaled writes until the process exits, the backlog is logged while there is one
+pub async fn run(journal: Arc<Journal>, db: Arc<dyn XpStore>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+
+        if journal.is_empty() {
+            continue;
+        }
+
+        match journal.replay(&db).await {
+            Ok(report) => {
+                for (entry, why) in &report.dropped {
+                    log::error!(
+                        "Dropped journaled write {} ({:?}): {}",
+                        entry.id,
+                        entry.write,
+                        why
+                    );
+                }
+
+                if report.remaining > 0 {
+                    log::warn!(
+                        "Write journal backlog: {} writes waiting ({} replayed)",
+                        report.remaining,
+                        report.applied
+                    );
+                } else {
+                    log::info!("Write journal drained, replayed {} writes", report.applied);
+                }
+            }
+            Err(why) => log::error!("Could not replay write journal: {}", why),
+        }
+    }
+}
@@ -1,6 +1,12 @@
+pub mod batcher;
 pub mod colors;
+pub mod db;
+pub mod entitlements;
+pub mod journal;
 pub mod math;
 pub mod opentdb;
+pub mod rules;
 pub mod topgg;
 pub mod utils;
+pub mod voice;
 pub mod ilum;
@@ -1,3 +1,5 @@
+use tokio::sync::OnceCell;
+
 #[derive(serde::Deserialize, Debug)]
 pub struct OTDBResponse {
     pub response_code: u8,
@@ -14,16 +16,48 @@ pub struct OTDBQuestion {
     pub incorrect_answers: Vec<String>,
 }
 
+#[derive(serde::Deserialize, Debug)]
+struct OTDBCategories {
+    trivia_categories: Vec<OTDBCategory>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct OTDBCategory {
+    pub id: u64,
+    pub name: String,
+}
+
+// categories hardly ever change, they are loaded once and kept until the bot restarts
+static CATEGORIES: OnceCell<Vec<OTDBCategory>> = OnceCell::const_new();
+
 pub struct OpenTriviaDB;
 
 impl OpenTriviaDB {
-    pub async fn get_question() -> Result<OTDBResponse, reqwest::Error> {
+    pub async fn get_question(category: Option<u64>) -> Result<OTDBResponse, reqwest::Error> {
+        let mut url = "https://opentdb.com/api.php?amount=1&type=multiple".to_string();
+        if let Some(category) = category {
+            url.push_str(&format!("&category={}", category));
+        }
+
         let client = reqwest::Client::new();
-        let response = client
-            .get("https://opentdb.com/api.php?amount=1&type=multiple")
-            .send()
-            .await?;
+        let response = client.get(url).send().await?;
         let response_json: OTDBResponse = response.json().await?;
         Ok(response_json)
     }
+
+    pub async fn get_categories() -> Result<&'static [OTDBCategory], reqwest::Error> {
+        let categories = CATEGORIES
+            .get_or_try_init(|| async {
+                let client = reqwest::Client::new();
+                let response = client
+                    .get("https://opentdb.com/api_category.php")
+                    .send()
+                    .await?;
+                let response_json: OTDBCategories = response.json().await?;
+                Ok::<_, reqwest::Error>(response_json.trivia_categories)
+            })
+            .await?;
+
+        Ok(categories)
+    }
 }
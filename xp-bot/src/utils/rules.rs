@@ -0,0 +1,403 @@
+use serenity::{
+    model::prelude::{Channel, ChannelId, ChannelType, GuildChannel, GuildId},
+    prelude::Context,
+};
+use xp_db_connector::guild::Guild;
+
+// what a member earns xp for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XpSource {
+    Message,
+    Reaction,
+    Voice,
+}
+
+/*
+    Where xp is earned, as far as ignores and boosts are concerned.
+    > Threads count as their parent channel, `thread_id` is only set to tell them apart.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub channel_id: u64,
+    pub category_id: Option<u64>,
+    pub thread_id: Option<u64>,
+}
+
+impl Location {
+    pub async fn resolve(ctx: &Context, guild_id: u64, channel_id: u64) -> Option<Location> {
+        let channel = guild_channel(ctx, guild_id, ChannelId(channel_id)).await?;
+        Some(Location::of(ctx, guild_id, &channel).await)
+    }
+
+    pub async fn of(ctx: &Context, guild_id: u64, channel: &GuildChannel) -> Location {
+        if !is_thread(channel.kind) {
+            return Location {
+                channel_id: channel.id.0,
+                category_id: channel.parent_id.map(|parent_id| parent_id.0),
+                thread_id: None,
+            };
+        }
+
+        // the category of a thread is the category of its parent
+        let parent = match channel.parent_id {
+            Some(parent_id) => guild_channel(ctx, guild_id, parent_id).await,
+            None => None,
+        };
+
+        Location {
+            channel_id: parent.as_ref().map_or(channel.id.0, |parent| parent.id.0),
+            category_id: parent
+                .and_then(|parent| parent.parent_id)
+                .map(|category_id| category_id.0),
+            thread_id: Some(channel.id.0),
+        }
+    }
+}
+
+/*
+    Whether a member with `roles` earns `source` xp in `channel_id`.
+    > The one place that answers this for message, reaction and voice xp, so the xp paths
+    > can't drift apart. Covers the modules, ignored or allowed roles, channels and categories
+    > and the afk channel for voice.
+    > Some(location) if they do, for the boosts of that channel and category.
+*/
+pub async fn earns(
+    ctx: &Context,
+    guild: &Guild,
+    guild_id: u64,
+    source: XpSource,
+    channel_id: u64,
+    roles: &[u64],
+) -> Option<Location> {
+    if !enabled(guild, source) {
+        return None;
+    }
+
+    let location = Location::resolve(ctx, guild_id, channel_id).await?;
+    let afk_channel_id = ctx
+        .cache
+        .guild_field(GuildId(guild_id), |guild| guild.afk_channel_id)
+        .flatten()
+        .map(|channel_id| channel_id.0);
+
+    earns_at(guild, source, &location, roles, afk_channel_id).then_some(location)
+}
+
+// `earns` once the location and the guild's afk channel are known
+pub fn earns_at(
+    guild: &Guild,
+    source: XpSource,
+    location: &Location,
+    roles: &[u64],
+    afk_channel_id: Option<u64>,
+) -> bool {
+    if !enabled(guild, source) {
+        return false;
+    }
+
+    if ignores_location(guild, location) || ignores_roles(guild, roles) {
+        return false;
+    }
+
+    let in_afk_channel = afk_channel_id == Some(location.channel_id);
+    !(source == XpSource::Voice && guild.modules.ignoreafk && in_afk_channel)
+}
+
+fn enabled(guild: &Guild, source: XpSource) -> bool {
+    match source {
+        XpSource::Message => guild.modules.messagexp,
+        XpSource::Reaction => guild.modules.reactionxp,
+        XpSource::Voice => guild.modules.voicexp,
+    }
+}
+
+/*
+    Whether the guild's ignore and allow lists keep `location` from earning xp.
+    > Ignored channels and categories never earn xp. In allow-list mode only the allowed ones do,
+    > an allow list without channels or categories leaves them unrestricted, so a guild can
+    > allow just a few roles everywhere.
+*/
+pub fn ignores_location(guild: &Guild, location: &Location) -> bool {
+    let in_lists = |channels: &[String], categories: &[String]| {
+        listed(channels, location.channel_id)
+            || location
+                .category_id
+                .is_some_and(|category_id| listed(categories, category_id))
+    };
+
+    let ignored = &guild.ignored;
+    if in_lists(&ignored.channels, &ignored.categories) {
+        return true;
+    }
+
+    let allowed = &ignored.allowed;
+    let restricted = !allowed.channels.is_empty() || !allowed.categories.is_empty();
+    ignored.allowlist && restricted && !in_lists(&allowed.channels, &allowed.categories)
+}
+
+// the same for a member's roles, in allow-list mode they need one of the allowed roles
+pub fn ignores_roles(guild: &Guild, roles: &[u64]) -> bool {
+    let in_list = |list: &[String]| roles.iter().any(|role_id| listed(list, *role_id));
+
+    let ignored = &guild.ignored;
+    if in_list(&ignored.roles) {
+        return true;
+    }
+
+    let allowed = &ignored.allowed.roles;
+    ignored.allowlist && !allowed.is_empty() && !in_list(allowed)
+}
+
+fn listed(list: &[String], id: u64) -> bool {
+    list.iter().any(|entry| entry.parse() == Ok(id))
+}
+
+/*
+    Whether `level` is past the guild's maximum level, members earn no more xp from there on.
+    > Only with the module on and a maximum set, a maximum of 0 is what new guilds start with.
+*/
+pub fn capped(guild: &Guild, level: i32) -> bool {
+    guild.modules.maximumlevel
+        && guild.values.maximumlevel > 0
+        && level as i64 >= guild.values.maximumlevel
+}
+
+// threads are not part of the channel cache, they are looked up in the guild or fetched
+pub async fn guild_channel(
+    ctx: &Context,
+    guild_id: u64,
+    channel_id: ChannelId,
+) -> Option<GuildChannel> {
+    if let Some(channel) = ctx.cache.guild_channel(channel_id) {
+        return Some(channel);
+    }
+
+    let thread = ctx
+        .cache
+        .guild_field(guild_id, |guild| {
+            guild
+                .threads
+                .iter()
+                .find(|thread| thread.id == channel_id)
+                .cloned()
+        })
+        .flatten();
+    if thread.is_some() {
+        return thread;
+    }
+
+    match channel_id.to_channel(ctx).await {
+        Ok(Channel::Guild(channel)) => Some(channel),
+        Ok(_) => None,
+        Err(why) => {
+            log::warn!("Could not get channel ({}): {}", channel_id, why);
+            None
+        }
+    }
+}
+
+pub fn is_thread(kind: ChannelType) -> bool {
+    matches!(
+        kind,
+        ChannelType::PublicThread | ChannelType::PrivateThread | ChannelType::NewsThread
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use xp_db_connector::guild::Guild;
+
+    use super::{capped, earns_at, Location, XpSource};
+
+    const CHANNEL: u64 = 10;
+    const OTHER_CHANNEL: u64 = 11;
+    const CATEGORY: u64 = 20;
+    const ROLE: u64 = 30;
+    const OTHER_ROLE: u64 = 31;
+    const AFK_CHANNEL: u64 = 40;
+
+    fn location(channel_id: u64) -> Location {
+        Location {
+            channel_id,
+            category_id: Some(CATEGORY),
+            thread_id: None,
+        }
+    }
+
+    fn earns(guild: &Guild, channel_id: u64, roles: &[u64]) -> bool {
+        earns_at(guild, XpSource::Message, &location(channel_id), roles, None)
+    }
+
+    fn ids(ids: &[u64]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn default_guild_is_not_capped() {
+        assert!(!capped(&Guild::default(), 0));
+        assert!(!capped(&Guild::default(), 1000));
+    }
+
+    #[test]
+    fn maximum_level_caps_with_the_module_on() {
+        let mut guild = Guild::default();
+        guild.values.maximumlevel = 10;
+        assert!(!capped(&guild, 10));
+
+        guild.modules.maximumlevel = true;
+        assert!(!capped(&guild, 9));
+        assert!(capped(&guild, 10));
+        assert!(capped(&guild, 11));
+    }
+
+    #[test]
+    fn maximum_of_zero_does_not_cap() {
+        let mut guild = Guild::default();
+        guild.modules.maximumlevel = true;
+
+        assert!(!capped(&guild, 0));
+        assert!(!capped(&guild, 1000));
+    }
+
+    #[test]
+    fn default_guild_earns_everywhere() {
+        let guild = Guild::default();
+
+        for source in [XpSource::Message, XpSource::Reaction, XpSource::Voice] {
+            assert!(earns_at(&guild, source, &location(CHANNEL), &[], None));
+        }
+    }
+
+    #[test]
+    fn disabled_module_earns_nothing() {
+        let mut guild = Guild::default();
+        guild.modules.reactionxp = false;
+
+        assert!(!earns_at(
+            &guild,
+            XpSource::Reaction,
+            &location(CHANNEL),
+            &[],
+            None
+        ));
+        assert!(earns_at(
+            &guild,
+            XpSource::Message,
+            &location(CHANNEL),
+            &[],
+            None
+        ));
+    }
+
+    #[test]
+    fn deny_list_ignores_listed_roles_channels_and_categories() {
+        let mut guild = Guild::default();
+        guild.ignored.channels = ids(&[CHANNEL]);
+        guild.ignored.roles = ids(&[ROLE]);
+
+        assert!(!earns(&guild, CHANNEL, &[]));
+        assert!(earns(&guild, OTHER_CHANNEL, &[]));
+        assert!(!earns(&guild, OTHER_CHANNEL, &[OTHER_ROLE, ROLE]));
+        assert!(earns(&guild, OTHER_CHANNEL, &[OTHER_ROLE]));
+
+        guild.ignored.categories = ids(&[CATEGORY]);
+        assert!(!earns(&guild, OTHER_CHANNEL, &[]));
+    }
+
+    #[test]
+    fn threads_count_as_their_parent() {
+        let mut guild = Guild::default();
+        guild.ignored.channels = ids(&[CHANNEL]);
+
+        let thread = Location {
+            thread_id: Some(99),
+            ..location(CHANNEL)
+        };
+        assert!(!earns_at(&guild, XpSource::Message, &thread, &[], None));
+    }
+
+    #[test]
+    fn allow_list_only_earns_in_allowed_places() {
+        let mut guild = Guild::default();
+        guild.ignored.allowlist = true;
+        guild.ignored.allowed.channels = ids(&[CHANNEL]);
+        guild.ignored.allowed.roles = ids(&[ROLE]);
+
+        assert!(earns(&guild, CHANNEL, &[OTHER_ROLE, ROLE]));
+        assert!(!earns(&guild, CHANNEL, &[OTHER_ROLE]));
+        assert!(!earns(&guild, OTHER_CHANNEL, &[ROLE]));
+
+        guild.ignored.allowed.channels.clear();
+        guild.ignored.allowed.categories = ids(&[CATEGORY]);
+        assert!(earns(&guild, OTHER_CHANNEL, &[ROLE]));
+    }
+
+    #[test]
+    fn empty_allow_lists_do_not_restrict() {
+        let mut guild = Guild::default();
+        guild.ignored.allowlist = true;
+        assert!(earns(&guild, CHANNEL, &[]));
+
+        // allowing roles only leaves every channel open to them
+        guild.ignored.allowed.roles = ids(&[ROLE]);
+        assert!(earns(&guild, CHANNEL, &[ROLE]));
+        assert!(earns(&guild, OTHER_CHANNEL, &[ROLE]));
+    }
+
+    #[test]
+    fn ignores_still_apply_in_allow_list_mode() {
+        let mut guild = Guild::default();
+        guild.ignored.channels = ids(&[CHANNEL]);
+        guild.ignored.allowlist = true;
+        guild.ignored.allowed.channels = ids(&[CHANNEL, OTHER_CHANNEL]);
+
+        assert!(!earns(&guild, CHANNEL, &[]));
+        assert!(earns(&guild, OTHER_CHANNEL, &[]));
+    }
+
+    #[test]
+    fn switching_modes_keeps_the_ignore_lists_ignored() {
+        let mut guild = Guild::default();
+        guild.ignored.channels = ids(&[CHANNEL]);
+        guild.ignored.allowed.channels = ids(&[OTHER_CHANNEL]);
+
+        // allowed channels don't matter with the mode off
+        assert!(!earns(&guild, CHANNEL, &[]));
+        assert!(earns(&guild, 12, &[]));
+
+        guild.ignored.allowlist = true;
+        assert!(!earns(&guild, CHANNEL, &[]));
+        assert!(!earns(&guild, 12, &[]));
+        assert!(earns(&guild, OTHER_CHANNEL, &[]));
+    }
+
+    #[test]
+    fn afk_channel_earns_no_voice_xp() {
+        let mut guild = Guild::default();
+        let afk = location(AFK_CHANNEL);
+
+        assert!(!earns_at(
+            &guild,
+            XpSource::Voice,
+            &afk,
+            &[],
+            Some(AFK_CHANNEL)
+        ));
+        assert!(earns_at(
+            &guild,
+            XpSource::Message,
+            &afk,
+            &[],
+            Some(AFK_CHANNEL)
+        ));
+
+        guild.modules.ignoreafk = false;
+        assert!(earns_at(
+            &guild,
+            XpSource::Voice,
+            &afk,
+            &[],
+            Some(AFK_CHANNEL)
+        ));
+    }
+}
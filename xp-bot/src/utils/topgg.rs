@@ -1,11 +1,12 @@
 use serde_json::json;
 
-pub async fn check_user_vote(user_id: &u64) -> bool {
+// None if top.gg could not be asked, so the answer is not mistaken for "has not voted"
+pub async fn check_user_vote(user_id: &u64) -> Option<bool> {
     let client = reqwest::Client::new();
 
     let topgg_token = dotenv::var("TOPGG_TOKEN").expect("Expected a token in the environment");
 
-    let response = client
+    let response = match client
         .get(format!(
             "https://top.gg/api/bots/{}/check?userId={}",
             "706935674800177193", user_id
@@ -13,18 +14,26 @@ pub async fn check_user_vote(user_id: &u64) -> bool {
         .header("Authorization", topgg_token)
         .send()
         .await
-        .unwrap();
-
-    if response.status().is_success() {
-        let body = response.text().await.unwrap();
-        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
-
-        if json["voted"].as_i64() == Some(1) {
-            return true;
+    {
+        Ok(response) => response,
+        Err(why) => {
+            log::warn!("Could not check vote of user ({}): {}", user_id, why);
+            return None;
         }
+    };
+
+    if !response.status().is_success() {
+        log::warn!(
+            "Could not check vote of user ({}): status {}",
+            user_id,
+            response.status()
+        );
+        return None;
     }
 
-    false
+    let json = response.json::<serde_json::Value>().await.ok()?;
+
+    Some(json["voted"].as_i64() == Some(1))
 }
 
 pub async fn post_bot_stats(shard_id: u64, guild_count: usize, shard_count: u64) {
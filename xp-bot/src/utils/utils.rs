@@ -1,52 +1,13 @@
+use std::borrow::Cow;
+
 use rand::Rng;
 use serenity::{
-    builder::CreateMessage,
-    model::prelude::{ChannelId, RoleId},
-};
-use xp_db_connector::{
-    guild::Guild, guild_member::GuildMember, guild_premium::GuildPremium, user::User,
+    builder::{CreateEmbed, CreateMessage},
+    model::prelude::{AttachmentType, ChannelId, UserId},
 };
+use xp_db_connector::{guild::Guild, guild_member::GuildMember, snapshot::GuildSnapshot, DbError, XpStore};
 
-use super::{colors, topgg};
-
-pub fn calculate_total_boost_percentage(
-    guild: Guild,
-    role_ids: &Vec<RoleId>,
-    channel_id: u64,
-    category_id: Option<ChannelId>,
-) -> f32 {
-    let mut boost_percentage = 0.0;
-
-    let role_boosts = guild.boosts.roles;
-    let channel_boosts = guild.boosts.channels;
-    let category_boosts = guild.boosts.categories;
-
-    for boost in &role_boosts {
-        if role_ids.contains(&boost.id.parse::<RoleId>().unwrap()) {
-            boost_percentage += boost.percentage as f32 / 100 as f32;
-        }
-    }
-
-    for boost in &channel_boosts {
-        if boost.id.parse::<u64>().unwrap() == channel_id {
-            boost_percentage += boost.percentage as f32 / 100 as f32;
-        }
-    }
-
-    if let Some(category_id) = category_id {
-        if category_boosts.is_none() {
-            return boost_percentage;
-        }
-
-        for boost in &category_boosts.unwrap() {
-            if boost.id.parse::<u64>().unwrap() == category_id.0 {
-                boost_percentage += boost.percentage as f32 / 100 as f32;
-            }
-        }
-    }
-
-    boost_percentage
-}
+use super::{colors, entitlements};
 
 pub fn calculate_total_boost_percentage_by_ids(
     guild: Guild,
@@ -73,11 +34,7 @@ pub fn calculate_total_boost_percentage_by_ids(
     }
 
     if let Some(category_id) = category_id {
-        if category_boosts.is_none() {
-            return boost_percentage;
-        }
-
-        for boost in &category_boosts.unwrap() {
+        for boost in &category_boosts {
             if boost.id.parse::<u64>().unwrap() == category_id {
                 boost_percentage += boost.percentage as f32 / 100 as f32;
             }
@@ -106,23 +63,76 @@ pub fn format_number(number: i64) -> String {
     formatted_number.chars().rev().collect::<String>()
 }
 
-pub async fn eligibility_helper(user_id: u64, guild_id: &u64) -> bool {
-    let guild = GuildPremium::from_id(guild_id.to_owned()).await.unwrap();
-    if guild.voteFree {
-        return true;
-    }
+// red embed for a write the api refused, so nobody gets a success message for nothing
+pub fn db_error_embed<'a>(
+    embed: &'a mut CreateEmbed,
+    action: &str,
+    why: &DbError,
+) -> &'a mut CreateEmbed {
+    embed.description(format!("{}\n`{}`", action, why));
+    embed.color(colors::red());
+    embed
+}
 
-    let user = User::is_premium(user_id).await.unwrap_or(false);
-    if user {
-        return true;
-    }
+/*
+    Sends the guild as a snapshot file to `user_id`, before something is thrown away.
+    > A direct message, so the backup doesn't vanish like an ephemeral reply and isn't shown
+    > to the whole channel.
+    > Err(reason) if the backup could not be taken or did not arrive, callers must not go on.
+*/
+pub async fn send_backup(
+    ctx: &serenity::client::Context,
+    db: &dyn XpStore,
+    guild_id: u64,
+    user_id: UserId,
+    before: &str,
+) -> Result<(), String> {
+    let backup = GuildSnapshot::capture(db, guild_id)
+        .await
+        .and_then(|snapshot| snapshot.to_json().map(|data| (snapshot, data)));
+    let (snapshot, data) = match backup {
+        Ok(backup) => backup,
+        Err(why) => return Err(format!("Could not back up the community.\n`{}`", why)),
+    };
 
-    let voted = topgg::check_user_vote(&user_id).await;
-    if voted {
-        return true;
+    let file = AttachmentType::Bytes {
+        data: Cow::Owned(data),
+        filename: snapshot.file_name(),
+    };
+
+    let sent = match user_id.create_dm_channel(&ctx.http).await {
+        Ok(channel) => {
+            channel
+                .send_message(&ctx.http, |message| {
+                    message
+                        .embed(|embed| {
+                            embed.description(format!(
+                                "Backup of the community `{}`, taken before {}.\nIt can be loaded again with `/restore` on that server.",
+                                guild_id, before
+                            ));
+                            embed.color(colors::blue())
+                        })
+                        .add_file(file)
+                })
+                .await
+        }
+        Err(why) => Err(why),
+    };
+
+    if let Err(why) = sent {
+        log::warn!(
+            "Could not send backup of guild ({}) to user ({}): {}",
+            guild_id,
+            user_id,
+            why
+        );
+        return Err(
+            "Could not send you the backup. Allow direct messages from members of this server and try again."
+                .to_string(),
+        );
     }
 
-    false
+    Ok(())
 }
 
 pub fn is_cooldowned(timestamp_now: u64, timestamp_then: u64, cooldown: u64) -> bool {
@@ -193,7 +203,11 @@ pub async fn handle_level_roles(
 
     log::debug!("Roles to add: {:?}", roles_to_add);
 
-    let remove_reached_roles = guild.modules.removereachedlevelroles;
+    let remove_reached_roles = guild.modules.removereachedlevelroles
+        && entitlements::get(ctx)
+            .await
+            .premium(guild_id)
+            .await;
     let single_rank_role = guild.modules.singlerankrole;
 
     if remove_reached_roles || single_rank_role {
@@ -0,0 +1,791 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use serenity::{
+    model::{
+        prelude::{ChannelId, GuildId, UserId},
+        voice::VoiceState,
+    },
+    prelude::{Context, TypeMapKey},
+};
+use tokio::sync::Mutex;
+use xp_db_connector::{
+    guild::Guild,
+    guild_member::{GuildMember, XpReason},
+    journal::XpWrite,
+};
+
+use super::{
+    batcher, db, journal,
+    math::calculate_level,
+    rules::{self, XpSource},
+    utils::{self, conform_xpc, handle_level_roles, send_level_up},
+};
+
+pub struct Voice;
+
+impl TypeMapKey for Voice {
+    type Value = Arc<VoiceSessions>;
+}
+
+pub async fn get(ctx: &Context) -> Arc<VoiceSessions> {
+    let data = ctx.data.read().await;
+
+    data.get::<Voice>()
+        .expect("Expected VoiceSessions in the client data")
+        .clone()
+}
+
+// time a member has spent in one voice channel of one guild
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoiceSession {
+    pub channel_id: u64,
+    // in milliseconds, like the member timestamps
+    pub started_at: u64,
+    // seconds past the join cooldown that have already been paid out
+    pub paid: u64,
+    // xp paid out so far and the levels it went from and to
+    pub xp: u64,
+    pub levels: Option<(i32, i32)>,
+    // milliseconds the member was eligible for xp before `eligible_since`
+    pub banked: u64,
+    // set while the voice rules let the member earn xp
+    pub eligible_since: Option<u64>,
+    pub muted_since: Option<u64>,
+}
+
+impl VoiceSession {
+    // in seconds
+    pub fn duration(&self, now: u64) -> u64 {
+        now.saturating_sub(self.started_at) / 1000
+    }
+
+    // seconds the member was eligible for xp, members muted for too long stop counting
+    pub fn eligible(&self, now: u64, rules: &VoiceRules) -> u64 {
+        self.eligible_millis(now, rules) / 1000
+    }
+
+    fn eligible_millis(&self, now: u64, rules: &VoiceRules) -> u64 {
+        let current = match self.eligible_since {
+            Some(since) => {
+                let until = match (self.muted_since, rules.afk_after) {
+                    (Some(muted_since), Some(afk_after)) => {
+                        now.min((muted_since + afk_after).max(since))
+                    }
+                    _ => now,
+                };
+                until.saturating_sub(since)
+            }
+            None => 0,
+        };
+
+        self.banked + current
+    }
+
+    // eligible seconds past the join cooldown that haven't been paid out yet
+    pub fn unpaid(&self, now: u64, rules: &VoiceRules) -> u64 {
+        self.eligible(now, rules)
+            .saturating_sub(rules.join_cooldown)
+            .saturating_sub(self.paid)
+    }
+
+    fn update(&mut self, eligible: bool, self_muted: bool, now: u64, rules: &VoiceRules) {
+        // what was earned under the old state is kept, counting goes on under the new one
+        self.banked = self.eligible_millis(now, rules);
+
+        self.muted_since = match (self_muted, self.muted_since) {
+            (true, Some(muted_since)) => Some(muted_since),
+            (true, None) => Some(now),
+            (false, _) => None,
+        };
+        self.eligible_since = if eligible { Some(now) } else { None };
+    }
+}
+
+/*
+    When members in voice earn xp, from the guild's settings.
+    > Checked again whenever someone in the channel joins, leaves, mutes or deafens, only
+    > the time in between that passed the rules counts.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoiceRules {
+    // seconds at the start of a session that don't count
+    pub join_cooldown: u64,
+    // unmuted members a channel needs, bots are not counted
+    pub min_members: usize,
+    pub ignore_deafened: bool,
+    pub ignore_muted: bool,
+    // milliseconds of self-mute after which a member counts as afk
+    pub afk_after: Option<u64>,
+}
+
+impl VoiceRules {
+    pub fn new(guild: &Guild) -> Self {
+        VoiceRules {
+            join_cooldown: guild.values.voicejoincooldown.max(0) as u64,
+            min_members: guild.values.voiceminmembers.max(0) as usize,
+            ignore_deafened: guild.modules.voiceignoredeafened,
+            ignore_muted: guild.modules.voiceignoremuted,
+            afk_after: match guild.values.voicemuteafk {
+                minutes if minutes > 0 => Some(minutes as u64 * 60 * 1000),
+                _ => None,
+            },
+        }
+    }
+
+    // whether the member behind `state` earns xp in a channel with `unmuted` unmuted members
+    pub fn allows(&self, state: &VoiceState, unmuted: usize) -> bool {
+        if self.ignore_deafened && state.self_deaf {
+            return false;
+        }
+
+        if self.ignore_muted && (state.mute || state.deaf) {
+            return false;
+        }
+
+        unmuted >= self.min_members
+    }
+}
+
+// xp paid out for a stretch of a session
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Payout {
+    pub xp: u64,
+    pub old_level: i32,
+    pub new_level: i32,
+}
+
+/*
+    Voice sessions of all members the bot can see, kept per guild.
+    > A member can only be in one voice channel of a guild at a time, so a session is keyed
+    > by guild and member and remembers its channel. Being in voice on another server
+    > is a separate session.
+    > Sessions only end with the channel they were started in, so a leave that arrives after
+    > the member already moved on doesn't cut the new session short.
+    > Xp is paid out every full minute by `run` and the rest when the session ends, so a
+    > restart only loses the minute that was in progress.
+*/
+#[derive(Default)]
+pub struct VoiceSessions {
+    sessions: Mutex<HashMap<(u64, u64), VoiceSession>>,
+    ticking: AtomicBool,
+}
+
+impl VoiceSessions {
+    pub fn new() -> Self {
+        VoiceSessions::default()
+    }
+
+    // starts a session in `channel_id`, replacing whatever was left of an earlier one
+    pub async fn start(&self, guild_id: u64, user_id: u64, channel_id: u64, now: u64) {
+        if let Some(previous) = self
+            .sessions
+            .lock()
+            .await
+            .insert((guild_id, user_id), new_session(channel_id, now))
+        {
+            if previous.channel_id != channel_id {
+                log::warn!(
+                    "Voice session of member ({}) of guild ({}) in channel ({}) was never ended",
+                    user_id,
+                    guild_id,
+                    previous.channel_id
+                );
+            }
+        }
+    }
+
+    // the session in `channel_id`, None if the member is not in voice or in another channel
+    pub async fn end(&self, guild_id: u64, user_id: u64, channel_id: u64) -> Option<VoiceSession> {
+        let mut sessions = self.sessions.lock().await;
+
+        match sessions.get(&(guild_id, user_id)) {
+            Some(session) if session.channel_id == channel_id => {
+                sessions.remove(&(guild_id, user_id))
+            }
+            _ => None,
+        }
+    }
+
+    pub async fn get(&self, guild_id: u64, user_id: u64) -> Option<VoiceSession> {
+        self.sessions
+            .lock()
+            .await
+            .get(&(guild_id, user_id))
+            .copied()
+    }
+
+    /*
+        Brings the sessions of a guild in line with who is in voice right now.
+        > Used after the bot (re)connected, leaves and moves that happened in the meantime
+        > were never received. Sessions that still match are kept as they are, everyone else
+        > starts a new one, so members that were in voice keep earning from here on.
+        > `states` are (member, channel) pairs.
+    */
+    pub async fn recover(&self, guild_id: u64, states: &[(u64, u64)], now: u64) -> usize {
+        let mut sessions = self.sessions.lock().await;
+
+        sessions.retain(|(guild, user), session| {
+            *guild != guild_id || states.contains(&(*user, session.channel_id))
+        });
+
+        let mut started = 0;
+        for (user_id, channel_id) in states {
+            sessions.entry((guild_id, *user_id)).or_insert_with(|| {
+                started += 1;
+                new_session(*channel_id, now)
+            });
+        }
+
+        started
+    }
+
+    // applies the outcome of the voice rules to the session of the member behind `state`
+    pub async fn update(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        state: &VoiceState,
+        eligible: bool,
+        now: u64,
+        rules: &VoiceRules,
+    ) {
+        let key = (guild_id, state.user_id.0);
+        if let Some(session) = self.sessions.lock().await.get_mut(&key) {
+            if session.channel_id == channel_id {
+                session.update(eligible, state.self_mute, now, rules);
+            }
+        }
+    }
+
+    /*
+        Marks the full minutes of a session that are due as paid and returns them in seconds.
+        > Claimed before the xp is written, so a leave that comes in at the same time can't
+        > pay for the same minutes again.
+    */
+    async fn claim(
+        &self,
+        guild_id: u64,
+        user_id: u64,
+        channel_id: u64,
+        now: u64,
+        rules: &VoiceRules,
+    ) -> u64 {
+        match self.sessions.lock().await.get_mut(&(guild_id, user_id)) {
+            Some(session) if session.channel_id == channel_id => {
+                let due = session.unpaid(now, rules) / 60 * 60;
+                session.paid += due;
+                due
+            }
+            _ => 0,
+        }
+    }
+
+    async fn record(&self, guild_id: u64, user_id: u64, channel_id: u64, payout: Payout) {
+        if let Some(session) = self.sessions.lock().await.get_mut(&(guild_id, user_id)) {
+            if session.channel_id == channel_id {
+                session.xp += payout.xp;
+                session.levels = Some(merge_levels(session.levels, payout));
+            }
+        }
+    }
+
+    // true for the first caller only, the tick runs once for all shards
+    pub fn start_ticking(&self) -> bool {
+        !self.ticking.swap(true, Ordering::SeqCst)
+    }
+
+    async fn active(&self) -> Vec<((u64, u64), VoiceSession)> {
+        self.sessions
+            .lock()
+            .await
+            .iter()
+            .map(|(key, session)| (*key, *session))
+            .collect()
+    }
+}
+
+fn new_session(channel_id: u64, now: u64) -> VoiceSession {
+    VoiceSession {
+        channel_id,
+        started_at: now,
+        paid: 0,
+        xp: 0,
+        levels: None,
+        banked: 0,
+        // nothing counts until the voice rules have been checked
+        eligible_since: None,
+        muted_since: None,
+    }
+}
+
+// the levels of a whole session, from the first payout to the last
+pub fn merge_levels(levels: Option<(i32, i32)>, payout: Payout) -> (i32, i32) {
+    match levels {
+        Some((old_level, _)) => (old_level, payout.new_level),
+        None => (payout.old_level, payout.new_level),
+    }
+}
+
+// pays out the full minutes of all sessions every `interval`
+pub async fn run(ctx: Context, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let sessions = get(&ctx).await;
+        let now = chrono::Utc::now().timestamp() as u64 * 1000;
+        let db = db::client(&ctx).await;
+
+        for ((guild_id, user_id), session) in sessions.active().await {
+            let guild = match Guild::from_id(&db, guild_id).await {
+                Ok(guild) => guild,
+                Err(why) => {
+                    log::error!("Could not get guild ({}) from database: {}", guild_id, why);
+                    continue;
+                }
+            };
+
+            let rules = VoiceRules::new(&guild);
+            let seconds = sessions
+                .claim(guild_id, user_id, session.channel_id, now, &rules)
+                .await;
+            if seconds == 0 {
+                continue;
+            }
+
+            if let Some(payout) =
+                pay(&ctx, &guild, guild_id, user_id, session.channel_id, seconds).await
+            {
+                sessions
+                    .record(guild_id, user_id, session.channel_id, payout)
+                    .await;
+            }
+        }
+    }
+}
+
+// restarts the sessions of a guild from its voice states and checks the voice rules for them
+pub async fn recover(ctx: &Context, guild_id: u64, states: &HashMap<UserId, VoiceState>) -> usize {
+    let in_voice = states
+        .values()
+        .filter_map(|state| {
+            state
+                .channel_id
+                .map(|channel_id| (state.user_id.0, channel_id.0))
+        })
+        .collect::<Vec<(u64, u64)>>();
+
+    let now = chrono::Utc::now().timestamp() as u64 * 1000;
+    let started = get(ctx).await.recover(guild_id, &in_voice, now).await;
+    if in_voice.is_empty() {
+        return started;
+    }
+
+    let guild = match Guild::from_id(&db::client(ctx).await, guild_id).await {
+        Ok(guild) => guild,
+        Err(why) => {
+            log::error!("Could not get guild ({}) from database: {}", guild_id, why);
+            return started;
+        }
+    };
+
+    let mut channels = in_voice
+        .iter()
+        .map(|(_, channel_id)| *channel_id)
+        .collect::<Vec<u64>>();
+    channels.sort_unstable();
+    channels.dedup();
+    for channel_id in channels {
+        evaluate(ctx, &guild, guild_id, channel_id).await;
+    }
+
+    started
+}
+
+/*
+    Checks the voice rules for everyone in `channel_id` and updates their sessions.
+    > Called after every voice state change in the channel, the cache already holds the new state.
+*/
+pub async fn evaluate(ctx: &Context, guild: &Guild, guild_id: u64, channel_id: u64) {
+    let states = ctx
+        .cache
+        .guild_field(GuildId(guild_id), |guild| {
+            guild
+                .voice_states
+                .values()
+                .filter(|state| state.channel_id == Some(ChannelId(channel_id)))
+                .cloned()
+                .collect::<Vec<VoiceState>>()
+        })
+        .unwrap_or_default();
+
+    let unmuted = states
+        .iter()
+        .filter(|state| !(is_bot(ctx, state) || state.self_mute || state.mute))
+        .count();
+
+    let sessions = get(ctx).await;
+    let rules = VoiceRules::new(guild);
+    let now = chrono::Utc::now().timestamp() as u64 * 1000;
+
+    for state in states {
+        let eligible = !is_bot(ctx, &state) && rules.allows(&state, unmuted);
+        sessions
+            .update(guild_id, channel_id, &state, eligible, now, &rules)
+            .await;
+    }
+}
+
+pub fn member_roles(ctx: &Context, guild_id: u64, user_id: u64) -> Vec<u64> {
+    ctx.cache
+        .member_field(GuildId(guild_id), user_id, |member| {
+            member.roles.iter().map(|role| role.0).collect::<Vec<u64>>()
+        })
+        .unwrap_or_default()
+}
+
+fn is_bot(ctx: &Context, state: &VoiceState) -> bool {
+    match &state.member {
+        Some(member) => member.user.bot,
+        None => ctx.cache.user(state.user_id).is_some_and(|user| user.bot),
+    }
+}
+
+// xp for `seconds` in voice, `voicexp` is paid per minute
+pub fn voice_xp(guild: &Guild, seconds: u64, boost_percentage: f32) -> u64 {
+    ((guild.values.voicexp as f32 * (seconds as f32 / 60.)) * (boost_percentage + 1.0)) as u64
+}
+
+/*
+    Adds the xp for `seconds` in `channel_id` and announces level ups.
+    > None if the member doesn't earn voice xp there or could not be loaded.
+*/
+pub async fn pay(
+    ctx: &Context,
+    guild: &Guild,
+    guild_id: u64,
+    user_id: u64,
+    channel_id: u64,
+    seconds: u64,
+) -> Option<Payout> {
+    let roles = member_roles(ctx, guild_id, user_id);
+    let location = rules::earns(ctx, guild, guild_id, XpSource::Voice, channel_id, &roles).await?;
+
+    let db = db::client(ctx).await;
+    let member = match GuildMember::from_id(&db, guild_id, user_id).await {
+        Ok(member) => member,
+        Err(why) => {
+            log::error!(
+                "Could not get member ({}) of guild ({}) from database: {}",
+                user_id,
+                guild_id,
+                why
+            );
+            return None;
+        }
+    };
+
+    let boost_percentage = utils::calculate_total_boost_percentage_by_ids(
+        guild.clone(),
+        roles,
+        location.channel_id,
+        location.category_id,
+    );
+
+    let xp = voice_xp(guild, seconds, boost_percentage);
+
+    let current_level = calculate_level(&member.xp);
+    let new_level = calculate_level(&(member.xp + xp));
+
+    let member = conform_xpc(member, ctx, &guild_id, &user_id).await;
+
+    let (old_level, new_level) = if !rules::capped(guild, new_level) {
+        let write = XpWrite::AddXp {
+            guild_id,
+            member_id: user_id,
+            delta: xp as i64,
+            reason: XpReason::Voice,
+            user_data: Some(member.userData.clone()),
+        };
+        match journal::get(ctx).await.write(&db, write).await {
+            Ok(Some(increment)) => (increment.oldLevel, increment.newLevel),
+            // journaled, the xp is added once the api is back
+            Ok(None) => (current_level, new_level),
+            Err(why) => {
+                log::error!(
+                    "Could not add xp to member ({}) of guild ({}): {}",
+                    user_id,
+                    guild_id,
+                    why
+                );
+                return None;
+            }
+        }
+    } else {
+        // capped at the maximum level
+        return Some(Payout {
+            xp: 0,
+            old_level: current_level,
+            new_level: current_level,
+        });
+    };
+    batcher::get(ctx).await.forget(guild_id, user_id).await;
+
+    // check if user leveled up, dont send if user is incognito
+    if new_level > old_level {
+        handle_level_roles(guild, &user_id, &new_level, ctx, guild_id).await;
+
+        if !member.settings.incognito.unwrap_or(false) {
+            match ctx.http.get_user(user_id).await {
+                Ok(user) => {
+                    send_level_up(
+                        guild.clone(),
+                        user_id,
+                        old_level,
+                        new_level,
+                        ctx,
+                        channel_id,
+                        &user.name,
+                    )
+                    .await
+                }
+                Err(why) => log::warn!("Could not get user ({}): {}", user_id, why),
+            }
+        }
+    }
+
+    Some(Payout {
+        xp,
+        old_level,
+        new_level,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{new_session, VoiceRules, VoiceSessions};
+
+    const GUILD: u64 = 1;
+    const MEMBER: u64 = 2;
+    const CHANNEL: u64 = 3;
+    const OTHER_CHANNEL: u64 = 4;
+
+    const SECOND: u64 = 1000;
+    const MINUTE: u64 = 60 * SECOND;
+
+    fn rules() -> VoiceRules {
+        VoiceRules {
+            join_cooldown: 0,
+            min_members: 0,
+            ignore_deafened: false,
+            ignore_muted: false,
+            afk_after: None,
+        }
+    }
+
+    // a session in CHANNEL the voice rules let earn from `now` on
+    async fn sessions(now: u64, rules: &VoiceRules) -> VoiceSessions {
+        let sessions = VoiceSessions::new();
+        sessions.start(GUILD, MEMBER, CHANNEL, now).await;
+        sessions
+            .sessions
+            .lock()
+            .await
+            .get_mut(&(GUILD, MEMBER))
+            .unwrap()
+            .update(true, false, now, rules);
+
+        sessions
+    }
+
+    #[tokio::test]
+    async fn claims_pay_full_minutes_once() {
+        let rules = rules();
+        let sessions = sessions(0, &rules).await;
+
+        assert_eq!(
+            sessions
+                .claim(GUILD, MEMBER, CHANNEL, 150 * SECOND, &rules)
+                .await,
+            120
+        );
+        assert_eq!(
+            sessions
+                .claim(GUILD, MEMBER, CHANNEL, 170 * SECOND, &rules)
+                .await,
+            0
+        );
+        assert_eq!(
+            sessions
+                .claim(GUILD, MEMBER, CHANNEL, 3 * MINUTE, &rules)
+                .await,
+            60
+        );
+        // the rest is paid when the session ends
+        let session = sessions.end(GUILD, MEMBER, CHANNEL).await.unwrap();
+        assert_eq!(session.unpaid(200 * SECOND, &rules), 20);
+    }
+
+    #[tokio::test]
+    async fn claims_skip_the_join_cooldown() {
+        let rules = VoiceRules {
+            join_cooldown: 30,
+            ..rules()
+        };
+        let sessions = sessions(0, &rules).await;
+
+        assert_eq!(
+            sessions.claim(GUILD, MEMBER, CHANNEL, MINUTE, &rules).await,
+            0
+        );
+        assert_eq!(
+            sessions
+                .claim(GUILD, MEMBER, CHANNEL, 90 * SECOND, &rules)
+                .await,
+            60
+        );
+    }
+
+    #[tokio::test]
+    async fn claims_for_another_channel_pay_nothing() {
+        let rules = rules();
+        let sessions = sessions(0, &rules).await;
+
+        assert_eq!(
+            sessions
+                .claim(GUILD, MEMBER, OTHER_CHANNEL, 5 * MINUTE, &rules)
+                .await,
+            0
+        );
+        assert_eq!(sessions.get(GUILD, MEMBER).await.unwrap().paid, 0);
+    }
+
+    #[tokio::test]
+    async fn late_leaves_do_not_end_the_new_session() {
+        let sessions = VoiceSessions::new();
+        sessions.start(GUILD, MEMBER, CHANNEL, 0).await;
+        sessions.start(GUILD, MEMBER, OTHER_CHANNEL, MINUTE).await;
+
+        assert_eq!(sessions.end(GUILD, MEMBER, CHANNEL).await, None);
+        assert_eq!(
+            sessions.end(GUILD, MEMBER, OTHER_CHANNEL).await,
+            Some(new_session(OTHER_CHANNEL, MINUTE))
+        );
+    }
+
+    #[tokio::test]
+    async fn recover_keeps_matching_sessions() {
+        let rules = rules();
+        let sessions = sessions(0, &rules).await;
+        sessions.start(GUILD, 5, CHANNEL, 0).await;
+        sessions.start(GUILD + 1, MEMBER, CHANNEL, 0).await;
+
+        // MEMBER is still in CHANNEL, 5 left and 6 joined while the bot was away
+        let started = sessions
+            .recover(GUILD, &[(MEMBER, CHANNEL), (6, OTHER_CHANNEL)], MINUTE)
+            .await;
+
+        assert_eq!(started, 1);
+        assert_eq!(
+            sessions
+                .get(GUILD, MEMBER)
+                .await
+                .map(|session| session.started_at),
+            Some(0)
+        );
+        assert_eq!(sessions.get(GUILD, 5).await, None);
+        assert_eq!(
+            sessions.get(GUILD, 6).await,
+            Some(new_session(OTHER_CHANNEL, MINUTE))
+        );
+        // other guilds are left alone
+        assert!(sessions.get(GUILD + 1, MEMBER).await.is_some());
+    }
+
+    #[test]
+    fn nothing_counts_before_the_rules_were_checked() {
+        let session = new_session(CHANNEL, 0);
+
+        assert_eq!(session.eligible(10 * MINUTE, &rules()), 0);
+        assert_eq!(session.duration(10 * MINUTE), 600);
+    }
+
+    #[test]
+    fn only_eligible_stretches_count() {
+        let rules = rules();
+        let mut session = new_session(CHANNEL, 0);
+
+        session.update(true, false, 0, &rules);
+        // alone in the channel for a minute
+        session.update(false, false, MINUTE, &rules);
+        session.update(true, false, 2 * MINUTE, &rules);
+
+        assert_eq!(session.eligible(150 * SECOND, &rules), 90);
+        assert_eq!(session.unpaid(150 * SECOND, &rules), 90);
+    }
+
+    #[test]
+    fn rechecks_keep_what_was_earned() {
+        let rules = rules();
+        let mut session = new_session(CHANNEL, 0);
+
+        session.update(true, false, 0, &rules);
+        session.update(true, false, 30 * SECOND, &rules);
+        session.update(true, false, 45 * SECOND, &rules);
+
+        assert_eq!(session.eligible(MINUTE, &rules), 60);
+    }
+
+    #[test]
+    fn members_muted_for_too_long_stop_counting() {
+        let rules = VoiceRules {
+            afk_after: Some(2 * MINUTE),
+            ..rules()
+        };
+        let mut session = new_session(CHANNEL, 0);
+
+        session.update(true, false, 0, &rules);
+        session.update(true, true, MINUTE, &rules);
+        assert_eq!(session.eligible(2 * MINUTE, &rules), 120);
+        assert_eq!(session.eligible(10 * MINUTE, &rules), 180);
+
+        // rechecks while muted don't restart the afk timer
+        session.update(true, true, 5 * MINUTE, &rules);
+        assert_eq!(session.eligible(10 * MINUTE, &rules), 180);
+
+        // unmuting counts again from there
+        session.update(true, false, 10 * MINUTE, &rules);
+        assert_eq!(session.eligible(11 * MINUTE, &rules), 240);
+    }
+
+    #[test]
+    fn muting_without_an_afk_limit_keeps_counting() {
+        let rules = rules();
+        let mut session = new_session(CHANNEL, 0);
+
+        session.update(true, true, 0, &rules);
+
+        assert_eq!(session.eligible(10 * MINUTE, &rules), 600);
+    }
+
+    #[test]
+    fn paid_seconds_are_not_unpaid_again() {
+        let rules = VoiceRules {
+            join_cooldown: 10,
+            ..rules()
+        };
+        let mut session = new_session(CHANNEL, 0);
+
+        session.update(true, false, 0, &rules);
+        session.paid = 60;
+
+        assert_eq!(session.unpaid(100 * SECOND, &rules), 30);
+        assert_eq!(session.unpaid(MINUTE, &rules), 0);
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::DbResult;
+
+type Slot<V> = Arc<tokio::sync::Mutex<Option<(Instant, V)>>>;
+
+// per-guild cache; every key has its own async lock, so concurrent lookups of the same guild
+// wait for the fetch already in flight instead of starting their own
+#[derive(Debug)]
+pub(crate) struct TtlCache<V> {
+    ttl: Duration,
+    slots: Mutex<HashMap<u64, Slot<V>>>,
+}
+
+impl<V: Clone> TtlCache<V> {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        TtlCache {
+            ttl,
+            slots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) async fn get_or_load<F>(&self, key: u64, load: F) -> DbResult<V>
+    where
+        F: Future<Output = DbResult<V>>,
+    {
+        if self.ttl.is_zero() {
+            return load.await;
+        }
+
+        let slot = self.slots.lock().unwrap().entry(key).or_default().clone();
+        let mut entry = slot.lock().await;
+
+        if let Some((loaded_at, value)) = entry.as_ref() {
+            if loaded_at.elapsed() < self.ttl {
+                return Ok(value.clone());
+            }
+        }
+
+        // errors are not cached, the next lookup tries again
+        let value = load.await?;
+        *entry = Some((Instant::now(), value.clone()));
+
+        Ok(value)
+    }
+
+    // a load still running on the removed slot only fills that slot, later lookups start fresh
+    pub(crate) fn invalidate(&self, key: u64) {
+        self.slots.lock().unwrap().remove(&key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::TtlCache;
+    use crate::{DbError, DbResult};
+
+    // counts the loads and takes a while, so concurrent lookups overlap
+    async fn load(loads: &AtomicUsize, value: u32) -> DbResult<u32> {
+        loads.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        Ok(value)
+    }
+
+    #[tokio::test]
+    async fn concurrent_lookups_share_one_load() {
+        let cache = TtlCache::new(Duration::from_secs(60));
+        let loads = AtomicUsize::new(0);
+
+        let (first, second, third) = tokio::join!(
+            cache.get_or_load(1, load(&loads, 5)),
+            cache.get_or_load(1, load(&loads, 6)),
+            cache.get_or_load(1, load(&loads, 7)),
+        );
+
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!((first.unwrap(), second.unwrap(), third.unwrap()), (5, 5, 5));
+    }
+
+    #[tokio::test]
+    async fn keys_load_on_their_own() {
+        let cache = TtlCache::new(Duration::from_secs(60));
+        let loads = AtomicUsize::new(0);
+
+        let (first, second) = tokio::join!(
+            cache.get_or_load(1, load(&loads, 5)),
+            cache.get_or_load(2, load(&loads, 6)),
+        );
+
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+        assert_eq!((first.unwrap(), second.unwrap()), (5, 6));
+    }
+
+    #[tokio::test]
+    async fn invalidate_loads_again() {
+        let cache = TtlCache::new(Duration::from_secs(60));
+        let loads = AtomicUsize::new(0);
+
+        assert_eq!(cache.get_or_load(1, load(&loads, 5)).await.unwrap(), 5);
+        assert_eq!(cache.get_or_load(1, load(&loads, 6)).await.unwrap(), 5);
+
+        cache.invalidate(1);
+        assert_eq!(cache.get_or_load(1, load(&loads, 7)).await.unwrap(), 7);
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn errors_are_not_cached() {
+        let cache = TtlCache::new(Duration::from_secs(60));
+        let loads = AtomicUsize::new(0);
+
+        let failed = cache
+            .get_or_load(1, async {
+                Err(DbError::Api {
+                    message: "down".to_string(),
+                })
+            })
+            .await;
+        assert!(failed.is_err());
+
+        assert_eq!(cache.get_or_load(1, load(&loads, 5)).await.unwrap(), 5);
+    }
+}
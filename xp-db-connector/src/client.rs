@@ -0,0 +1,551 @@
+use std::{
+    env,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    cache::TtlCache,
+    guild::Guild,
+    guild_premium::GuildPremium,
+    retry::{CircuitBreaker, RetryPolicy},
+    DbError, DbResult,
+};
+
+#[derive(Clone, Debug)]
+pub struct ApiClient {
+    http: reqwest::Client,
+    base_url: String,
+    auth: String,
+    retry: RetryPolicy,
+    // shared between clones, so every handle sees the same api health
+    breaker: Option<Arc<CircuitBreaker>>,
+    guilds: Arc<TtlCache<Guild>>,
+    guild_premiums: Arc<TtlCache<GuildPremium>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ApiClientBuilder {
+    base_url: String,
+    auth: String,
+    timeout: Duration,
+    connect_timeout: Duration,
+    pool_max_idle_per_host: usize,
+    user_agent: String,
+    retry: RetryPolicy,
+    failure_threshold: u32,
+    cooldown: Duration,
+    cache_ttl: Duration,
+}
+
+impl ApiClientBuilder {
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = max;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    // opens after `failure_threshold` failures in a row, 0 disables the breaker
+    pub fn circuit_breaker(mut self, failure_threshold: u32, cooldown: Duration) -> Self {
+        self.failure_threshold = failure_threshold;
+        self.cooldown = cooldown;
+        self
+    }
+
+    // how long guild settings and premium state are served from memory, zero disables the cache
+    pub fn cache_ttl(mut self, cache_ttl: Duration) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    pub fn build(self) -> DbResult<ApiClient> {
+        let http = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .user_agent(self.user_agent)
+            .build()?;
+
+        Ok(ApiClient {
+            http,
+            base_url: self.base_url.trim_end_matches('/').to_string(),
+            auth: self.auth,
+            retry: self.retry,
+            breaker: match self.failure_threshold {
+                0 => None,
+                threshold => Some(Arc::new(CircuitBreaker::new(threshold, self.cooldown))),
+            },
+            guilds: Arc::new(TtlCache::new(self.cache_ttl)),
+            guild_premiums: Arc::new(TtlCache::new(self.cache_ttl)),
+        })
+    }
+}
+
+impl ApiClient {
+    pub fn builder(base_url: impl Into<String>, auth: impl Into<String>) -> ApiClientBuilder {
+        ApiClientBuilder {
+            base_url: base_url.into(),
+            auth: auth.into(),
+            timeout: Duration::from_secs(10),
+            connect_timeout: Duration::from_secs(5),
+            pool_max_idle_per_host: 32,
+            user_agent: format!("xp-db-connector/{}", env!("CARGO_PKG_VERSION")),
+            retry: RetryPolicy::default(),
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+            cache_ttl: Duration::from_secs(60),
+        }
+    }
+
+    // reads API_URL and API_AUTH, like the connector did before it had a client
+    pub fn from_env() -> DbResult<ApiClient> {
+        let base_url =
+            env::var("API_URL").map_err(|_| DbError::Config("API_URL must be set".to_string()))?;
+        let api_auth = env::var("API_AUTH")
+            .map_err(|_| DbError::Config("API_AUTH must be set".to_string()))?;
+
+        ApiClient::builder(base_url, api_auth).build()
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    // has to be called after anything changed the settings of a guild
+    pub fn invalidate_guild(&self, guild_id: u64) {
+        self.guilds.invalidate(guild_id);
+        self.guild_premiums.invalidate(guild_id);
+    }
+
+    pub(crate) fn guild_cache(&self) -> &TtlCache<Guild> {
+        &self.guilds
+    }
+
+    pub(crate) fn guild_premium_cache(&self) -> &TtlCache<GuildPremium> {
+        &self.guild_premiums
+    }
+
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        self.http
+            .request(method, format!("{}{}", self.base_url, url))
+            .header("Authorization", format!("Bearer {}", self.auth))
+    }
+
+    // sends the request and returns the body of a 2xx response. GET, DELETE and writes with an
+    // idempotency key are retried on transport errors and 5xx, anything else only on a 429,
+    // which the api did not process
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        body: Option<&serde_json::Value>,
+        idempotency_key: Option<&str>,
+    ) -> DbResult<String> {
+        let idempotent = method == reqwest::Method::GET
+            || method == reqwest::Method::DELETE
+            || idempotency_key.is_some();
+        let mut attempt = 0;
+
+        loop {
+            if let Some(breaker) = &self.breaker {
+                breaker.check()?;
+            }
+
+            let mut request = self.request(method.clone(), url);
+            if let Some(body) = body {
+                request = request.json(body);
+            }
+            if let Some(idempotency_key) = idempotency_key {
+                request = request.header("Idempotency-Key", idempotency_key);
+            }
+
+            let (result, retry_after) = self.send_once(request).await;
+
+            let unhealthy = match &result {
+                Err(DbError::Transport(_)) => true,
+                Err(DbError::Status { status, .. }) => *status >= 500,
+                _ => false,
+            };
+
+            if let Some(breaker) = &self.breaker {
+                if unhealthy {
+                    breaker.record_failure();
+                } else {
+                    breaker.record_success();
+                }
+            }
+
+            let delay = match &result {
+                Err(DbError::Status { status: 429, .. }) => {
+                    Some(retry_after.unwrap_or_else(|| self.retry.backoff(attempt)))
+                }
+                Err(_) if idempotent && unhealthy => Some(self.retry.backoff(attempt)),
+                _ => None,
+            };
+
+            match delay {
+                Some(delay)
+                    if attempt < self.retry.max_retries && delay <= self.retry.max_retry_after =>
+                {
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => return result,
+            }
+        }
+    }
+
+    // a single attempt; non-2xx responses become an error carrying the message of the api
+    // envelope (or the raw body if there is none), alongside the `Retry-After` of a 429
+    async fn send_once(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> (DbResult<String>, Option<Duration>) {
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(why) => return (Err(why.into()), None),
+        };
+
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(why) => return (Err(why.into()), None),
+        };
+
+        if status.is_success() {
+            return (Ok(body), None);
+        }
+
+        let message = match serde_json::from_str::<Envelope>(&body) {
+            Ok(Envelope {
+                message: Some(message),
+                ..
+            }) => message,
+            _ => body,
+        };
+
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return (Err(DbError::NotFound { message }), None);
+        }
+
+        (
+            Err(DbError::Status {
+                status: status.as_u16(),
+                body: message,
+            }),
+            retry_after,
+        )
+    }
+
+    // writes only answer with the envelope, which can report a failure even on a 2xx
+    async fn send_write(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        body: Option<&serde_json::Value>,
+        idempotency_key: Option<&str>,
+    ) -> DbResult<()> {
+        let body = self.send(method, url, body, idempotency_key).await?;
+
+        if let Ok(Envelope {
+            success: Some(false),
+            message,
+        }) = serde_json::from_str::<Envelope>(&body)
+        {
+            return Err(DbError::Api {
+                message: message.unwrap_or_default(),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn get_json<T>(&self, url: &str) -> DbResult<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let body = self.send(reqwest::Method::GET, url, None, None).await?;
+
+        Ok(serde_json::from_str::<T>(&body)?)
+    }
+
+    pub(crate) async fn post_json<T>(&self, url: &str, body: T) -> DbResult<()>
+    where
+        T: serde::Serialize,
+    {
+        self.send_write(
+            reqwest::Method::POST,
+            url,
+            Some(&serde_json::to_value(body)?),
+            None,
+        )
+        .await
+    }
+
+    // a post the api applies only once per key, so it can be repeated safely
+    pub(crate) async fn post_json_idempotent<T>(
+        &self,
+        url: &str,
+        body: T,
+        idempotency_key: &str,
+    ) -> DbResult<()>
+    where
+        T: serde::Serialize,
+    {
+        self.send_write(
+            reqwest::Method::POST,
+            url,
+            Some(&serde_json::to_value(body)?),
+            Some(idempotency_key),
+        )
+        .await
+    }
+
+    // for writes that answer with content, which is unpacked by the caller
+    pub(crate) async fn post_json_response<T, R>(&self, url: &str, body: T) -> DbResult<R>
+    where
+        T: serde::Serialize,
+        R: serde::de::DeserializeOwned,
+    {
+        let body = self
+            .send(
+                reqwest::Method::POST,
+                url,
+                Some(&serde_json::to_value(body)?),
+                None,
+            )
+            .await?;
+
+        Ok(serde_json::from_str::<R>(&body)?)
+    }
+
+    pub(crate) async fn post_json_response_idempotent<T, R>(
+        &self,
+        url: &str,
+        body: T,
+        idempotency_key: &str,
+    ) -> DbResult<R>
+    where
+        T: serde::Serialize,
+        R: serde::de::DeserializeOwned,
+    {
+        let body = self
+            .send(
+                reqwest::Method::POST,
+                url,
+                Some(&serde_json::to_value(body)?),
+                Some(idempotency_key),
+            )
+            .await?;
+
+        Ok(serde_json::from_str::<R>(&body)?)
+    }
+
+    pub(crate) async fn patch_json<T>(&self, url: &str, body: T) -> DbResult<()>
+    where
+        T: serde::Serialize,
+    {
+        self.send_write(
+            reqwest::Method::PATCH,
+            url,
+            Some(&serde_json::to_value(body)?),
+            None,
+        )
+        .await
+    }
+
+    pub(crate) async fn delete_json(&self, url: &str) -> DbResult<()> {
+        self.send_write(reqwest::Method::DELETE, url, None, None)
+            .await
+    }
+}
+
+// `Retry-After` is either a number of seconds or an http-date, a date that already passed
+// means the request can be retried right away
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()
+            .map(|at| at.duration_since(SystemTime::now()).unwrap_or_default()),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct Envelope {
+    success: Option<bool>,
+    message: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant, SystemTime};
+
+    use xp_mock_api::{Faults, Fixtures, MockApi};
+
+    use super::{parse_retry_after, ApiClient};
+    use crate::{retry::RetryPolicy, store::XpStore, DbError};
+
+    const AUTH: &str = "secret";
+
+    fn retry() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+            max_retry_after: Duration::from_secs(2),
+        }
+    }
+
+    async fn client(auth: &str) -> (MockApi, ApiClient) {
+        let api = MockApi::new(Fixtures::default(), Some(AUTH.to_string()));
+        let base_url = api.clone().spawn().await.unwrap();
+        let client = ApiClient::builder(base_url, auth)
+            .retry(retry())
+            .circuit_breaker(0, Duration::ZERO)
+            .cache_ttl(Duration::ZERO)
+            .build()
+            .unwrap();
+
+        (api, client)
+    }
+
+    #[tokio::test]
+    async fn not_found_is_mapped() {
+        let (_, client) = client(AUTH).await;
+
+        let result = client.get_json::<serde_json::Value>("/nothing/here").await;
+        assert!(matches!(result, Err(DbError::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn status_carries_the_envelope_message() {
+        let (_, client) = client("wrong").await;
+
+        match client.guild(1).await {
+            Err(DbError::Status { status, body }) => {
+                assert_eq!(status, 401);
+                assert_eq!(body, "Unauthorized");
+            }
+            other => panic!("expected a 401, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_are_retried_on_server_errors() {
+        let (api, client) = client(AUTH).await;
+        api.set_faults(Faults {
+            fail_next: 2,
+            ..Faults::default()
+        });
+
+        assert!(client.guild(1).await.is_ok());
+        assert_eq!(api.requests(), vec!["GET /guild/1"; 3]);
+    }
+
+    #[tokio::test]
+    async fn retries_give_up_with_the_last_error() {
+        let (api, client) = client(AUTH).await;
+        api.set_faults(Faults {
+            fail_next: 10,
+            ..Faults::default()
+        });
+
+        match client.guild(1).await {
+            Err(DbError::Status { status, body }) => {
+                assert_eq!(status, 500);
+                assert_eq!(body, "injected failure");
+            }
+            other => panic!("expected a 500, got {:?}", other),
+        }
+        assert_eq!(api.requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn plain_writes_are_not_retried_on_server_errors() {
+        let (api, client) = client(AUTH).await;
+        api.set_faults(Faults {
+            fail_next: 1,
+            ..Faults::default()
+        });
+
+        let result = client
+            .post_json(
+                "/guild/1/member/2/direct/xp",
+                serde_json::json!({ "xp": 5 }),
+            )
+            .await;
+        assert!(matches!(result, Err(DbError::Status { status: 500, .. })));
+        assert_eq!(api.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_wait_for_retry_after() {
+        let (api, client) = client(AUTH).await;
+        api.set_faults(Faults {
+            rate_limit_next: 1,
+            retry_after_secs: 1,
+            ..Faults::default()
+        });
+
+        let started = Instant::now();
+        assert!(client.guild(1).await.is_ok());
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(api.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_beyond_the_limit_are_returned() {
+        let (api, client) = client(AUTH).await;
+        api.set_faults(Faults {
+            rate_limit_next: 1,
+            retry_after_secs: 60,
+            ..Faults::default()
+        });
+
+        let result = client.guild(1).await;
+        assert!(matches!(result, Err(DbError::Status { status: 429, .. })));
+        assert_eq!(api.requests().len(), 1);
+    }
+
+    #[test]
+    fn retry_after_accepts_seconds_and_dates() {
+        assert_eq!(parse_retry_after(" 5 "), Some(Duration::from_secs(5)));
+
+        let in_a_minute = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        let delay = parse_retry_after(&in_a_minute).unwrap();
+        assert!(delay > Duration::from_secs(55) && delay <= Duration::from_secs(60));
+
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
@@ -0,0 +1,97 @@
+use std::{fmt, time::Duration};
+
+#[derive(Debug)]
+pub enum DbError {
+    /// The request never produced a response (connection refused, timeout, tls, ...).
+    Transport(reqwest::Error),
+    /// The api responded with a non-2xx status code, `body` holds the api's message if it sent one.
+    Status { status: u16, body: String },
+    /// The api responded, but reported `success: false`.
+    Api { message: String },
+    /// The requested document does not exist (yet).
+    NotFound { message: String },
+    /// The response body did not match the expected model.
+    Deserialize(serde_json::Error),
+    /// The client could not be configured (missing env vars, ...).
+    Config(String),
+    /// The input was rejected before anything was sent (oversized or unsupported image, ...).
+    Invalid(String),
+    /// The local database or the write journal failed.
+    Storage(String),
+    /// The api failed too often in a row, requests are refused until the cooldown is over.
+    CircuitOpen { retry_in: Duration },
+}
+
+impl DbError {
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, DbError::NotFound { .. })
+    }
+
+    // errors where the same write may still be accepted later on
+    pub fn is_transient(&self) -> bool {
+        match self {
+            DbError::Transport(_) | DbError::CircuitOpen { .. } => true,
+            DbError::Status { status, .. } => *status >= 500 || *status == 429,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Transport(why) => write!(f, "could not reach the xp api: {}", why),
+            DbError::Status { status, body } => {
+                write!(f, "xp api responded with status {}: {}", status, body)
+            }
+            DbError::Api { message } => write!(f, "xp api reported an error: {}", message),
+            DbError::NotFound { message } => write!(f, "not found: {}", message),
+            DbError::Deserialize(why) => {
+                write!(f, "could not deserialize xp api response: {}", why)
+            }
+            DbError::Config(message) => write!(f, "invalid xp api configuration: {}", message),
+            DbError::Invalid(message) => write!(f, "invalid input: {}", message),
+            DbError::Storage(message) => write!(f, "local database error: {}", message),
+            DbError::CircuitOpen { retry_in } => write!(
+                f,
+                "xp api is unavailable, requests are paused for another {}ms",
+                retry_in.as_millis()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbError::Transport(why) => Some(why),
+            DbError::Deserialize(why) => Some(why),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for DbError {
+    fn from(why: reqwest::Error) -> Self {
+        DbError::Transport(why)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for DbError {
+    fn from(why: rusqlite::Error) -> Self {
+        DbError::Storage(why.to_string())
+    }
+}
+
+impl From<std::io::Error> for DbError {
+    fn from(why: std::io::Error) -> Self {
+        DbError::Storage(why.to_string())
+    }
+}
+
+impl From<serde_json::Error> for DbError {
+    fn from(why: serde_json::Error) -> Self {
+        DbError::Deserialize(why)
+    }
+}
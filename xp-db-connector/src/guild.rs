@@ -1,16 +1,25 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
-use crate::DbResult;
+use crate::{
+    migrate::{self, Versioned},
+    store::XpStore,
+    DbError, DbResult,
+};
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct GuildResponse {
     pub success: bool,
     pub message: String,
+    #[serde(default, deserialize_with = "migrate::content")]
     pub content: Option<Guild>,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct Guild {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
     pub values: GuildValues,
     pub modules: GuildModules,
     pub ignored: GuildIgnores,
@@ -21,7 +30,8 @@ pub struct Guild {
 }
 
 #[allow(non_snake_case)]
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
 pub struct GuildValues {
     pub reactionxp: i64,
     pub fishXP: i64,
@@ -36,9 +46,14 @@ pub struct GuildValues {
     pub triviacooldown: i64,
     pub triviaxp: i64,
     pub maximumlevel: i64,
+    // unmuted members, bots not counted, a voice channel needs before anyone in it earns xp
+    pub voiceminmembers: i64,
+    // minutes a member can stay self-muted before counting as afk, 0 never does
+    pub voicemuteafk: i64,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
 pub struct GuildModules {
     pub reactionxp: bool,
     pub maximumlevel: bool,
@@ -55,42 +70,62 @@ pub struct GuildModules {
     pub removereachedlevelroles: bool,
     pub singlerankrole: bool,
     pub ignoreafk: bool,
+    // no voice xp while self-deafened
+    pub voiceignoredeafened: bool,
+    // no voice xp while muted or deafened by a moderator
+    pub voiceignoremuted: bool,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct GuildLogs {
     pub voicetime: Option<String>,
     pub levelup: Option<String>,
     pub exceptions: Option<String>,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct GuildIgnores {
     pub roles: Vec<String>,
-    pub channels: Option<Vec<String>>,
-    pub categories: Option<Vec<String>>,
+    pub channels: Vec<String>,
+    pub categories: Vec<String>,
+    // only what is in `allowed` earns xp, the ignore lists still apply on top
+    pub allowlist: bool,
+    pub allowed: GuildAllowed,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+// kept apart from the ignore lists, so switching modes never changes what a list means
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct GuildAllowed {
+    pub roles: Vec<String>,
+    pub channels: Vec<String>,
+    pub categories: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct GuildBoosts {
     pub roles: Vec<GuildBoostObject>,
     pub channels: Vec<GuildBoostObject>,
-    pub categories: Option<Vec<GuildBoostObject>>,
+    pub categories: Vec<GuildBoostObject>,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct GuildBoostObject {
     pub id: String,
     pub percentage: i64,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct GuildLevelRoles {
     pub id: String,
     pub level: i32,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
 pub struct GuildAnnounce {
     pub current: bool,
     pub message: String,
@@ -104,37 +139,398 @@ pub struct GuildPremiumResponse {
     pub voteFree: bool,
 }
 
-impl Guild {
-    pub async fn from_id(guild_id: u64) -> DbResult<Guild> {
-        let response = crate::get_json::<GuildResponse>(format!("/guild/{}", guild_id)).await?;
+// the settings a new guild starts with
+impl Default for GuildValues {
+    fn default() -> Self {
+        GuildValues {
+            reactionxp: 5,
+            fishXP: 25,
+            lootXP: 50,
+            messagecooldown: 10,
+            messagexp: 5,
+            rollXP: 10,
+            voicejoincooldown: 10,
+            voicexp: 5,
+            gamecooldown: 60,
+            maximumdailyxp: 1000,
+            triviacooldown: 60,
+            triviaxp: 25,
+            // no maximum, see the maximumlevel module
+            maximumlevel: 0,
+            voiceminmembers: 2,
+            voicemuteafk: 0,
+        }
+    }
+}
 
-        if response.success && response.content.is_some() {
-            Ok(response.content.unwrap())
-        } else {
-            Err(format!("Failed to get guild with id {}", guild_id).into())
+impl Default for GuildModules {
+    fn default() -> Self {
+        GuildModules {
+            reactionxp: true,
+            maximumlevel: false,
+            autonick: false,
+            games: true,
+            messagexp: true,
+            resetonleave: false,
+            voicexp: true,
+            enablecommandsinthreads: false,
+            autonickshowstring: false,
+            autonickuseprefix: false,
+            trivia: true,
+            leaderboard: true,
+            removereachedlevelroles: false,
+            singlerankrole: false,
+            ignoreafk: true,
+            voiceignoredeafened: true,
+            voiceignoremuted: true,
         }
     }
+}
 
-    pub async fn delete(
-        guild_id: &u64,
-    ) -> DbResult<Result<(), Box<(dyn std::error::Error + Send + Sync + 'static)>>> {
-        crate::delete_json(format!("/guild/{}", guild_id)).await?;
+impl Default for GuildAnnounce {
+    fn default() -> Self {
+        GuildAnnounce {
+            current: true,
+            message: "GG {MNT}, you reached level {LVL}!".to_string(),
+            ping: false,
+        }
+    }
+}
 
-        Ok(Ok(()))
+impl Versioned for Guild {
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn upgrade(document: &mut Value, version: u32) {
+        // 0 -> 1: channel and category lists were added later and are null on old guilds
+        if version == 0 {
+            migrate::fill_null(document, "/ignored/channels", json!([]));
+            migrate::fill_null(document, "/ignored/categories", json!([]));
+            migrate::fill_null(document, "/boosts/categories", json!([]));
+        }
+    }
+}
+
+// partial guild update, sections left at `None` are not touched by the api
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct GuildPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<GuildValues>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modules: Option<GuildModules>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ignored: Option<GuildIgnores>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boosts: Option<GuildBoosts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub levelroles: Option<Vec<GuildLevelRoles>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub announce: Option<GuildAnnounce>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logs: Option<GuildLogs>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuildModule {
+    ReactionXp,
+    MaximumLevel,
+    Autonick,
+    Games,
+    MessageXp,
+    ResetOnLeave,
+    VoiceXp,
+    EnableCommandsInThreads,
+    AutonickShowString,
+    AutonickUsePrefix,
+    Trivia,
+    Leaderboard,
+    RemoveReachedLevelRoles,
+    SingleRankRole,
+    IgnoreAfk,
+    VoiceIgnoreDeafened,
+    VoiceIgnoreMuted,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuildValue {
+    ReactionXp,
+    FishXp,
+    LootXp,
+    MessageCooldown,
+    MessageXp,
+    RollXp,
+    VoiceJoinCooldown,
+    VoiceXp,
+    GameCooldown,
+    MaximumDailyXp,
+    TriviaCooldown,
+    TriviaXp,
+    MaximumLevel,
+    VoiceMinMembers,
+    VoiceMuteAfk,
+}
+
+// what a boost or ignore applies to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuildTarget {
+    Role,
+    Channel,
+    Category,
+}
+
+/*
+    A single settings change, applied with `Guild::update`.
+    > Adding a level role or boost for an id that already has one replaces it.
+    > Removing something that isn't there is not an error.
+*/
+#[derive(Clone, Debug)]
+pub enum GuildUpdate {
+    SetModule {
+        module: GuildModule,
+        enabled: bool,
+    },
+    SetValue {
+        value: GuildValue,
+        amount: i64,
+    },
+    AddLevelRole {
+        role_id: u64,
+        level: i32,
+    },
+    RemoveLevelRole {
+        role_id: u64,
+    },
+    AddBoost {
+        target: GuildTarget,
+        id: u64,
+        percentage: i64,
+    },
+    RemoveBoost {
+        target: GuildTarget,
+        id: u64,
+    },
+    AddIgnore {
+        target: GuildTarget,
+        id: u64,
+    },
+    RemoveIgnore {
+        target: GuildTarget,
+        id: u64,
+    },
+    AddAllowed {
+        target: GuildTarget,
+        id: u64,
+    },
+    RemoveAllowed {
+        target: GuildTarget,
+        id: u64,
+    },
+    SetAllowList {
+        enabled: bool,
+    },
+}
+
+impl GuildModules {
+    fn slot(&mut self, module: GuildModule) -> &mut bool {
+        match module {
+            GuildModule::ReactionXp => &mut self.reactionxp,
+            GuildModule::MaximumLevel => &mut self.maximumlevel,
+            GuildModule::Autonick => &mut self.autonick,
+            GuildModule::Games => &mut self.games,
+            GuildModule::MessageXp => &mut self.messagexp,
+            GuildModule::ResetOnLeave => &mut self.resetonleave,
+            GuildModule::VoiceXp => &mut self.voicexp,
+            GuildModule::EnableCommandsInThreads => &mut self.enablecommandsinthreads,
+            GuildModule::AutonickShowString => &mut self.autonickshowstring,
+            GuildModule::AutonickUsePrefix => &mut self.autonickuseprefix,
+            GuildModule::Trivia => &mut self.trivia,
+            GuildModule::Leaderboard => &mut self.leaderboard,
+            GuildModule::RemoveReachedLevelRoles => &mut self.removereachedlevelroles,
+            GuildModule::SingleRankRole => &mut self.singlerankrole,
+            GuildModule::IgnoreAfk => &mut self.ignoreafk,
+            GuildModule::VoiceIgnoreDeafened => &mut self.voiceignoredeafened,
+            GuildModule::VoiceIgnoreMuted => &mut self.voiceignoremuted,
+        }
+    }
+}
+
+impl GuildValues {
+    fn slot(&mut self, value: GuildValue) -> &mut i64 {
+        match value {
+            GuildValue::ReactionXp => &mut self.reactionxp,
+            GuildValue::FishXp => &mut self.fishXP,
+            GuildValue::LootXp => &mut self.lootXP,
+            GuildValue::MessageCooldown => &mut self.messagecooldown,
+            GuildValue::MessageXp => &mut self.messagexp,
+            GuildValue::RollXp => &mut self.rollXP,
+            GuildValue::VoiceJoinCooldown => &mut self.voicejoincooldown,
+            GuildValue::VoiceXp => &mut self.voicexp,
+            GuildValue::GameCooldown => &mut self.gamecooldown,
+            GuildValue::MaximumDailyXp => &mut self.maximumdailyxp,
+            GuildValue::TriviaCooldown => &mut self.triviacooldown,
+            GuildValue::TriviaXp => &mut self.triviaxp,
+            GuildValue::MaximumLevel => &mut self.maximumlevel,
+            GuildValue::VoiceMinMembers => &mut self.voiceminmembers,
+            GuildValue::VoiceMuteAfk => &mut self.voicemuteafk,
+        }
+    }
+}
+
+impl GuildBoosts {
+    fn list(&mut self, target: GuildTarget) -> &mut Vec<GuildBoostObject> {
+        match target {
+            GuildTarget::Role => &mut self.roles,
+            GuildTarget::Channel => &mut self.channels,
+            GuildTarget::Category => &mut self.categories,
+        }
+    }
+}
+
+impl GuildIgnores {
+    fn list(&mut self, target: GuildTarget) -> &mut Vec<String> {
+        match target {
+            GuildTarget::Role => &mut self.roles,
+            GuildTarget::Channel => &mut self.channels,
+            GuildTarget::Category => &mut self.categories,
+        }
+    }
+}
+
+impl GuildAllowed {
+    fn list(&mut self, target: GuildTarget) -> &mut Vec<String> {
+        match target {
+            GuildTarget::Role => &mut self.roles,
+            GuildTarget::Channel => &mut self.channels,
+            GuildTarget::Category => &mut self.categories,
+        }
+    }
+}
+
+impl GuildUpdate {
+    // applies the change to `guild` and returns a patch with only the section it touched
+    pub fn apply(self, guild: &mut Guild) -> DbResult<GuildPatch> {
+        let mut patch = GuildPatch::default();
+
+        match self {
+            GuildUpdate::SetModule { module, enabled } => {
+                *guild.modules.slot(module) = enabled;
+                patch.modules = Some(guild.modules.clone());
+            }
+            GuildUpdate::SetValue { value, amount } => {
+                if amount < 0 {
+                    return Err(DbError::Invalid(format!("{:?} can not be negative", value)));
+                }
+
+                *guild.values.slot(value) = amount;
+                patch.values = Some(guild.values.clone());
+            }
+            GuildUpdate::AddLevelRole { role_id, level } => {
+                if level < 1 {
+                    return Err(DbError::Invalid(
+                        "level roles need a level of at least 1".to_string(),
+                    ));
+                }
+
+                let role_id = role_id.to_string();
+                guild.levelroles.retain(|role| role.id != role_id);
+                guild
+                    .levelroles
+                    .push(GuildLevelRoles { id: role_id, level });
+                patch.levelroles = Some(guild.levelroles.clone());
+            }
+            GuildUpdate::RemoveLevelRole { role_id } => {
+                let role_id = role_id.to_string();
+                guild.levelroles.retain(|role| role.id != role_id);
+                patch.levelroles = Some(guild.levelroles.clone());
+            }
+            GuildUpdate::AddBoost {
+                target,
+                id,
+                percentage,
+            } => {
+                let id = id.to_string();
+                let boosts = guild.boosts.list(target);
+                boosts.retain(|boost| boost.id != id);
+                boosts.push(GuildBoostObject { id, percentage });
+                patch.boosts = Some(guild.boosts.clone());
+            }
+            GuildUpdate::RemoveBoost { target, id } => {
+                let id = id.to_string();
+                guild.boosts.list(target).retain(|boost| boost.id != id);
+                patch.boosts = Some(guild.boosts.clone());
+            }
+            GuildUpdate::AddIgnore { target, id } => {
+                let id = id.to_string();
+                let ignored = guild.ignored.list(target);
+                if !ignored.contains(&id) {
+                    ignored.push(id);
+                }
+                patch.ignored = Some(guild.ignored.clone());
+            }
+            GuildUpdate::RemoveIgnore { target, id } => {
+                let id = id.to_string();
+                guild.ignored.list(target).retain(|ignored| *ignored != id);
+                patch.ignored = Some(guild.ignored.clone());
+            }
+            GuildUpdate::AddAllowed { target, id } => {
+                let id = id.to_string();
+                let allowed = guild.ignored.allowed.list(target);
+                if !allowed.contains(&id) {
+                    allowed.push(id);
+                }
+                patch.ignored = Some(guild.ignored.clone());
+            }
+            GuildUpdate::RemoveAllowed { target, id } => {
+                let id = id.to_string();
+                guild
+                    .ignored
+                    .allowed
+                    .list(target)
+                    .retain(|allowed| *allowed != id);
+                patch.ignored = Some(guild.ignored.clone());
+            }
+            GuildUpdate::SetAllowList { enabled } => {
+                guild.ignored.allowlist = enabled;
+                patch.ignored = Some(guild.ignored.clone());
+            }
+        }
+
+        Ok(patch)
+    }
+}
+
+impl Guild {
+    pub async fn from_id(store: &dyn XpStore, guild_id: u64) -> DbResult<Guild> {
+        store.guild(guild_id).await
+    }
+
+    pub async fn delete(store: &dyn XpStore, guild_id: &u64) -> DbResult<()> {
+        store.delete_guild(*guild_id).await
     }
 
     pub async fn delete_xp(
+        store: &dyn XpStore,
         guild_id: &u64,
-    ) -> DbResult<Result<(), Box<(dyn std::error::Error + Send + Sync + 'static)>>> {
-        crate::delete_json(format!("/guild/{}/members/xp", guild_id)).await?;
+        actor: Option<u64>,
+    ) -> DbResult<()> {
+        store.delete_guild_xp(*guild_id, actor).await
+    }
 
-        Ok(Ok(()))
+    pub async fn patch(store: &dyn XpStore, guild_id: u64, patch: GuildPatch) -> DbResult<()> {
+        store.patch_guild(guild_id, patch).await
     }
 
-    pub async fn is_premium(guild_id: &u64) -> DbResult<bool> {
-        let response =
-            crate::get_json::<GuildPremiumResponse>(format!("/guild/{}/premium", guild_id)).await?;
+    // applies one change on top of the latest settings, returns the guild after the change
+    pub async fn update(
+        store: &dyn XpStore,
+        guild_id: u64,
+        update: GuildUpdate,
+    ) -> DbResult<Guild> {
+        store.invalidate_guild(guild_id);
+
+        let mut guild = store.guild(guild_id).await?;
+        let patch = update.apply(&mut guild)?;
+        store.patch_guild(guild_id, patch).await?;
 
-        Ok(response.premium)
+        Ok(guild)
     }
 }
@@ -1,17 +1,26 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
 
-use crate::DbResult;
+use crate::{
+    migrate::{self, Versioned},
+    store::XpStore,
+    DbResult,
+};
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct GuildMemberResponse {
     pub success: bool,
     pub message: String,
+    #[serde(default, deserialize_with = "migrate::content")]
     pub content: Option<GuildMember>,
 }
 
 #[allow(non_snake_case)]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct GuildMember {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
     pub xp: u64,
     pub userData: GuildMemberData,
     pub settings: GuildMemberSettings,
@@ -19,20 +28,26 @@ pub struct GuildMember {
     pub streaks: GuildMemberStreaks,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct GuildMemberData {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub avatar: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub banner: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct GuildMemberSettings {
     pub incognito: Option<bool>,
     pub ranking: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct GuildMemberTimestamps {
     pub message_cooldown: Option<u64>,
     pub game_trivia: Option<u64>,
@@ -42,7 +57,8 @@ pub struct GuildMemberTimestamps {
     pub game_roll: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct GuildMemberStreaks {
     pub game_daily: Option<u64>,
     pub game_trivia: Option<u64>,
@@ -52,60 +68,263 @@ pub struct GuildMemberStreaks {
 #[allow(non_snake_case)]
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct XPPostBody {
-    xp: u64,
-    userData: GuildMemberData,
+    pub(crate) xp: u64,
+    pub(crate) userData: GuildMemberData,
+    pub(crate) reason: XpReason,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) actor: Option<String>,
 }
 
-impl GuildMember {
-    pub async fn from_id(guild_id: u64, member_id: u64) -> DbResult<GuildMember> {
-        let response = crate::get_json::<GuildMemberResponse>(format!(
-            "/guild/{}/member/{}",
-            guild_id, member_id
-        ))
-        .await?;
-
-        if response.success && response.content.is_some() {
-            Ok(response.content.unwrap())
-        } else {
-            Err(format!("Failed to get guild member: {}", response.message).into())
+// what an xp change was made for, stored with the change in the member's ledger
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum XpReason {
+    Message,
+    Reaction,
+    Voice,
+    Daily,
+    Fish,
+    Loot,
+    Roll,
+    Trivia,
+    Party,
+    // admin commands
+    Add,
+    Remove,
+    Set,
+    SetLevel,
+    // all xp of the member or guild was reset
+    Reset,
+    // the member left a guild with `resetonleave`
+    Leave,
+    // xp was taken from a guild snapshot
+    Restore,
+    // written by a newer version
+    #[serde(other)]
+    Unknown,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Clone, Debug)]
+pub struct XPIncrementBody {
+    pub(crate) delta: i64,
+    pub(crate) reason: XpReason,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) userData: Option<GuildMemberData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) actor: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct XpIncrementResponse {
+    pub success: bool,
+    pub message: String,
+    pub content: Option<XpIncrement>,
+}
+
+impl Versioned for GuildMember {
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn upgrade(document: &mut Value, version: u32) {
+        // 0 -> 1: the daily streak moved from `streaks.daily` to `streaks.game_daily`
+        if version == 0 {
+            if let Some(streaks) = document.get_mut("streaks").and_then(Value::as_object_mut) {
+                let daily = streaks.remove("daily").unwrap_or(Value::Null);
+                if streaks.get("game_daily").is_none_or(Value::is_null) && !daily.is_null() {
+                    streaks.insert("game_daily".to_string(), daily);
+                }
+            }
         }
     }
+}
+
+// result of an increment, computed by the api after the delta has been applied
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct XpIncrement {
+    pub xp: u64,
+    pub oldLevel: i32,
+    pub newLevel: i32,
+}
+
+// one member's accumulated gains, ids are strings since snowflakes don't fit into a js number
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct XpBatchEntry {
+    pub guildId: String,
+    pub memberId: String,
+    pub delta: i64,
+    pub reason: XpReason,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub messageCooldown: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub userData: Option<GuildMemberData>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct XPBatchBody {
+    pub(crate) increments: Vec<XpBatchEntry>,
+}
+
+/*
+    Partial member update, fields left at `None` are not touched by the api.
+    > Timestamps and streaks are merged field by field, only the ones that are set are written.
+    > That way a command only writes its own cooldown and doesn't overwrite one that was
+    > written in the meantime, like the message cooldown of a batch.
+*/
+#[allow(non_snake_case)]
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct GuildMemberPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub userData: Option<GuildMemberData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub settings: Option<GuildMemberSettings>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "set_fields")]
+    pub timestamps: Option<GuildMemberTimestamps>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "set_fields")]
+    pub streaks: Option<GuildMemberStreaks>,
+}
+
+// leaves out the fields that are `None`, so the api keeps what it has for them
+fn set_fields<S, T>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize,
+{
+    let mut value = serde_json::to_value(value).map_err(serde::ser::Error::custom)?;
+    if let Value::Object(fields) = &mut value {
+        fields.retain(|_, field| !field.is_null());
+    }
 
+    value.serialize(serializer)
+}
+
+impl GuildMemberTimestamps {
+    // takes the timestamps that are set in `patch` and keeps the rest
+    pub fn merge(&mut self, patch: GuildMemberTimestamps) {
+        self.message_cooldown = patch.message_cooldown.or(self.message_cooldown);
+        self.game_trivia = patch.game_trivia.or(self.game_trivia);
+        self.game_daily = patch.game_daily.or(self.game_daily);
+        self.game_fish = patch.game_fish.or(self.game_fish);
+        self.game_loot = patch.game_loot.or(self.game_loot);
+        self.game_roll = patch.game_roll.or(self.game_roll);
+    }
+}
+
+impl GuildMemberStreaks {
+    pub fn merge(&mut self, patch: GuildMemberStreaks) {
+        self.game_daily = patch.game_daily.or(self.game_daily);
+        self.game_trivia = patch.game_trivia.or(self.game_trivia);
+        self.daily = patch.daily.or(self.daily);
+    }
+}
+
+impl GuildMember {
+    pub async fn from_id(
+        store: &dyn XpStore,
+        guild_id: u64,
+        member_id: u64,
+    ) -> DbResult<GuildMember> {
+        store.member(guild_id, member_id).await
+    }
+
+    // `actor` is the user who set the xp, None if it happened on its own
     pub async fn set_xp(
+        store: &dyn XpStore,
         guild_id: u64,
         member_id: u64,
         xp: &u64,
         guild_member: &GuildMember,
-    ) -> DbResult<Result<(), Box<(dyn std::error::Error + Send + Sync + 'static)>>> {
-        let response = crate::post_json(
-            format!("/guild/{}/member/{}/direct/xp", guild_id, member_id),
-            XPPostBody {
-                xp: *xp,
-                userData: guild_member.userData.clone(),
-            },
-        )
-        .await;
+        reason: XpReason,
+        actor: Option<u64>,
+    ) -> DbResult<()> {
+        store
+            .set_member_xp(
+                guild_id,
+                member_id,
+                *xp,
+                guild_member.userData.clone(),
+                reason,
+                actor,
+            )
+            .await
+    }
 
-        match response {
-            Ok(_) => Ok(Ok(())),
-            Err(e) => Ok(Err(e.into())),
-        }
+    // applies `delta` on the store side, so concurrent gains of the same member add up.
+    // `actor` is the admin behind a manual change, for the ledger
+    pub async fn add_xp(
+        store: &dyn XpStore,
+        guild_id: u64,
+        member_id: u64,
+        delta: i64,
+        reason: XpReason,
+        user_data: Option<GuildMemberData>,
+        actor: Option<u64>,
+    ) -> DbResult<XpIncrement> {
+        store
+            .add_member_xp(guild_id, member_id, delta, reason, user_data, actor)
+            .await
+    }
+
+    // applies many increments at once, like `add_xp` every delta is added on the store side
+    pub async fn add_xp_batch(store: &dyn XpStore, entries: Vec<XpBatchEntry>) -> DbResult<()> {
+        store.add_member_xp_batch(entries).await
+    }
+
+    pub async fn patch(
+        store: &dyn XpStore,
+        guild_id: u64,
+        member_id: u64,
+        patch: GuildMemberPatch,
+    ) -> DbResult<()> {
+        store.patch_member(guild_id, member_id, patch).await
     }
 
     pub async fn set_guild_member(
+        store: &dyn XpStore,
         guild_id: u64,
         member_id: u64,
         guild_member: GuildMember,
-    ) -> DbResult<Result<(), Box<(dyn std::error::Error + Send + Sync + 'static)>>> {
-        let response = crate::patch_json(
-            format!("/guild/{}/member/{}", guild_id, member_id),
-            guild_member,
-        )
-        .await;
-
-        match response {
-            Ok(_) => Ok(Ok(())),
-            Err(e) => Ok(Err(e.into())),
+    ) -> DbResult<()> {
+        store.set_member(guild_id, member_id, guild_member).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xp_mock_api::{Fixtures, MockApi};
+
+    use super::{GuildMember, GuildMemberPatch, GuildMemberTimestamps};
+    use crate::ApiClient;
+
+    #[tokio::test]
+    async fn patches_keep_the_timestamps_they_do_not_set() {
+        let base_url = MockApi::new(Fixtures::default(), None)
+            .spawn()
+            .await
+            .unwrap();
+        let client = ApiClient::builder(&base_url, "").build().unwrap();
+
+        for timestamps in [
+            GuildMemberTimestamps {
+                message_cooldown: Some(1),
+                ..Default::default()
+            },
+            GuildMemberTimestamps {
+                game_fish: Some(2),
+                ..Default::default()
+            },
+        ] {
+            let patch = GuildMemberPatch {
+                timestamps: Some(timestamps),
+                ..Default::default()
+            };
+            GuildMember::patch(&client, 1, 2, patch).await.unwrap();
         }
+
+        let member = GuildMember::from_id(&client, 1, 2).await.unwrap();
+        assert_eq!(member.timestamps.message_cooldown, Some(1));
+        assert_eq!(member.timestamps.game_fish, Some(2));
     }
 }
@@ -1,6 +1,6 @@
 use serde::Deserialize;
 
-use crate::DbResult;
+use crate::{store::XpStore, DbResult};
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct GuildPremiumResponse {
@@ -10,21 +10,15 @@ pub struct GuildPremiumResponse {
 }
 
 #[allow(non_snake_case)]
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct GuildPremium {
     pub premium: bool,
     pub voteFree: bool,
 }
 
 impl GuildPremium {
-    pub async fn from_id(guild_id: u64) -> DbResult<GuildPremium> {
-        let response =
-            crate::get_json::<GuildPremiumResponse>(format!("/guild/{}/premium", guild_id)).await?;
-
-        if response.success && response.content.is_some() {
-            Ok(response.content.unwrap())
-        } else {
-            Err(format!("Failed to get guild premium with id {}", guild_id).into())
-        }
+    pub async fn from_id(store: &dyn XpStore, guild_id: u64) -> DbResult<GuildPremium> {
+        store.guild_premium(guild_id).await
     }
 }
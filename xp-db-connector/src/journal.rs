@@ -0,0 +1,410 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    guild_member::{GuildMemberData, XpBatchEntry, XpIncrement, XpReason},
+    store::XpStore,
+    DbError, DbResult,
+};
+
+// an xp write that has to reach the store eventually, even if the store is down right now
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum XpWrite {
+    SetXp {
+        guild_id: u64,
+        member_id: u64,
+        xp: u64,
+        user_data: GuildMemberData,
+        reason: XpReason,
+        actor: Option<u64>,
+    },
+    AddXp {
+        guild_id: u64,
+        member_id: u64,
+        delta: i64,
+        reason: XpReason,
+        user_data: Option<GuildMemberData>,
+    },
+    AddXpBatch {
+        entries: Vec<XpBatchEntry>,
+    },
+}
+
+// a write and its idempotency id, stores skip ids they have applied before
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JournalEntry {
+    pub id: String,
+    #[serde(flatten)]
+    pub write: XpWrite,
+}
+
+impl JournalEntry {
+    pub fn new(write: XpWrite) -> JournalEntry {
+        JournalEntry {
+            id: format!("{:032x}", rand::random::<u128>()),
+            write,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    pub applied: usize,
+    // entries the store refused for good, they are not kept around
+    pub dropped: Vec<(JournalEntry, DbError)>,
+    pub remaining: usize,
+}
+
+struct JournalState {
+    entries: VecDeque<JournalEntry>,
+    file: File,
+}
+
+/*
+    Append-only file of xp writes that could not reach the store.
+    > One json entry per line, every append is synced before it returns.
+    > Once something is journaled, later writes are journaled behind it instead of being
+    > sent, so `replay` applies them in the order they were made.
+    > Every entry carries an id the store deduplicates on, so an entry that was applied
+    > right before a crash or a timeout is not applied twice when it is replayed.
+    > Replayed entries are removed by rewriting the file, after each replay.
+*/
+pub struct Journal {
+    path: PathBuf,
+    state: Mutex<JournalState>,
+    replaying: tokio::sync::Mutex<()>,
+}
+
+impl Journal {
+    // picks up the entries left over from the last run, a line cut off by a crash is skipped
+    pub fn open(path: impl AsRef<Path>) -> DbResult<Journal> {
+        let path = path.as_ref().to_path_buf();
+        let mut entries = VecDeque::new();
+
+        if path.exists() {
+            let lines = BufReader::new(File::open(&path)?)
+                .lines()
+                .collect::<Result<Vec<String>, _>>()?;
+            let last = lines.len().saturating_sub(1);
+
+            for (index, line) in lines.iter().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                match serde_json::from_str::<JournalEntry>(line) {
+                    Ok(entry) => entries.push_back(entry),
+                    Err(_) if index == last => {}
+                    Err(why) => {
+                        return Err(DbError::Storage(format!(
+                            "corrupt entry in journal {} on line {}: {}",
+                            path.display(),
+                            index + 1,
+                            why
+                        )))
+                    }
+                }
+            }
+        }
+
+        let journal = Journal {
+            state: Mutex::new(JournalState {
+                entries,
+                file: OpenOptions::new().create(true).append(true).open(&path)?,
+            }),
+            path,
+            replaying: tokio::sync::Mutex::new(()),
+        };
+
+        // drops a cut off line, so the next append starts on a line of its own
+        journal.compact()?;
+
+        Ok(journal)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // number of writes waiting to be replayed
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // sends the write right away unless older writes are still waiting. Transient failures
+    // are journaled and answer with None, just like a write that had to queue up
+    pub async fn write(
+        &self,
+        store: &dyn XpStore,
+        write: XpWrite,
+    ) -> DbResult<Option<XpIncrement>> {
+        let entry = JournalEntry::new(write);
+
+        if !self.is_empty() {
+            self.append(entry)?;
+            return Ok(None);
+        }
+
+        match store.apply_write(&entry).await {
+            Ok(increment) => Ok(increment),
+            Err(why) if why.is_transient() => {
+                self.append(entry)?;
+                Ok(None)
+            }
+            Err(why) => Err(why),
+        }
+    }
+
+    pub fn append(&self, entry: JournalEntry) -> DbResult<()> {
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+
+        let mut state = self.state.lock().unwrap();
+        state.file.write_all(line.as_bytes())?;
+        state.file.sync_data()?;
+        state.entries.push_back(entry);
+
+        Ok(())
+    }
+
+    // applies waiting writes oldest first, stops at the first one that fails transiently
+    pub async fn replay(&self, store: &dyn XpStore) -> DbResult<ReplayReport> {
+        let _replaying = self.replaying.lock().await;
+        let mut report = ReplayReport::default();
+
+        loop {
+            let entry = match self.state.lock().unwrap().entries.front() {
+                Some(entry) => entry.clone(),
+                None => break,
+            };
+
+            match store.apply_write(&entry).await {
+                Ok(_) => report.applied += 1,
+                Err(why) if why.is_transient() => break,
+                Err(why) => report.dropped.push((entry, why)),
+            }
+
+            // only replay removes entries, so the front is still the entry that was applied
+            self.state.lock().unwrap().entries.pop_front();
+        }
+
+        if report.applied > 0 || !report.dropped.is_empty() {
+            self.compact()?;
+        }
+        report.remaining = self.len();
+
+        Ok(report)
+    }
+
+    // rewrites the file with the entries that are still waiting
+    fn compact(&self) -> DbResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let temporary = self.path.with_extension("tmp");
+
+        let mut file = File::create(&temporary)?;
+        for entry in &state.entries {
+            let mut line = serde_json::to_string(entry)?;
+            line.push('\n');
+            file.write_all(line.as_bytes())?;
+        }
+        file.sync_all()?;
+
+        fs::rename(&temporary, &self.path)?;
+        state.file = OpenOptions::new().append(true).open(&self.path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, time::Duration};
+
+    use tempfile::TempDir;
+    use xp_mock_api::{Faults, Fixtures, MockApi};
+
+    use super::{Journal, JournalEntry, XpWrite};
+    use crate::{guild_member::XpReason, retry::RetryPolicy, store::XpStore, ApiClient};
+
+    const GUILD: u64 = 1;
+    const MEMBER: u64 = 2;
+
+    // the directory goes away with the journal and its compaction files once it is dropped
+    fn path() -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("xp.journal");
+
+        (dir, path)
+    }
+
+    async fn store() -> (MockApi, ApiClient) {
+        let api = MockApi::new(Fixtures::default(), None);
+        let base_url = api.clone().spawn().await.unwrap();
+        let client = ApiClient::builder(base_url, "")
+            .retry(RetryPolicy::none())
+            .circuit_breaker(0, Duration::ZERO)
+            .build()
+            .unwrap();
+
+        (api, client)
+    }
+
+    fn add(delta: i64) -> XpWrite {
+        XpWrite::AddXp {
+            guild_id: GUILD,
+            member_id: MEMBER,
+            delta,
+            reason: XpReason::Message,
+            user_data: None,
+        }
+    }
+
+    fn set(xp: u64) -> XpWrite {
+        XpWrite::SetXp {
+            guild_id: GUILD,
+            member_id: MEMBER,
+            xp,
+            user_data: Default::default(),
+            reason: XpReason::Set,
+            actor: None,
+        }
+    }
+
+    fn xp(api: &MockApi) -> u64 {
+        api.member(GUILD, MEMBER)
+            .and_then(|member| member["xp"].as_u64())
+            .unwrap_or(0)
+    }
+
+    #[tokio::test]
+    async fn writes_go_through_while_nothing_waits() {
+        let (api, store) = store().await;
+        let (_dir, path) = path();
+        let journal = Journal::open(&path).unwrap();
+
+        let increment = journal.write(&store, add(5)).await.unwrap();
+        assert!(increment.is_some());
+        assert!(journal.is_empty());
+        assert_eq!(xp(&api), 5);
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_journaled_and_queue_later_writes() {
+        let (api, store) = store().await;
+        let (_dir, path) = path();
+        let journal = Journal::open(&path).unwrap();
+        api.set_faults(Faults {
+            fail_next: 1,
+            ..Faults::default()
+        });
+
+        assert!(journal.write(&store, add(5)).await.unwrap().is_none());
+        // the api is fine again, but the second write must not overtake the first
+        assert!(journal.write(&store, add(3)).await.unwrap().is_none());
+        assert_eq!(journal.len(), 2);
+        assert_eq!(api.requests().len(), 1);
+        assert_eq!(xp(&api), 0);
+    }
+
+    #[tokio::test]
+    async fn replay_applies_in_order() {
+        let (api, store) = store().await;
+        let (_dir, path) = path();
+        let journal = Journal::open(&path).unwrap();
+
+        journal.append(JournalEntry::new(set(10))).unwrap();
+        journal.append(JournalEntry::new(add(5))).unwrap();
+
+        let report = journal.replay(&store).await.unwrap();
+        assert_eq!((report.applied, report.remaining), (2, 0));
+        assert!(report.dropped.is_empty());
+        assert_eq!(xp(&api), 15);
+    }
+
+    #[tokio::test]
+    async fn replay_does_not_apply_an_entry_twice() {
+        let (api, store) = store().await;
+        let (_dir, path) = path();
+        let journal = Journal::open(&path).unwrap();
+
+        // applied right before a crash, but still in the journal
+        let entry = JournalEntry::new(add(5));
+        store.apply_write(&entry).await.unwrap();
+        journal.append(entry).unwrap();
+
+        let report = journal.replay(&store).await.unwrap();
+        assert_eq!((report.applied, report.remaining), (1, 0));
+        assert_eq!(xp(&api), 5);
+    }
+
+    #[tokio::test]
+    async fn replay_stops_at_a_transient_failure() {
+        let (api, store) = store().await;
+        let (_dir, path) = path();
+        let journal = Journal::open(&path).unwrap();
+
+        journal.append(JournalEntry::new(add(5))).unwrap();
+        journal.append(JournalEntry::new(add(3))).unwrap();
+        api.set_faults(Faults {
+            fail_next: 1,
+            ..Faults::default()
+        });
+
+        let report = journal.replay(&store).await.unwrap();
+        assert_eq!((report.applied, report.remaining), (0, 2));
+
+        let report = journal.replay(&store).await.unwrap();
+        assert_eq!((report.applied, report.remaining), (2, 0));
+        assert_eq!(xp(&api), 8);
+    }
+
+    #[tokio::test]
+    async fn entries_survive_a_restart() {
+        let (api, store) = store().await;
+        let (_dir, path) = path();
+
+        let journal = Journal::open(&path).unwrap();
+        journal.append(JournalEntry::new(add(5))).unwrap();
+        drop(journal);
+
+        let journal = Journal::open(&path).unwrap();
+        assert_eq!(journal.len(), 1);
+        journal.replay(&store).await.unwrap();
+        drop(journal);
+
+        assert!(Journal::open(&path).unwrap().is_empty());
+        assert_eq!(xp(&api), 5);
+    }
+
+    #[test]
+    fn a_cut_off_last_line_is_skipped() {
+        let (_dir, path) = path();
+        let entry = serde_json::to_string(&JournalEntry::new(add(5))).unwrap();
+        fs::write(&path, format!("{}\n{}", entry, &entry[..entry.len() / 2])).unwrap();
+
+        let journal = Journal::open(&path).unwrap();
+        assert_eq!(journal.len(), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n", entry));
+    }
+
+    #[test]
+    fn a_corrupt_line_in_the_middle_is_refused() {
+        let (_dir, path) = path();
+        let entry = serde_json::to_string(&JournalEntry::new(add(5))).unwrap();
+        fs::write(&path, format!("{{\n{}\n", entry)).unwrap();
+
+        assert!(Journal::open(&path).is_err());
+    }
+}
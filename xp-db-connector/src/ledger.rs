@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{guild_member::XpReason, store::XpStore, DbResult};
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct LedgerResponse {
+    pub success: bool,
+    pub message: String,
+    pub content: Option<Vec<LedgerEntry>>,
+}
+
+/*
+    One change of a member's xp.
+    > Entries are written by the store in the same step as the change itself, so a change
+    > without an entry never reached the store.
+    > `actor` is the user who made the change, if it wasn't the member (admin commands, resets).
+*/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LedgerEntry {
+    pub delta: i64,
+    pub xp: u64,
+    pub reason: XpReason,
+    #[serde(default)]
+    pub actor: Option<String>,
+    pub timestamp: u64,
+}
+
+impl LedgerEntry {
+    // newest entries first
+    pub async fn for_member(
+        store: &dyn XpStore,
+        guild_id: u64,
+        member_id: u64,
+        limit: u32,
+    ) -> DbResult<Vec<LedgerEntry>> {
+        store.member_ledger(guild_id, member_id, limit).await
+    }
+}
@@ -0,0 +1,183 @@
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use serde_json::Value;
+
+/*
+    Documents written by older versions of the api are upgraded before they are deserialized.
+    > Every document carries a `schemaVersion`, documents without one are version 0.
+    > `upgrade` moves a document up by exactly one version, so old documents run through every
+    > step in order.
+    > Fields that are missing after that are filled in by the `Default` impls of the models.
+*/
+pub trait Versioned: DeserializeOwned {
+    const SCHEMA_VERSION: u32;
+
+    // upgrades `document` from `version` to `version + 1`
+    fn upgrade(document: &mut Value, version: u32);
+
+    fn from_document(mut document: Value) -> serde_json::Result<Self> {
+        let mut version = document
+            .get("schemaVersion")
+            .and_then(Value::as_u64)
+            .unwrap_or(0) as u32;
+
+        while version < Self::SCHEMA_VERSION {
+            Self::upgrade(&mut document, version);
+            version += 1;
+        }
+
+        if let Some(document) = document.as_object_mut() {
+            document.insert("schemaVersion".to_string(), version.into());
+        }
+
+        serde_json::from_value(document)
+    }
+}
+
+// `deserialize_with` for the `content` of api responses
+pub(crate) fn content<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Versioned,
+{
+    match Option::<Value>::deserialize(deserializer)? {
+        Some(document) => T::from_document(document)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+// `deserialize_with` for documents embedded in other models, like the guild of a snapshot
+pub(crate) fn document<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Versioned,
+{
+    T::from_document(Value::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+// replaces a missing or null value at `pointer` (like "/ignored/channels") with `value`
+pub(crate) fn fill_null(document: &mut Value, pointer: &str, value: Value) {
+    let (parent, key) = match pointer.rsplit_once('/') {
+        Some(split) => split,
+        None => return,
+    };
+
+    let parent = match document.pointer_mut(parent) {
+        Some(Value::Object(parent)) => parent,
+        _ => return,
+    };
+
+    match parent.get(key) {
+        None | Some(Value::Null) => {
+            parent.insert(key.to_string(), value);
+        }
+        Some(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    use super::{fill_null, Versioned};
+    use crate::{guild::Guild, guild_member::GuildMember};
+
+    // records every upgrade step it runs through
+    #[derive(Deserialize, Debug)]
+    struct Document {
+        #[serde(rename = "schemaVersion")]
+        schema_version: u32,
+        steps: Vec<u32>,
+    }
+
+    impl Versioned for Document {
+        const SCHEMA_VERSION: u32 = 3;
+
+        fn upgrade(document: &mut Value, version: u32) {
+            document["steps"]
+                .as_array_mut()
+                .unwrap()
+                .push(version.into());
+        }
+    }
+
+    #[test]
+    fn documents_without_a_version_run_through_every_step() {
+        let document = Document::from_document(json!({ "steps": [] })).unwrap();
+
+        assert_eq!(document.steps, vec![0, 1, 2]);
+        assert_eq!(document.schema_version, 3);
+    }
+
+    #[test]
+    fn upgrades_start_at_the_version_of_the_document() {
+        let document = Document::from_document(json!({ "schemaVersion": 2, "steps": [] })).unwrap();
+
+        assert_eq!(document.steps, vec![2]);
+        assert_eq!(document.schema_version, 3);
+    }
+
+    #[test]
+    fn current_and_newer_documents_are_left_alone() {
+        for version in [3, 4] {
+            let document =
+                Document::from_document(json!({ "schemaVersion": version, "steps": [] })).unwrap();
+
+            assert!(document.steps.is_empty());
+            assert_eq!(document.schema_version, version);
+        }
+    }
+
+    #[test]
+    fn fill_null_only_replaces_missing_and_null_values() {
+        let mut document = json!({ "ignored": { "roles": ["1"], "channels": null } });
+
+        fill_null(&mut document, "/ignored/roles", json!([]));
+        fill_null(&mut document, "/ignored/channels", json!([]));
+        fill_null(&mut document, "/ignored/categories", json!([]));
+        // no parent to fill in
+        fill_null(&mut document, "/boosts/categories", json!([]));
+
+        assert_eq!(
+            document,
+            json!({ "ignored": { "roles": ["1"], "channels": [], "categories": [] } })
+        );
+    }
+
+    #[test]
+    fn old_guilds_get_empty_lists() {
+        let guild = Guild::from_document(json!({
+            "ignored": { "roles": ["1"], "channels": null, "categories": null },
+            "boosts": { "roles": [], "channels": [], "categories": null },
+        }))
+        .unwrap();
+
+        assert_eq!(guild.schema_version, 1);
+        assert_eq!(guild.ignored.roles, vec!["1".to_string()]);
+        assert!(guild.ignored.channels.is_empty());
+        assert!(guild.ignored.categories.is_empty());
+        assert!(guild.boosts.categories.is_empty());
+    }
+
+    #[test]
+    fn old_members_keep_their_daily_streak() {
+        let member =
+            GuildMember::from_document(json!({ "xp": 10, "streaks": { "daily": 4 } })).unwrap();
+
+        assert_eq!(member.schema_version, 1);
+        assert_eq!(member.streaks.game_daily, Some(4));
+        assert_eq!(member.streaks.daily, None);
+    }
+
+    #[test]
+    fn moved_streaks_are_not_overwritten() {
+        let member = GuildMember::from_document(json!({
+            "streaks": { "daily": 4, "game_daily": 7 },
+        }))
+        .unwrap();
+
+        assert_eq!(member.streaks.game_daily, Some(7));
+    }
+}
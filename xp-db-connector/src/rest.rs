@@ -0,0 +1,319 @@
+use async_trait::async_trait;
+
+use crate::{
+    guild::{Guild, GuildPatch, GuildResponse},
+    guild_member::{
+        GuildMember, GuildMemberData, GuildMemberPatch, GuildMemberResponse, XPBatchBody,
+        XPIncrementBody, XPPostBody, XpBatchEntry, XpIncrement, XpIncrementResponse, XpReason,
+    },
+    guild_premium::{GuildPremium, GuildPremiumResponse},
+    journal::{JournalEntry, XpWrite},
+    ledger::{LedgerEntry, LedgerResponse},
+    snapshot::{self, GuildMembersResponse, MemberSnapshot, MembersImportBody},
+    store::XpStore,
+    user::{User, UserPremium, UserPremiumResponse, UserResponse},
+    user_background::{
+        BackgroundUpload, BackgroundUploadBody, UserBackground, UserBackgroundResponse,
+        UserBackgroundsResponse,
+    },
+    ApiClient, DbResult,
+};
+
+#[async_trait]
+impl XpStore for ApiClient {
+    async fn guild(&self, guild_id: u64) -> DbResult<Guild> {
+        self.guild_cache()
+            .get_or_load(guild_id, async {
+                let response = self
+                    .get_json::<GuildResponse>(&format!("/guild/{}", guild_id))
+                    .await?;
+
+                crate::unwrap_content(response.success, response.message, response.content)
+            })
+            .await
+    }
+
+    async fn patch_guild(&self, guild_id: u64, patch: GuildPatch) -> DbResult<()> {
+        self.patch_json(&format!("/guild/{}", guild_id), patch)
+            .await?;
+        ApiClient::invalidate_guild(self, guild_id);
+
+        Ok(())
+    }
+
+    async fn delete_guild(&self, guild_id: u64) -> DbResult<()> {
+        self.delete_json(&format!("/guild/{}", guild_id)).await
+    }
+
+    async fn delete_guild_xp(&self, guild_id: u64, actor: Option<u64>) -> DbResult<()> {
+        match actor {
+            Some(actor) => {
+                self.delete_json(&format!("/guild/{}/members/xp?actor={}", guild_id, actor))
+                    .await
+            }
+            None => {
+                self.delete_json(&format!("/guild/{}/members/xp", guild_id))
+                    .await
+            }
+        }
+    }
+
+    async fn guild_premium(&self, guild_id: u64) -> DbResult<GuildPremium> {
+        self.guild_premium_cache()
+            .get_or_load(guild_id, async {
+                let response = self
+                    .get_json::<GuildPremiumResponse>(&format!("/guild/{}/premium", guild_id))
+                    .await?;
+
+                crate::unwrap_content(response.success, response.message, response.content)
+            })
+            .await
+    }
+
+    async fn member(&self, guild_id: u64, member_id: u64) -> DbResult<GuildMember> {
+        let response = self
+            .get_json::<GuildMemberResponse>(&format!("/guild/{}/member/{}", guild_id, member_id))
+            .await?;
+
+        crate::unwrap_content(response.success, response.message, response.content)
+    }
+
+    async fn guild_members(&self, guild_id: u64) -> DbResult<Vec<(u64, GuildMember)>> {
+        let response = self
+            .get_json::<GuildMembersResponse>(&format!("/guild/{}/members", guild_id))
+            .await?;
+
+        snapshot::members_from_content(crate::unwrap_content(
+            response.success,
+            response.message,
+            response.content,
+        )?)
+    }
+
+    async fn import_members(
+        &self,
+        guild_id: u64,
+        members: Vec<MemberSnapshot>,
+        actor: Option<u64>,
+    ) -> DbResult<()> {
+        self.post_json(
+            &format!("/guild/{}/members/import", guild_id),
+            MembersImportBody {
+                members,
+                actor: actor.map(|actor| actor.to_string()),
+            },
+        )
+        .await
+    }
+
+    async fn set_member(&self, guild_id: u64, member_id: u64, member: GuildMember) -> DbResult<()> {
+        self.patch_json(&format!("/guild/{}/member/{}", guild_id, member_id), member)
+            .await
+    }
+
+    async fn patch_member(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        patch: GuildMemberPatch,
+    ) -> DbResult<()> {
+        self.patch_json(&format!("/guild/{}/member/{}", guild_id, member_id), patch)
+            .await
+    }
+
+    async fn set_member_xp(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        xp: u64,
+        user_data: GuildMemberData,
+        reason: XpReason,
+        actor: Option<u64>,
+    ) -> DbResult<()> {
+        self.post_json(
+            &format!("/guild/{}/member/{}/direct/xp", guild_id, member_id),
+            XPPostBody {
+                xp,
+                userData: user_data,
+                reason,
+                actor: actor.map(|actor| actor.to_string()),
+            },
+        )
+        .await
+    }
+
+    async fn add_member_xp(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        delta: i64,
+        reason: XpReason,
+        user_data: Option<GuildMemberData>,
+        actor: Option<u64>,
+    ) -> DbResult<XpIncrement> {
+        let response = self
+            .post_json_response::<_, XpIncrementResponse>(
+                &format!("/guild/{}/member/{}/xp/increment", guild_id, member_id),
+                XPIncrementBody {
+                    delta,
+                    reason,
+                    userData: user_data,
+                    actor: actor.map(|actor| actor.to_string()),
+                },
+            )
+            .await?;
+
+        crate::unwrap_content(response.success, response.message, response.content)
+    }
+
+    async fn add_member_xp_batch(&self, entries: Vec<XpBatchEntry>) -> DbResult<()> {
+        self.post_json(
+            "/guilds/members/xp/batch",
+            XPBatchBody {
+                increments: entries,
+            },
+        )
+        .await
+    }
+
+    async fn member_ledger(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        limit: u32,
+    ) -> DbResult<Vec<LedgerEntry>> {
+        let response = self
+            .get_json::<LedgerResponse>(&format!(
+                "/guild/{}/member/{}/ledger?limit={}",
+                guild_id, member_id, limit
+            ))
+            .await?;
+
+        crate::unwrap_content(response.success, response.message, response.content)
+    }
+
+    async fn apply_write(&self, entry: &JournalEntry) -> DbResult<Option<XpIncrement>> {
+        match &entry.write {
+            XpWrite::SetXp {
+                guild_id,
+                member_id,
+                xp,
+                user_data,
+                reason,
+                actor,
+            } => {
+                self.post_json_idempotent(
+                    &format!("/guild/{}/member/{}/direct/xp", guild_id, member_id),
+                    XPPostBody {
+                        xp: *xp,
+                        userData: user_data.clone(),
+                        reason: *reason,
+                        actor: actor.map(|actor| actor.to_string()),
+                    },
+                    &entry.id,
+                )
+                .await?;
+
+                Ok(None)
+            }
+            XpWrite::AddXp {
+                guild_id,
+                member_id,
+                delta,
+                reason,
+                user_data,
+            } => {
+                let response = self
+                    .post_json_response_idempotent::<_, XpIncrementResponse>(
+                        &format!("/guild/{}/member/{}/xp/increment", guild_id, member_id),
+                        XPIncrementBody {
+                            delta: *delta,
+                            reason: *reason,
+                            userData: user_data.clone(),
+                            actor: None,
+                        },
+                        &entry.id,
+                    )
+                    .await?;
+
+                crate::unwrap_content(response.success, response.message, response.content)
+                    .map(Some)
+            }
+            XpWrite::AddXpBatch { entries } => {
+                self.post_json_idempotent(
+                    "/guilds/members/xp/batch",
+                    XPBatchBody {
+                        increments: entries.clone(),
+                    },
+                    &entry.id,
+                )
+                .await?;
+
+                Ok(None)
+            }
+        }
+    }
+
+    async fn user(&self, user_id: u64) -> DbResult<User> {
+        let response = self
+            .get_json::<UserResponse>(&format!("/user/{}", user_id))
+            .await?;
+
+        crate::unwrap_content(response.success, response.message, response.content)
+    }
+
+    async fn set_user(&self, user_id: u64, user: User) -> DbResult<()> {
+        self.patch_json(&format!("/user/{}", user_id), user).await
+    }
+
+    async fn user_premium(&self, user_id: u64) -> DbResult<UserPremium> {
+        let response = self
+            .get_json::<UserPremiumResponse>(&format!("/user/{}/premium", user_id))
+            .await?;
+
+        crate::unwrap_content(response.success, response.message, response.content)
+    }
+
+    async fn user_backgrounds(&self, user_id: u64) -> DbResult<Vec<UserBackground>> {
+        let response = self
+            .get_json::<UserBackgroundsResponse>(&format!("/user/{}/backgrounds", user_id))
+            .await?;
+
+        crate::unwrap_content(response.success, response.message, response.content)
+    }
+
+    async fn user_background(&self, user_id: u64, background_id: i32) -> DbResult<UserBackground> {
+        let response = self
+            .get_json::<UserBackgroundResponse>(&format!(
+                "/user/{}/background/{}",
+                user_id, background_id
+            ))
+            .await?;
+
+        crate::unwrap_content(response.success, response.message, response.content)
+    }
+
+    async fn upload_user_background(
+        &self,
+        user_id: u64,
+        upload: BackgroundUpload,
+    ) -> DbResult<UserBackground> {
+        let response = self
+            .post_json_response::<_, UserBackgroundResponse>(
+                &format!("/user/{}/backgrounds", user_id),
+                BackgroundUploadBody::from(&upload),
+            )
+            .await?;
+
+        crate::unwrap_content(response.success, response.message, response.content)
+    }
+
+    async fn delete_user_background(&self, user_id: u64, background_id: i32) -> DbResult<()> {
+        self.delete_json(&format!("/user/{}/background/{}", user_id, background_id))
+            .await
+    }
+
+    fn invalidate_guild(&self, guild_id: u64) {
+        ApiClient::invalidate_guild(self, guild_id);
+    }
+}
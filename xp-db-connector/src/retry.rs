@@ -0,0 +1,216 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+
+use crate::{DbError, DbResult};
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// How often a failed request is repeated before the error is returned.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every further attempt.
+    pub base_delay: Duration,
+    /// Upper bound for a single backoff delay.
+    pub max_delay: Duration,
+    /// Longest `Retry-After` of a 429 the client is willing to wait for.
+    pub max_retry_after: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+            max_retry_after: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        }
+    }
+
+    // exponential backoff with full jitter, so shards don't retry in lockstep
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+
+        Duration::from_millis(rand::thread_rng().gen_range(0..=delay.as_millis() as u64))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<CircuitState>,
+}
+
+#[derive(Debug, Default)]
+struct CircuitState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    // set while the single trial request of the half open state is in flight
+    probing_since: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            failure_threshold,
+            cooldown,
+            state: Mutex::new(CircuitState::default()),
+        }
+    }
+
+    pub(crate) fn check(&self) -> DbResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        // everyone else waits for the trial request, unless it was dropped and never reported
+        // back, then the next caller becomes the trial
+        if let Some(probing_since) = state.probing_since {
+            let probe_until = probing_since + self.cooldown;
+            if now < probe_until {
+                return Err(DbError::CircuitOpen {
+                    retry_in: probe_until - now,
+                });
+            }
+
+            state.probing_since = Some(now);
+            return Ok(());
+        }
+
+        if let Some(open_until) = state.open_until {
+            if now < open_until {
+                return Err(DbError::CircuitOpen {
+                    retry_in: open_until - now,
+                });
+            }
+
+            // half open: a single request goes through, if it fails the breaker opens again
+            state.open_until = None;
+            state.probing_since = Some(now);
+            state.consecutive_failures = self.failure_threshold.saturating_sub(1);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.open_until = None;
+        state.probing_since = None;
+    }
+
+    pub(crate) fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        state.probing_since = None;
+
+        if state.consecutive_failures >= self.failure_threshold {
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::CircuitBreaker;
+    use crate::DbError;
+
+    const COOLDOWN: Duration = Duration::from_millis(50);
+
+    fn opened() -> CircuitBreaker {
+        let breaker = CircuitBreaker::new(2, COOLDOWN);
+        breaker.record_failure();
+        breaker.record_failure();
+        breaker
+    }
+
+    #[test]
+    fn opens_after_failure_threshold() {
+        let breaker = CircuitBreaker::new(2, COOLDOWN);
+
+        breaker.record_failure();
+        assert!(breaker.check().is_ok());
+
+        breaker.record_failure();
+        assert!(matches!(
+            breaker.check(),
+            Err(DbError::CircuitOpen { retry_in }) if retry_in <= COOLDOWN
+        ));
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let breaker = CircuitBreaker::new(2, COOLDOWN);
+
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+
+        assert!(breaker.check().is_ok());
+    }
+
+    #[test]
+    fn half_open_admits_a_single_trial() {
+        let breaker = opened();
+        thread::sleep(COOLDOWN);
+
+        assert!(breaker.check().is_ok());
+        assert!(matches!(breaker.check(), Err(DbError::CircuitOpen { .. })));
+        assert!(matches!(breaker.check(), Err(DbError::CircuitOpen { .. })));
+    }
+
+    #[test]
+    fn successful_trial_closes() {
+        let breaker = opened();
+        thread::sleep(COOLDOWN);
+
+        assert!(breaker.check().is_ok());
+        breaker.record_success();
+
+        assert!(breaker.check().is_ok());
+        assert!(breaker.check().is_ok());
+    }
+
+    #[test]
+    fn failed_trial_opens_again() {
+        let breaker = opened();
+        thread::sleep(COOLDOWN);
+
+        assert!(breaker.check().is_ok());
+        breaker.record_failure();
+
+        assert!(matches!(breaker.check(), Err(DbError::CircuitOpen { .. })));
+        thread::sleep(COOLDOWN);
+        assert!(breaker.check().is_ok());
+    }
+
+    #[test]
+    fn dropped_trial_is_replaced() {
+        let breaker = opened();
+        thread::sleep(COOLDOWN);
+
+        // the trial never reports back
+        assert!(breaker.check().is_ok());
+        thread::sleep(COOLDOWN);
+
+        assert!(breaker.check().is_ok());
+        assert!(matches!(breaker.check(), Err(DbError::CircuitOpen { .. })));
+    }
+}
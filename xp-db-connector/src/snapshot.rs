@@ -0,0 +1,302 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    guild::{Guild, GuildPatch},
+    guild_member::{GuildMember, GuildMemberStreaks},
+    migrate::{self, Versioned},
+    store::XpStore,
+    DbError, DbResult,
+};
+
+// version of the snapshot document itself, the guild inside carries its own `schemaVersion`
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// largest snapshot that is written or read, small enough to be sent as a discord attachment
+pub const MAX_SNAPSHOT_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct GuildMembersResponse {
+    pub success: bool,
+    pub message: String,
+    // keyed by member id
+    pub content: Option<HashMap<String, Value>>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Clone, Debug)]
+pub struct MembersImportBody {
+    pub(crate) members: Vec<MemberSnapshot>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) actor: Option<String>,
+}
+
+/*
+    Everything needed to bring a guild back after a reset, as a json document.
+    > Holds the full guild settings and the xp and streaks of every member.
+    > Ids are strings, like everywhere else in the api.
+*/
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GuildSnapshot {
+    pub version: u32,
+    pub guildId: String,
+    pub createdAt: u64,
+    #[serde(deserialize_with = "migrate::document")]
+    pub guild: Guild,
+    pub members: Vec<MemberSnapshot>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MemberSnapshot {
+    pub memberId: String,
+    pub xp: u64,
+    #[serde(default)]
+    pub streaks: GuildMemberStreaks,
+}
+
+impl GuildSnapshot {
+    pub async fn capture(store: &dyn XpStore, guild_id: u64) -> DbResult<GuildSnapshot> {
+        // a backup has to include settings changed on the dashboard within the cache ttl
+        store.invalidate_guild(guild_id);
+        let guild = store.guild(guild_id).await?;
+        let mut members = store
+            .guild_members(guild_id)
+            .await?
+            .into_iter()
+            .map(|(member_id, member)| MemberSnapshot {
+                memberId: member_id.to_string(),
+                xp: member.xp,
+                streaks: member.streaks,
+            })
+            .collect::<Vec<MemberSnapshot>>();
+        members.sort_by_key(|member| std::cmp::Reverse(member.xp));
+
+        Ok(GuildSnapshot {
+            version: SNAPSHOT_VERSION,
+            guildId: guild_id.to_string(),
+            createdAt: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            guild,
+            members,
+        })
+    }
+
+    // reads an uploaded snapshot, which has to belong to `guild_id`
+    pub fn parse(bytes: &[u8], guild_id: u64) -> DbResult<GuildSnapshot> {
+        if bytes.len() > MAX_SNAPSHOT_SIZE {
+            return Err(DbError::Invalid(format!(
+                "the snapshot is larger than {} bytes",
+                MAX_SNAPSHOT_SIZE
+            )));
+        }
+
+        let document = serde_json::from_slice::<Value>(bytes)
+            .map_err(|why| DbError::Invalid(format!("not a snapshot: {}", why)))?;
+
+        let version = document
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| DbError::Invalid("the snapshot has no version".to_string()))?;
+        if version == 0 || version > SNAPSHOT_VERSION as u64 {
+            return Err(DbError::Invalid(format!(
+                "snapshot version {} is not supported, this version reads up to {}",
+                version, SNAPSHOT_VERSION
+            )));
+        }
+
+        let snapshot = serde_json::from_value::<GuildSnapshot>(document)
+            .map_err(|why| DbError::Invalid(format!("not a snapshot: {}", why)))?;
+
+        if snapshot.guildId != guild_id.to_string() {
+            return Err(DbError::Invalid(format!(
+                "the snapshot belongs to guild {}",
+                snapshot.guildId
+            )));
+        }
+
+        let mut seen = HashSet::new();
+        for member in &snapshot.members {
+            if member.memberId.parse::<u64>().unwrap_or(0) == 0 {
+                return Err(DbError::Invalid(format!(
+                    "invalid member id {}",
+                    member.memberId
+                )));
+            }
+            if !seen.insert(&member.memberId) {
+                return Err(DbError::Invalid(format!(
+                    "member {} is in the snapshot twice",
+                    member.memberId
+                )));
+            }
+        }
+
+        Ok(snapshot)
+    }
+
+    // compact, a snapshot that can't be read back by `parse` is refused right away
+    pub fn to_json(&self) -> DbResult<Vec<u8>> {
+        let json = serde_json::to_vec(self)?;
+
+        if json.len() > MAX_SNAPSHOT_SIZE {
+            return Err(DbError::Invalid(format!(
+                "the snapshot is {} bytes, more than the {} bytes a backup can have",
+                json.len(),
+                MAX_SNAPSHOT_SIZE
+            )));
+        }
+
+        Ok(json)
+    }
+
+    // file name for the snapshot as an attachment
+    pub fn file_name(&self) -> String {
+        format!("xp-snapshot-{}-{}.json", self.guildId, self.createdAt)
+    }
+
+    // writes the settings back and sets every member of the snapshot to its xp and streaks,
+    // members that are not in the snapshot are left alone
+    pub async fn restore(
+        &self,
+        store: &dyn XpStore,
+        guild_id: u64,
+        actor: Option<u64>,
+    ) -> DbResult<()> {
+        let guild = self.guild.clone();
+
+        store
+            .patch_guild(
+                guild_id,
+                GuildPatch {
+                    values: Some(guild.values),
+                    modules: Some(guild.modules),
+                    ignored: Some(guild.ignored),
+                    boosts: Some(guild.boosts),
+                    levelroles: Some(guild.levelroles),
+                    announce: Some(guild.announce),
+                    logs: Some(guild.logs),
+                },
+            )
+            .await?;
+
+        store
+            .import_members(guild_id, self.members.clone(), actor)
+            .await
+    }
+}
+
+// the member documents of a guild response, upgraded like every other member
+pub(crate) fn members_from_content(
+    content: HashMap<String, Value>,
+) -> DbResult<Vec<(u64, GuildMember)>> {
+    content
+        .into_iter()
+        .map(|(member_id, document)| {
+            let member_id = member_id.parse::<u64>().map_err(|_| {
+                DbError::Deserialize(serde::de::Error::custom(format!(
+                    "invalid member id {}",
+                    member_id
+                )))
+            })?;
+
+            Ok((member_id, GuildMember::from_document(document)?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use xp_mock_api::{Fixtures, MockApi};
+
+    use super::{GuildSnapshot, MemberSnapshot, MAX_SNAPSHOT_SIZE, SNAPSHOT_VERSION};
+    use crate::{
+        guild::{Guild, GuildPatch},
+        store::XpStore,
+        ApiClient, DbError,
+    };
+
+    fn snapshot(members: usize) -> GuildSnapshot {
+        GuildSnapshot {
+            version: SNAPSHOT_VERSION,
+            guildId: "1".to_string(),
+            createdAt: 0,
+            guild: Guild::default(),
+            members: (1..=members)
+                .map(|member_id| MemberSnapshot {
+                    memberId: member_id.to_string(),
+                    xp: member_id as u64,
+                    streaks: Default::default(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn round_trips_compactly() {
+        let json = snapshot(3).to_json().unwrap();
+        assert!(!json.contains(&b'\n'));
+
+        let parsed = GuildSnapshot::parse(&json, 1).unwrap();
+        assert_eq!(parsed.members.len(), 3);
+        assert_eq!(parsed.members[2].xp, 3);
+    }
+
+    #[test]
+    fn refuses_snapshots_that_could_not_be_restored() {
+        // every member takes well over 40 bytes
+        let members = MAX_SNAPSHOT_SIZE / 40;
+        assert!(matches!(
+            snapshot(members).to_json(),
+            Err(DbError::Invalid(_))
+        ));
+        assert!(matches!(
+            GuildSnapshot::parse(&vec![b' '; MAX_SNAPSHOT_SIZE + 1], 1),
+            Err(DbError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_snapshots_of_other_guilds() {
+        let json = snapshot(1).to_json().unwrap();
+        assert!(matches!(
+            GuildSnapshot::parse(&json, 2),
+            Err(DbError::Invalid(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn captures_settings_changed_elsewhere() {
+        let base_url = MockApi::new(Fixtures::default(), None)
+            .spawn()
+            .await
+            .unwrap();
+        let bot = ApiClient::builder(&base_url, "").build().unwrap();
+        let dashboard = ApiClient::builder(&base_url, "").build().unwrap();
+
+        assert!(bot.guild(1).await.unwrap().modules.games);
+
+        let mut modules = Guild::default().modules;
+        modules.games = false;
+        dashboard
+            .patch_guild(
+                1,
+                GuildPatch {
+                    modules: Some(modules),
+                    ..GuildPatch::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let snapshot = GuildSnapshot::capture(&bot, 1).await.unwrap();
+        assert!(!snapshot.guild.modules.games);
+    }
+}
@@ -0,0 +1,765 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::{
+    guild::{Guild, GuildPatch},
+    guild_member::{
+        GuildMember, GuildMemberData, GuildMemberPatch, XpBatchEntry, XpIncrement, XpReason,
+    },
+    guild_premium::GuildPremium,
+    journal::{JournalEntry, XpWrite},
+    ledger::LedgerEntry,
+    migrate::Versioned,
+    snapshot::MemberSnapshot,
+    store::XpStore,
+    user::{User, UserPremium},
+    user_background::{BackgroundUpload, UserBackground},
+    DbError, DbResult,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS guilds (
+        guild_id TEXT PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS members (
+        guild_id TEXT NOT NULL,
+        member_id TEXT NOT NULL,
+        data TEXT NOT NULL,
+        PRIMARY KEY (guild_id, member_id)
+    );
+    CREATE TABLE IF NOT EXISTS users (
+        user_id TEXT PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS ledger (
+        entry_id INTEGER PRIMARY KEY AUTOINCREMENT,
+        guild_id TEXT NOT NULL,
+        member_id TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS ledger_member ON ledger (guild_id, member_id);
+    CREATE TABLE IF NOT EXISTS backgrounds (
+        background_id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id TEXT NOT NULL,
+        format TEXT NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS applied_writes (
+        write_id TEXT PRIMARY KEY,
+        result TEXT
+    );
+";
+
+/*
+    Embedded backend for self hosting and tests.
+    > Documents are stored as json, in the same shape the api returns them.
+    > Guilds, members and users that don't exist yet start out with the models' defaults.
+    > Premium is always unlocked, there is nobody to pay.
+    > Uploaded backgrounds are kept as blobs, there is no url to render them from.
+    > Every xp change writes its ledger entry in the same transaction.
+    > Ids of journaled writes are kept with their result, a replayed id answers with that result.
+*/
+#[derive(Clone)]
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<Path>) -> DbResult<SqliteStore> {
+        SqliteStore::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> DbResult<SqliteStore> {
+        SqliteStore::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> DbResult<SqliteStore> {
+        conn.execute_batch(SCHEMA)?;
+
+        Ok(SqliteStore {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    // rusqlite blocks, so every call runs on the blocking pool; the single connection also
+    // makes each call atomic
+    async fn run<T, F>(&self, f: F) -> DbResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> DbResult<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap();
+            f(&mut conn)
+        })
+        .await
+        .map_err(|why| DbError::Storage(why.to_string()))?
+    }
+}
+
+// same curve as the bot's `calculate_level`
+fn level(xp: u64) -> i32 {
+    ((2. * xp as f64 + 5.).sqrt() as f32 / 10.).floor() as i32
+}
+
+// stored documents go through the same migrations as api responses, missing ones start out
+// empty and get their fields from the models' defaults
+fn parse<T: Versioned>(data: Option<String>) -> DbResult<T> {
+    let document = match data {
+        Some(data) => serde_json::from_str(&data)?,
+        None => serde_json::json!({}),
+    };
+
+    Ok(T::from_document(document)?)
+}
+
+// guilds are stored with the default settings the first time they are read, so self hosters
+// can change them in the `guilds` table
+fn load_guild(conn: &Connection, guild_id: u64) -> DbResult<Guild> {
+    let data = conn
+        .query_row(
+            "SELECT data FROM guilds WHERE guild_id = ?1",
+            params![guild_id.to_string()],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?;
+
+    let stored = data.is_some();
+    let guild = parse::<Guild>(data)?;
+
+    if !stored {
+        conn.execute(
+            "INSERT INTO guilds (guild_id, data) VALUES (?1, ?2)",
+            params![guild_id.to_string(), serde_json::to_string(&guild)?],
+        )?;
+    }
+
+    Ok(guild)
+}
+
+fn load_member(conn: &Connection, guild_id: u64, member_id: u64) -> DbResult<GuildMember> {
+    let data = conn
+        .query_row(
+            "SELECT data FROM members WHERE guild_id = ?1 AND member_id = ?2",
+            params![guild_id.to_string(), member_id.to_string()],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?;
+
+    parse(data)
+}
+
+fn save_member(
+    conn: &Connection,
+    guild_id: u64,
+    member_id: u64,
+    member: &GuildMember,
+) -> DbResult<()> {
+    conn.execute(
+        "INSERT INTO members (guild_id, member_id, data) VALUES (?1, ?2, ?3)
+        ON CONFLICT (guild_id, member_id) DO UPDATE SET data = excluded.data",
+        params![
+            guild_id.to_string(),
+            member_id.to_string(),
+            serde_json::to_string(member)?
+        ],
+    )?;
+
+    Ok(())
+}
+
+// writes a ledger entry for a change from `old_xp` to `new_xp`, unchanged xp is not recorded
+fn record(
+    conn: &Connection,
+    guild_id: u64,
+    member_id: u64,
+    old_xp: u64,
+    new_xp: u64,
+    reason: XpReason,
+    actor: Option<u64>,
+) -> DbResult<()> {
+    if old_xp == new_xp {
+        return Ok(());
+    }
+
+    let entry = LedgerEntry {
+        delta: new_xp as i64 - old_xp as i64,
+        xp: new_xp,
+        reason,
+        actor: actor.map(|actor| actor.to_string()),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    };
+
+    conn.execute(
+        "INSERT INTO ledger (guild_id, member_id, data) VALUES (?1, ?2, ?3)",
+        params![
+            guild_id.to_string(),
+            member_id.to_string(),
+            serde_json::to_string(&entry)?
+        ],
+    )?;
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn increment_member(
+    conn: &Connection,
+    guild_id: u64,
+    member_id: u64,
+    delta: i64,
+    reason: XpReason,
+    user_data: Option<GuildMemberData>,
+    message_cooldown: Option<u64>,
+    actor: Option<u64>,
+) -> DbResult<XpIncrement> {
+    let mut member = load_member(conn, guild_id, member_id)?;
+    let old_xp = member.xp;
+
+    member.xp = (member.xp as i64 + delta).max(0) as u64;
+    if let Some(user_data) = user_data {
+        member.userData = user_data;
+    }
+    if message_cooldown.is_some() {
+        member.timestamps.message_cooldown = message_cooldown;
+    }
+
+    save_member(conn, guild_id, member_id, &member)?;
+    record(conn, guild_id, member_id, old_xp, member.xp, reason, actor)?;
+
+    Ok(XpIncrement {
+        xp: member.xp,
+        oldLevel: level(old_xp),
+        newLevel: level(member.xp),
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn set_member_xp(
+    conn: &Connection,
+    guild_id: u64,
+    member_id: u64,
+    xp: u64,
+    user_data: GuildMemberData,
+    reason: XpReason,
+    actor: Option<u64>,
+) -> DbResult<()> {
+    let mut member = load_member(conn, guild_id, member_id)?;
+    let old_xp = member.xp;
+    member.xp = xp;
+    member.userData = user_data;
+
+    save_member(conn, guild_id, member_id, &member)?;
+    record(conn, guild_id, member_id, old_xp, xp, reason, actor)
+}
+
+fn increment_batch(conn: &Connection, entries: Vec<XpBatchEntry>) -> DbResult<()> {
+    for entry in entries {
+        let guild_id = entry
+            .guildId
+            .parse::<u64>()
+            .map_err(|_| DbError::Storage(format!("invalid guild id {}", entry.guildId)))?;
+        let member_id = entry
+            .memberId
+            .parse::<u64>()
+            .map_err(|_| DbError::Storage(format!("invalid member id {}", entry.memberId)))?;
+
+        increment_member(
+            conn,
+            guild_id,
+            member_id,
+            entry.delta,
+            entry.reason,
+            entry.userData,
+            entry.messageCooldown,
+            None,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn load_user(conn: &Connection, user_id: u64) -> DbResult<User> {
+    let data = conn
+        .query_row(
+            "SELECT data FROM users WHERE user_id = ?1",
+            params![user_id.to_string()],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?;
+
+    parse(data)
+}
+
+#[async_trait]
+impl XpStore for SqliteStore {
+    async fn guild(&self, guild_id: u64) -> DbResult<Guild> {
+        self.run(move |conn| load_guild(conn, guild_id)).await
+    }
+
+    async fn patch_guild(&self, guild_id: u64, patch: GuildPatch) -> DbResult<()> {
+        self.run(move |conn| {
+            let mut guild = load_guild(conn, guild_id)?;
+
+            if let Some(values) = patch.values {
+                guild.values = values;
+            }
+            if let Some(modules) = patch.modules {
+                guild.modules = modules;
+            }
+            if let Some(ignored) = patch.ignored {
+                guild.ignored = ignored;
+            }
+            if let Some(boosts) = patch.boosts {
+                guild.boosts = boosts;
+            }
+            if let Some(levelroles) = patch.levelroles {
+                guild.levelroles = levelroles;
+            }
+            if let Some(announce) = patch.announce {
+                guild.announce = announce;
+            }
+            if let Some(logs) = patch.logs {
+                guild.logs = logs;
+            }
+
+            conn.execute(
+                "UPDATE guilds SET data = ?2 WHERE guild_id = ?1",
+                params![guild_id.to_string(), serde_json::to_string(&guild)?],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_guild(&self, guild_id: u64) -> DbResult<()> {
+        self.run(move |conn| {
+            conn.execute(
+                "DELETE FROM guilds WHERE guild_id = ?1",
+                params![guild_id.to_string()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_guild_xp(&self, guild_id: u64, actor: Option<u64>) -> DbResult<()> {
+        self.run(move |conn| {
+            let transaction = conn.transaction()?;
+
+            let members = {
+                let mut statement = transaction.prepare(
+                    "SELECT member_id, json_extract(data, '$.xp') FROM members
+                    WHERE guild_id = ?1 AND json_extract(data, '$.xp') > 0",
+                )?;
+                let members = statement
+                    .query_map(params![guild_id.to_string()], |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+                    })?
+                    .collect::<Result<Vec<(String, i64)>, _>>()?;
+                members
+            };
+
+            for (member_id, xp) in members {
+                if let Ok(member_id) = member_id.parse::<u64>() {
+                    record(
+                        &transaction,
+                        guild_id,
+                        member_id,
+                        xp as u64,
+                        0,
+                        XpReason::Reset,
+                        actor,
+                    )?;
+                }
+            }
+
+            transaction.execute(
+                "UPDATE members SET data = json_set(data, '$.xp', 0) WHERE guild_id = ?1",
+                params![guild_id.to_string()],
+            )?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn guild_premium(&self, _guild_id: u64) -> DbResult<GuildPremium> {
+        Ok(GuildPremium {
+            premium: true,
+            voteFree: true,
+        })
+    }
+
+    async fn member(&self, guild_id: u64, member_id: u64) -> DbResult<GuildMember> {
+        self.run(move |conn| load_member(conn, guild_id, member_id))
+            .await
+    }
+
+    async fn guild_members(&self, guild_id: u64) -> DbResult<Vec<(u64, GuildMember)>> {
+        self.run(move |conn| {
+            let mut statement =
+                conn.prepare("SELECT member_id, data FROM members WHERE guild_id = ?1")?;
+            let members = statement
+                .query_map(params![guild_id.to_string()], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<Result<Vec<(String, String)>, _>>()?;
+
+            members
+                .into_iter()
+                .map(|(member_id, data)| {
+                    let member_id = member_id.parse::<u64>().map_err(|_| {
+                        DbError::Storage(format!("invalid member id {}", member_id))
+                    })?;
+
+                    Ok((member_id, parse::<GuildMember>(Some(data))?))
+                })
+                .collect()
+        })
+        .await
+    }
+
+    async fn import_members(
+        &self,
+        guild_id: u64,
+        members: Vec<MemberSnapshot>,
+        actor: Option<u64>,
+    ) -> DbResult<()> {
+        self.run(move |conn| {
+            let transaction = conn.transaction()?;
+
+            for snapshot in members {
+                let member_id = snapshot.memberId.parse::<u64>().map_err(|_| {
+                    DbError::Storage(format!("invalid member id {}", snapshot.memberId))
+                })?;
+
+                let mut member = load_member(&transaction, guild_id, member_id)?;
+                let old_xp = member.xp;
+                member.xp = snapshot.xp;
+                member.streaks = snapshot.streaks;
+
+                save_member(&transaction, guild_id, member_id, &member)?;
+                record(
+                    &transaction,
+                    guild_id,
+                    member_id,
+                    old_xp,
+                    member.xp,
+                    XpReason::Restore,
+                    actor,
+                )?;
+            }
+
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_member(&self, guild_id: u64, member_id: u64, member: GuildMember) -> DbResult<()> {
+        self.run(move |conn| save_member(conn, guild_id, member_id, &member))
+            .await
+    }
+
+    async fn patch_member(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        patch: GuildMemberPatch,
+    ) -> DbResult<()> {
+        self.run(move |conn| {
+            let mut member = load_member(conn, guild_id, member_id)?;
+
+            if let Some(user_data) = patch.userData {
+                member.userData = user_data;
+            }
+            if let Some(settings) = patch.settings {
+                member.settings = settings;
+            }
+            if let Some(timestamps) = patch.timestamps {
+                member.timestamps.merge(timestamps);
+            }
+            if let Some(streaks) = patch.streaks {
+                member.streaks.merge(streaks);
+            }
+
+            save_member(conn, guild_id, member_id, &member)
+        })
+        .await
+    }
+
+    async fn set_member_xp(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        xp: u64,
+        user_data: GuildMemberData,
+        reason: XpReason,
+        actor: Option<u64>,
+    ) -> DbResult<()> {
+        self.run(move |conn| {
+            let transaction = conn.transaction()?;
+            set_member_xp(
+                &transaction,
+                guild_id,
+                member_id,
+                xp,
+                user_data,
+                reason,
+                actor,
+            )?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn add_member_xp(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        delta: i64,
+        reason: XpReason,
+        user_data: Option<GuildMemberData>,
+        actor: Option<u64>,
+    ) -> DbResult<XpIncrement> {
+        self.run(move |conn| {
+            let transaction = conn.transaction()?;
+            let increment = increment_member(
+                &transaction,
+                guild_id,
+                member_id,
+                delta,
+                reason,
+                user_data,
+                None,
+                actor,
+            )?;
+            transaction.commit()?;
+            Ok(increment)
+        })
+        .await
+    }
+
+    async fn add_member_xp_batch(&self, entries: Vec<XpBatchEntry>) -> DbResult<()> {
+        self.run(move |conn| {
+            let transaction = conn.transaction()?;
+            increment_batch(&transaction, entries)?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn member_ledger(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        limit: u32,
+    ) -> DbResult<Vec<LedgerEntry>> {
+        self.run(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT data FROM ledger WHERE guild_id = ?1 AND member_id = ?2
+                ORDER BY entry_id DESC LIMIT ?3",
+            )?;
+            let entries = statement
+                .query_map(
+                    params![guild_id.to_string(), member_id.to_string(), limit],
+                    |row| row.get::<_, String>(0),
+                )?
+                .collect::<Result<Vec<String>, _>>()?;
+
+            entries
+                .iter()
+                .map(|entry| Ok(serde_json::from_str(entry)?))
+                .collect()
+        })
+        .await
+    }
+
+    async fn apply_write(&self, entry: &JournalEntry) -> DbResult<Option<XpIncrement>> {
+        let entry = entry.clone();
+
+        self.run(move |conn| {
+            let transaction = conn.transaction()?;
+
+            let applied = transaction
+                .query_row(
+                    "SELECT result FROM applied_writes WHERE write_id = ?1",
+                    params![entry.id],
+                    |row| row.get::<_, Option<String>>(0),
+                )
+                .optional()?;
+            if let Some(result) = applied {
+                return match result {
+                    Some(result) => Ok(Some(serde_json::from_str(&result)?)),
+                    None => Ok(None),
+                };
+            }
+
+            let increment = match entry.write {
+                XpWrite::SetXp {
+                    guild_id,
+                    member_id,
+                    xp,
+                    user_data,
+                    reason,
+                    actor,
+                } => {
+                    set_member_xp(
+                        &transaction,
+                        guild_id,
+                        member_id,
+                        xp,
+                        user_data,
+                        reason,
+                        actor,
+                    )?;
+                    None
+                }
+                XpWrite::AddXp {
+                    guild_id,
+                    member_id,
+                    delta,
+                    reason,
+                    user_data,
+                } => Some(increment_member(
+                    &transaction,
+                    guild_id,
+                    member_id,
+                    delta,
+                    reason,
+                    user_data,
+                    None,
+                    None,
+                )?),
+                XpWrite::AddXpBatch { entries } => {
+                    increment_batch(&transaction, entries)?;
+                    None
+                }
+            };
+
+            transaction.execute(
+                "INSERT INTO applied_writes (write_id, result) VALUES (?1, ?2)",
+                params![
+                    entry.id,
+                    increment.as_ref().map(serde_json::to_string).transpose()?
+                ],
+            )?;
+            transaction.commit()?;
+            Ok(increment)
+        })
+        .await
+    }
+
+    async fn user(&self, user_id: u64) -> DbResult<User> {
+        self.run(move |conn| load_user(conn, user_id)).await
+    }
+
+    async fn set_user(&self, user_id: u64, user: User) -> DbResult<()> {
+        self.run(move |conn| {
+            conn.execute(
+                "INSERT INTO users (user_id, data) VALUES (?1, ?2)
+                ON CONFLICT (user_id) DO UPDATE SET data = excluded.data",
+                params![user_id.to_string(), serde_json::to_string(&user)?],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn user_premium(&self, _user_id: u64) -> DbResult<UserPremium> {
+        Ok(UserPremium {
+            userPremium: true,
+            serverPremium: 0,
+            servers: Vec::new(),
+            voteFreeCount: 0,
+            voteFreeServers: Vec::new(),
+        })
+    }
+
+    async fn user_backgrounds(&self, user_id: u64) -> DbResult<Vec<UserBackground>> {
+        self.run(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT background_id FROM backgrounds WHERE user_id = ?1 ORDER BY background_id",
+            )?;
+            let backgrounds = statement
+                .query_map(params![user_id.to_string()], |row| {
+                    Ok(UserBackground {
+                        id: row.get(0)?,
+                        custom: true,
+                        url: None,
+                    })
+                })?
+                .collect::<Result<Vec<UserBackground>, _>>()?;
+
+            Ok(backgrounds)
+        })
+        .await
+    }
+
+    async fn user_background(&self, user_id: u64, background_id: i32) -> DbResult<UserBackground> {
+        self.run(move |conn| {
+            conn.query_row(
+                "SELECT background_id FROM backgrounds WHERE user_id = ?1 AND background_id = ?2",
+                params![user_id.to_string(), background_id],
+                |row| {
+                    Ok(UserBackground {
+                        id: row.get(0)?,
+                        custom: true,
+                        url: None,
+                    })
+                },
+            )
+            .optional()?
+            .ok_or_else(|| DbError::NotFound {
+                message: format!("background {} does not exist", background_id),
+            })
+        })
+        .await
+    }
+
+    async fn upload_user_background(
+        &self,
+        user_id: u64,
+        upload: BackgroundUpload,
+    ) -> DbResult<UserBackground> {
+        self.run(move |conn| {
+            conn.execute(
+                "INSERT INTO backgrounds (user_id, format, data) VALUES (?1, ?2, ?3)",
+                params![user_id.to_string(), upload.format.as_str(), upload.data],
+            )?;
+
+            Ok(UserBackground {
+                id: conn.last_insert_rowid() as i32,
+                custom: true,
+                url: None,
+            })
+        })
+        .await
+    }
+
+    async fn delete_user_background(&self, user_id: u64, background_id: i32) -> DbResult<()> {
+        self.run(move |conn| {
+            conn.execute(
+                "DELETE FROM backgrounds WHERE user_id = ?1 AND background_id = ?2",
+                params![user_id.to_string(), background_id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,249 @@
+use std::{env, sync::Arc};
+
+use async_trait::async_trait;
+
+use crate::{
+    guild::{Guild, GuildPatch},
+    guild_member::{
+        GuildMember, GuildMemberData, GuildMemberPatch, XpBatchEntry, XpIncrement, XpReason,
+    },
+    guild_premium::GuildPremium,
+    journal::JournalEntry,
+    ledger::LedgerEntry,
+    snapshot::MemberSnapshot,
+    user::{User, UserPremium},
+    user_background::{BackgroundUpload, UserBackground},
+    ApiClient, DbError, DbResult,
+};
+
+// everything the bot reads from or writes to its backend
+#[async_trait]
+pub trait XpStore: Send + Sync {
+    async fn guild(&self, guild_id: u64) -> DbResult<Guild>;
+    async fn patch_guild(&self, guild_id: u64, patch: GuildPatch) -> DbResult<()>;
+    async fn delete_guild(&self, guild_id: u64) -> DbResult<()>;
+    async fn delete_guild_xp(&self, guild_id: u64, actor: Option<u64>) -> DbResult<()>;
+    async fn guild_premium(&self, guild_id: u64) -> DbResult<GuildPremium>;
+
+    async fn member(&self, guild_id: u64, member_id: u64) -> DbResult<GuildMember>;
+    async fn guild_members(&self, guild_id: u64) -> DbResult<Vec<(u64, GuildMember)>>;
+    // sets xp and streaks of every given member, the xp changes are recorded as `Restore`
+    async fn import_members(
+        &self,
+        guild_id: u64,
+        members: Vec<MemberSnapshot>,
+        actor: Option<u64>,
+    ) -> DbResult<()>;
+    async fn set_member(&self, guild_id: u64, member_id: u64, member: GuildMember) -> DbResult<()>;
+    async fn patch_member(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        patch: GuildMemberPatch,
+    ) -> DbResult<()>;
+    async fn set_member_xp(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        xp: u64,
+        user_data: GuildMemberData,
+        reason: XpReason,
+        actor: Option<u64>,
+    ) -> DbResult<()>;
+    async fn add_member_xp(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        delta: i64,
+        reason: XpReason,
+        user_data: Option<GuildMemberData>,
+        actor: Option<u64>,
+    ) -> DbResult<XpIncrement>;
+    async fn add_member_xp_batch(&self, entries: Vec<XpBatchEntry>) -> DbResult<()>;
+    async fn member_ledger(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        limit: u32,
+    ) -> DbResult<Vec<LedgerEntry>>;
+    // applies a journaled write once per id, answers with the increment of an `AddXp`
+    async fn apply_write(&self, entry: &JournalEntry) -> DbResult<Option<XpIncrement>>;
+
+    async fn user(&self, user_id: u64) -> DbResult<User>;
+    async fn set_user(&self, user_id: u64, user: User) -> DbResult<()>;
+    async fn user_premium(&self, user_id: u64) -> DbResult<UserPremium>;
+
+    async fn user_backgrounds(&self, user_id: u64) -> DbResult<Vec<UserBackground>>;
+    async fn user_background(&self, user_id: u64, background_id: i32) -> DbResult<UserBackground>;
+    async fn upload_user_background(
+        &self,
+        user_id: u64,
+        upload: BackgroundUpload,
+    ) -> DbResult<UserBackground>;
+    async fn delete_user_background(&self, user_id: u64, background_id: i32) -> DbResult<()>;
+
+    // has to be called after anything changed the settings of a guild, stores without a cache
+    // don't need to do anything
+    fn invalidate_guild(&self, _guild_id: u64) {}
+}
+
+// lets callers hand a shared `Arc<dyn XpStore>` to anything that takes `&dyn XpStore`
+#[async_trait]
+impl<S: XpStore + ?Sized> XpStore for Arc<S> {
+    async fn guild(&self, guild_id: u64) -> DbResult<Guild> {
+        (**self).guild(guild_id).await
+    }
+
+    async fn patch_guild(&self, guild_id: u64, patch: GuildPatch) -> DbResult<()> {
+        (**self).patch_guild(guild_id, patch).await
+    }
+
+    async fn delete_guild(&self, guild_id: u64) -> DbResult<()> {
+        (**self).delete_guild(guild_id).await
+    }
+
+    async fn delete_guild_xp(&self, guild_id: u64, actor: Option<u64>) -> DbResult<()> {
+        (**self).delete_guild_xp(guild_id, actor).await
+    }
+
+    async fn guild_premium(&self, guild_id: u64) -> DbResult<GuildPremium> {
+        (**self).guild_premium(guild_id).await
+    }
+
+    async fn member(&self, guild_id: u64, member_id: u64) -> DbResult<GuildMember> {
+        (**self).member(guild_id, member_id).await
+    }
+
+    async fn guild_members(&self, guild_id: u64) -> DbResult<Vec<(u64, GuildMember)>> {
+        (**self).guild_members(guild_id).await
+    }
+
+    async fn import_members(
+        &self,
+        guild_id: u64,
+        members: Vec<MemberSnapshot>,
+        actor: Option<u64>,
+    ) -> DbResult<()> {
+        (**self).import_members(guild_id, members, actor).await
+    }
+
+    async fn set_member(&self, guild_id: u64, member_id: u64, member: GuildMember) -> DbResult<()> {
+        (**self).set_member(guild_id, member_id, member).await
+    }
+
+    async fn patch_member(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        patch: GuildMemberPatch,
+    ) -> DbResult<()> {
+        (**self).patch_member(guild_id, member_id, patch).await
+    }
+
+    async fn set_member_xp(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        xp: u64,
+        user_data: GuildMemberData,
+        reason: XpReason,
+        actor: Option<u64>,
+    ) -> DbResult<()> {
+        (**self)
+            .set_member_xp(guild_id, member_id, xp, user_data, reason, actor)
+            .await
+    }
+
+    async fn add_member_xp(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        delta: i64,
+        reason: XpReason,
+        user_data: Option<GuildMemberData>,
+        actor: Option<u64>,
+    ) -> DbResult<XpIncrement> {
+        (**self)
+            .add_member_xp(guild_id, member_id, delta, reason, user_data, actor)
+            .await
+    }
+
+    async fn add_member_xp_batch(&self, entries: Vec<XpBatchEntry>) -> DbResult<()> {
+        (**self).add_member_xp_batch(entries).await
+    }
+
+    async fn member_ledger(
+        &self,
+        guild_id: u64,
+        member_id: u64,
+        limit: u32,
+    ) -> DbResult<Vec<LedgerEntry>> {
+        (**self).member_ledger(guild_id, member_id, limit).await
+    }
+
+    async fn apply_write(&self, entry: &JournalEntry) -> DbResult<Option<XpIncrement>> {
+        (**self).apply_write(entry).await
+    }
+
+    async fn user(&self, user_id: u64) -> DbResult<User> {
+        (**self).user(user_id).await
+    }
+
+    async fn set_user(&self, user_id: u64, user: User) -> DbResult<()> {
+        (**self).set_user(user_id, user).await
+    }
+
+    async fn user_premium(&self, user_id: u64) -> DbResult<UserPremium> {
+        (**self).user_premium(user_id).await
+    }
+
+    async fn user_backgrounds(&self, user_id: u64) -> DbResult<Vec<UserBackground>> {
+        (**self).user_backgrounds(user_id).await
+    }
+
+    async fn user_background(&self, user_id: u64, background_id: i32) -> DbResult<UserBackground> {
+        (**self).user_background(user_id, background_id).await
+    }
+
+    async fn upload_user_background(
+        &self,
+        user_id: u64,
+        upload: BackgroundUpload,
+    ) -> DbResult<UserBackground> {
+        (**self).upload_user_background(user_id, upload).await
+    }
+
+    async fn delete_user_background(&self, user_id: u64, background_id: i32) -> DbResult<()> {
+        (**self)
+            .delete_user_background(user_id, background_id)
+            .await
+    }
+
+    fn invalidate_guild(&self, guild_id: u64) {
+        (**self).invalidate_guild(guild_id)
+    }
+}
+
+/*
+    Picks the backend from the environment.
+    > XP_STORE=rest (default) talks to the hosted api, see `ApiClient::from_env`.
+    > XP_STORE=sqlite keeps everything in a local database at XP_SQLITE_PATH (default `xp.db`),
+    > which needs the `sqlite` feature.
+*/
+pub fn from_env() -> DbResult<Arc<dyn XpStore>> {
+    let backend = env::var("XP_STORE").unwrap_or_else(|_| "rest".to_string());
+
+    match backend.as_str() {
+        "rest" => Ok(Arc::new(ApiClient::from_env()?)),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => {
+            let path = env::var("XP_SQLITE_PATH").unwrap_or_else(|_| "xp.db".to_string());
+            Ok(Arc::new(crate::sqlite::SqliteStore::open(path)?))
+        }
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => Err(DbError::Config(
+            "XP_STORE=sqlite needs the sqlite feature".to_string(),
+        )),
+        other => Err(DbError::Config(format!("unknown XP_STORE {}", other))),
+    }
+}
@@ -1,25 +1,36 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
-use crate::DbResult;
+use crate::{
+    migrate::{self, Versioned},
+    store::XpStore,
+    DbResult,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct UserResponse {
     pub success: bool,
     pub message: String,
+    #[serde(default, deserialize_with = "migrate::content")]
     pub content: Option<User>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct User {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
     pub badges: Vec<String>,
     pub titles: Vec<String>,
     pub settings: UserSettings,
     pub timestamps: UserTimestamps,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct UserTimestamps {
     pub message_cooldown: Option<u64>,
+    // no longer written, voice sessions are tracked per guild by the bot
     pub join_voicechat: Option<u64>,
     pub game_trivia: Option<u64>,
     pub game_daily: Option<u64>,
@@ -29,12 +40,14 @@ pub struct UserTimestamps {
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
 pub struct UserSettings {
     pub background: UserSettingsBackground,
     pub language: String,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct UserSettingsBackground {
     pub bg: Option<i32>,
     pub blur: Option<i32>,
@@ -50,7 +63,8 @@ pub struct UserPremiumResponse {
 }
 
 #[allow(non_snake_case)]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct UserPremium {
     pub userPremium: bool,
     pub serverPremium: u32,
@@ -59,37 +73,32 @@ pub struct UserPremium {
     pub voteFreeServers: Vec<String>,
 }
 
-impl User {
-    pub async fn from_id(user_id: u64) -> DbResult<User> {
-        let response = crate::get_json::<UserResponse>(format!("/user/{}", user_id)).await?;
-
-        if response.success && response.content.is_some() {
-            Ok(response.content.unwrap())
-        } else {
-            Err(format!("Failed to get user: {}", response.message).into())
+impl Default for UserSettings {
+    fn default() -> Self {
+        UserSettings {
+            background: UserSettingsBackground::default(),
+            language: "en".to_string(),
         }
     }
+}
 
-    pub async fn is_premium(user_id: u64) -> DbResult<bool> {
-        let response =
-            crate::get_json::<UserPremiumResponse>(format!("/user/{}/premium", user_id)).await?;
+impl Versioned for User {
+    const SCHEMA_VERSION: u32 = 1;
 
-        if response.success && response.content.is_some() {
-            Ok(response.content.unwrap().userPremium)
-        } else {
-            Err(format!("Failed to get user premium: {}", response.message).into())
+    fn upgrade(document: &mut Value, version: u32) {
+        // 0 -> 1: users that never opened the background settings have them set to null
+        if version == 0 {
+            migrate::fill_null(document, "/settings/background", json!({}));
         }
     }
+}
 
-    pub async fn set(
-        user_id: u64,
-        user: User,
-    ) -> DbResult<Result<(), Box<(dyn std::error::Error + Send + Sync + 'static)>>> {
-        let response = crate::patch_json(format!("/user/{}", user_id), user).await;
+impl User {
+    pub async fn from_id(store: &dyn XpStore, user_id: u64) -> DbResult<User> {
+        store.user(user_id).await
+    }
 
-        match response {
-            Ok(_) => Ok(Ok(())),
-            Err(e) => Ok(Err(e.into())),
-        }
+    pub async fn set(store: &dyn XpStore, user_id: u64, user: User) -> DbResult<()> {
+        store.set_user(user_id, user).await
     }
 }
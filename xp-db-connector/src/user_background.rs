@@ -1 +1,147 @@
-// implement local api request here
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::{store::XpStore, user::User, DbError, DbResult};
+
+// largest upload the api accepts
+pub const MAX_BACKGROUND_SIZE: usize = 4 * 1024 * 1024;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct UserBackgroundResponse {
+    pub success: bool,
+    pub message: String,
+    pub content: Option<UserBackground>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct UserBackgroundsResponse {
+    pub success: bool,
+    pub message: String,
+    pub content: Option<Vec<UserBackground>>,
+}
+
+// a rank card background, `custom` ones were uploaded by the user, the others are built in
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct UserBackground {
+    pub id: i32,
+    pub custom: bool,
+    pub url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackgroundFormat {
+    Png,
+    Jpeg,
+    Webp,
+    Gif,
+}
+
+impl BackgroundFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackgroundFormat::Png => "png",
+            BackgroundFormat::Jpeg => "jpeg",
+            BackgroundFormat::Webp => "webp",
+            BackgroundFormat::Gif => "gif",
+        }
+    }
+
+    // the format is taken from the file's signature, not its name
+    pub fn detect(bytes: &[u8]) -> Option<BackgroundFormat> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(BackgroundFormat::Png)
+        } else if bytes.starts_with(b"\xff\xd8\xff") {
+            Some(BackgroundFormat::Jpeg)
+        } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+            Some(BackgroundFormat::Webp)
+        } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+            Some(BackgroundFormat::Gif)
+        } else {
+            None
+        }
+    }
+}
+
+// an image that passed validation, ready to be sent to the store
+#[derive(Clone, Debug)]
+pub struct BackgroundUpload {
+    pub format: BackgroundFormat,
+    pub data: Vec<u8>,
+}
+
+impl BackgroundUpload {
+    pub fn new(data: Vec<u8>) -> DbResult<BackgroundUpload> {
+        if data.is_empty() {
+            return Err(DbError::Invalid("the image is empty".to_string()));
+        }
+        if data.len() > MAX_BACKGROUND_SIZE {
+            return Err(DbError::Invalid(format!(
+                "the image is larger than {}MB",
+                MAX_BACKGROUND_SIZE / 1024 / 1024
+            )));
+        }
+
+        let format = BackgroundFormat::detect(&data).ok_or_else(|| {
+            DbError::Invalid("only png, jpeg, webp and gif images are supported".to_string())
+        })?;
+
+        Ok(BackgroundUpload { format, data })
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BackgroundUploadBody {
+    pub(crate) format: BackgroundFormat,
+    pub(crate) data: String,
+}
+
+impl From<&BackgroundUpload> for BackgroundUploadBody {
+    fn from(upload: &BackgroundUpload) -> Self {
+        BackgroundUploadBody {
+            format: upload.format,
+            data: base64::engine::general_purpose::STANDARD.encode(&upload.data),
+        }
+    }
+}
+
+impl UserBackground {
+    pub async fn from_id(
+        store: &dyn XpStore,
+        user_id: u64,
+        background_id: i32,
+    ) -> DbResult<UserBackground> {
+        store.user_background(user_id, background_id).await
+    }
+
+    // the background the user has selected, None if they use the default one
+    pub async fn current(store: &dyn XpStore, user_id: u64) -> DbResult<Option<UserBackground>> {
+        let user = User::from_id(store, user_id).await?;
+
+        match user.settings.background.bg {
+            Some(background_id) => match store.user_background(user_id, background_id).await {
+                Ok(background) => Ok(Some(background)),
+                Err(why) if why.is_not_found() => Ok(None),
+                Err(why) => Err(why),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub async fn list(store: &dyn XpStore, user_id: u64) -> DbResult<Vec<UserBackground>> {
+        store.user_backgrounds(user_id).await
+    }
+
+    pub async fn upload(
+        store: &dyn XpStore,
+        user_id: u64,
+        upload: BackgroundUpload,
+    ) -> DbResult<UserBackground> {
+        store.upload_user_background(user_id, upload).await
+    }
+
+    pub async fn delete(store: &dyn XpStore, user_id: u64, background_id: i32) -> DbResult<()> {
+        store.delete_user_background(user_id, background_id).await
+    }
+}
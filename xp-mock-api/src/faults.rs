@@ -0,0 +1,137 @@
+use std::{env, time::Duration};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::MockApi;
+
+/*
+    Failures the mock adds on top of the normal responses.
+    > `fail_next`, `malformed_next` and `rate_limit_next` hit the next n requests, the rates roll
+    > for every request after that.
+    > Rate limited requests get a 429 with `Retry-After: <retry_after_secs>`.
+    > Latency is applied to every request, including the ones that fail.
+*/
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Faults {
+    pub latency_ms: u64,
+    pub jitter_ms: u64,
+    pub error_rate: f64,
+    pub malformed_rate: f64,
+    pub fail_next: u32,
+    pub malformed_next: u32,
+    pub rate_limit_next: u32,
+    pub retry_after_secs: u64,
+}
+
+enum Fault {
+    Error,
+    Malformed,
+    RateLimited,
+}
+
+impl Faults {
+    // MOCK_LATENCY_MS, MOCK_JITTER_MS, MOCK_ERROR_RATE and MOCK_MALFORMED_RATE, all default to 0
+    pub fn from_env() -> Faults {
+        fn var<T: std::str::FromStr + Default>(name: &str) -> T {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or_default()
+        }
+
+        Faults {
+            latency_ms: var("MOCK_LATENCY_MS"),
+            jitter_ms: var("MOCK_JITTER_MS"),
+            error_rate: var("MOCK_ERROR_RATE"),
+            malformed_rate: var("MOCK_MALFORMED_RATE"),
+            fail_next: 0,
+            malformed_next: 0,
+            rate_limit_next: 0,
+            retry_after_secs: 0,
+        }
+    }
+
+    fn latency(&self) -> Duration {
+        let jitter = match self.jitter_ms {
+            0 => 0,
+            jitter => rand::thread_rng().gen_range(0..=jitter),
+        };
+
+        Duration::from_millis(self.latency_ms + jitter)
+    }
+
+    fn roll(&mut self) -> Option<Fault> {
+        if self.fail_next > 0 {
+            self.fail_next -= 1;
+            return Some(Fault::Error);
+        }
+        if self.malformed_next > 0 {
+            self.malformed_next -= 1;
+            return Some(Fault::Malformed);
+        }
+        if self.rate_limit_next > 0 {
+            self.rate_limit_next -= 1;
+            return Some(Fault::RateLimited);
+        }
+
+        let mut rng = rand::thread_rng();
+        if rng.gen_bool(self.error_rate.clamp(0., 1.)) {
+            Some(Fault::Error)
+        } else if rng.gen_bool(self.malformed_rate.clamp(0., 1.)) {
+            Some(Fault::Malformed)
+        } else {
+            None
+        }
+    }
+}
+
+// middleware in front of every api route
+pub(crate) async fn inject(
+    State(api): State<MockApi>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    api.record(format!("{} {}", request.method(), request.uri().path()));
+
+    let (latency, fault, retry_after) = {
+        let mut faults = api.faults_mut();
+        (faults.latency(), faults.roll(), faults.retry_after_secs)
+    };
+
+    if !latency.is_zero() {
+        tokio::time::sleep(latency).await;
+    }
+
+    match fault {
+        Some(Fault::Error) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "success": false, "message": "injected failure" })),
+        )
+            .into_response(),
+        // cut off in the middle of the content, like a dropped connection would
+        Some(Fault::Malformed) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "application/json")],
+            r#"{"success":true,"message":"OK","content":{"xp":"#,
+        )
+            .into_response(),
+        Some(Fault::RateLimited) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            Json(json!({ "success": false, "message": "rate limited" })),
+        )
+            .into_response(),
+        None => next.run(request).await,
+    }
+}
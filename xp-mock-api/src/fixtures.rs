@@ -0,0 +1,53 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+const DEFAULTS: &str = include_str!("../fixtures/defaults.json");
+const SAMPLE: &str = include_str!("../fixtures/sample.json");
+
+// documents the api hands out for ids it has not seen before
+#[allow(non_snake_case)]
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct Defaults {
+    pub guild: Value,
+    pub member: Value,
+    pub user: Value,
+    pub guildPremium: Value,
+    pub userPremium: Value,
+}
+
+impl Defaults {
+    pub(crate) fn load() -> Defaults {
+        serde_json::from_str(DEFAULTS).expect("fixtures/defaults.json is not valid")
+    }
+}
+
+/*
+    Initial state of the mock, keyed by snowflake.
+    > Documents only need the fields a test cares about, everything else is filled in from
+    > `fixtures/defaults.json`.
+    > Members are keyed by guild id first, then by member id.
+*/
+#[allow(non_snake_case)]
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Fixtures {
+    pub guilds: HashMap<String, Value>,
+    pub members: HashMap<String, HashMap<String, Value>>,
+    pub users: HashMap<String, Value>,
+    pub guildPremium: HashMap<String, Value>,
+    pub userPremium: HashMap<String, Value>,
+    pub backgrounds: HashMap<String, Vec<Value>>,
+}
+
+impl Fixtures {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Fixtures, axum::BoxError> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    // one premium guild with two members, see `fixtures/sample.json`
+    pub fn sample() -> Fixtures {
+        serde_json::from_str(SAMPLE).expect("fixtures/sample.json is not valid")
+    }
+}
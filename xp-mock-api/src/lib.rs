@@ -0,0 +1,118 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use serde_json::Value;
+
+pub mod faults;
+pub mod fixtures;
+mod routes;
+mod state;
+
+pub use faults::Faults;
+pub use fixtures::Fixtures;
+
+use state::MockState;
+
+struct Inner {
+    fixtures: Fixtures,
+    auth: Option<String>,
+    state: Mutex<MockState>,
+    faults: Mutex<Faults>,
+    requests: Mutex<Vec<String>>,
+}
+
+/*
+    Stand-in for the xp api, for running the connector and the bot without network access.
+    > Serves the same routes and {success, message, content} envelopes from in-memory state.
+    > State starts from `Fixtures` and can be reset to them at any time.
+    > `Faults` add latency, 500s and cut off bodies on top.
+*/
+#[derive(Clone)]
+pub struct MockApi {
+    inner: Arc<Inner>,
+}
+
+impl MockApi {
+    // requests need `Authorization: Bearer <auth>` if `auth` is set, like the real api
+    pub fn new(fixtures: Fixtures, auth: Option<String>) -> MockApi {
+        MockApi {
+            inner: Arc::new(Inner {
+                state: Mutex::new(MockState::new(&fixtures)),
+                fixtures,
+                auth,
+                faults: Mutex::new(Faults::default()),
+                requests: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn faults(&self) -> Faults {
+        self.faults_mut().clone()
+    }
+
+    pub fn set_faults(&self, faults: Faults) {
+        *self.faults_mut() = faults;
+    }
+
+    // back to the fixtures, without faults and with an empty request log
+    pub fn reset(&self) {
+        *self.state() = MockState::new(&self.inner.fixtures);
+        self.set_faults(Faults::default());
+        self.inner.requests.lock().unwrap().clear();
+    }
+
+    // every api request so far as "METHOD /path", including the ones that got a fault
+    pub fn requests(&self) -> Vec<String> {
+        self.inner.requests.lock().unwrap().clone()
+    }
+
+    pub fn member(&self, guild_id: u64, member_id: u64) -> Option<Value> {
+        self.state()
+            .peek_member(&guild_id.to_string(), &member_id.to_string())
+    }
+
+    pub fn router(&self) -> axum::Router {
+        routes::router(self.clone())
+    }
+
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), axum::BoxError> {
+        axum::Server::try_bind(&addr)?
+            .serve(self.router().into_make_service())
+            .await?;
+
+        Ok(())
+    }
+
+    // serves on a free local port in the background, returns the base url to point API_URL at
+    pub async fn spawn(self) -> Result<String, axum::BoxError> {
+        let server = axum::Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))?
+            .serve(self.router().into_make_service());
+        let base_url = format!("http://{}", server.local_addr());
+
+        tokio::spawn(async move {
+            if let Err(why) = server.await {
+                log::error!("Mock api stopped: {}", why);
+            }
+        });
+
+        Ok(base_url)
+    }
+
+    pub(crate) fn auth(&self) -> Option<&str> {
+        self.inner.auth.as_deref()
+    }
+
+    pub(crate) fn state(&self) -> MutexGuard<'_, MockState> {
+        self.inner.state.lock().unwrap()
+    }
+
+    pub(crate) fn faults_mut(&self) -> MutexGuard<'_, Faults> {
+        self.inner.faults.lock().unwrap()
+    }
+
+    pub(crate) fn record(&self, request: String) {
+        self.inner.requests.lock().unwrap().push(request);
+    }
+}
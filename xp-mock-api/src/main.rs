@@ -0,0 +1,40 @@
+use std::{env, net::SocketAddr};
+
+use log::{error, info};
+use xp_mock_api::{Faults, Fixtures, MockApi};
+
+/*
+    Runs the mock api on its own, point the bot's API_URL at it.
+    > MOCK_API_ADDR (default 127.0.0.1:3000) is the address to listen on.
+    > MOCK_API_FIXTURES is a fixture file, `fixtures/sample.json` is used without one.
+    > API_AUTH is checked like the real api does, if it is set.
+    > Faults are read from MOCK_LATENCY_MS, MOCK_JITTER_MS, MOCK_ERROR_RATE and
+    > MOCK_MALFORMED_RATE and can be changed at runtime through PUT /_mock/faults.
+*/
+#[tokio::main]
+async fn main() {
+    let _ = dotenv::dotenv();
+
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let addr: SocketAddr = env::var("MOCK_API_ADDR")
+        .unwrap_or_else(|_| "127.0.0.1:3000".to_string())
+        .parse()
+        .expect("MOCK_API_ADDR is not a valid address");
+
+    let fixtures = match env::var("MOCK_API_FIXTURES") {
+        Ok(path) => Fixtures::from_file(&path).expect("Could not load fixtures"),
+        Err(_) => Fixtures::sample(),
+    };
+
+    let auth = env::var("API_AUTH").ok().filter(|auth| !auth.is_empty());
+
+    let api = MockApi::new(fixtures, auth);
+    api.set_faults(Faults::from_env());
+
+    info!("Mock api listening on http://{}", addr);
+
+    if let Err(why) = api.serve(addr).await {
+        error!("Mock api stopped: {}", why);
+    }
+}
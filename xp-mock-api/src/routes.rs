@@ -0,0 +1,430 @@
+use axum::{
+    body::{Body, HttpBody},
+    extract::{Path, Query, State},
+    http::{header, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{faults, state::merge, Faults, MockApi};
+
+#[allow(non_snake_case)]
+#[derive(Deserialize)]
+struct XpBody {
+    xp: u64,
+    userData: Value,
+    reason: String,
+    actor: Option<String>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize)]
+struct IncrementBody {
+    delta: i64,
+    reason: String,
+    userData: Option<Value>,
+    actor: Option<String>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize)]
+struct BatchEntry {
+    guildId: String,
+    memberId: String,
+    delta: i64,
+    reason: String,
+    messageCooldown: Option<u64>,
+    userData: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct BatchBody {
+    increments: Vec<BatchEntry>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize)]
+struct ImportEntry {
+    memberId: String,
+    xp: u64,
+    #[serde(default)]
+    streaks: Value,
+}
+
+#[derive(Deserialize)]
+struct ImportBody {
+    members: Vec<ImportEntry>,
+    actor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ActorQuery {
+    actor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LedgerQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct BackgroundBody {
+    format: String,
+    data: String,
+}
+
+pub(crate) fn router(api: MockApi) -> Router {
+    let routes = Router::new()
+        .route(
+            "/guild/:guild_id",
+            get(get_guild).patch(patch_guild).delete(delete_guild),
+        )
+        .route("/guild/:guild_id/members", get(get_guild_members))
+        .route("/guild/:guild_id/members/import", post(import_members))
+        .route("/guild/:guild_id/members/xp", delete(delete_guild_xp))
+        .route("/guild/:guild_id/premium", get(get_guild_premium))
+        .route(
+            "/guild/:guild_id/member/:member_id",
+            get(get_member).patch(patch_member),
+        )
+        .route(
+            "/guild/:guild_id/member/:member_id/direct/xp",
+            post(set_member_xp),
+        )
+        .route(
+            "/guild/:guild_id/member/:member_id/xp/increment",
+            post(increment_member_xp),
+        )
+        .route(
+            "/guild/:guild_id/member/:member_id/ledger",
+            get(get_member_ledger),
+        )
+        .route("/guilds/members/xp/batch", post(increment_batch))
+        .route("/user/:user_id", get(get_user).patch(patch_user))
+        .route("/user/:user_id/premium", get(get_user_premium))
+        .route(
+            "/user/:user_id/backgrounds",
+            get(get_backgrounds).post(upload_background),
+        )
+        .route(
+            "/user/:user_id/background/:background_id",
+            get(get_background).delete(delete_background),
+        )
+        .route_layer(middleware::from_fn_with_state(api.clone(), idempotent))
+        .route_layer(middleware::from_fn_with_state(api.clone(), faults::inject))
+        .route_layer(middleware::from_fn_with_state(api.clone(), require_auth));
+
+    // control routes for tests, never slowed down or failed
+    Router::new()
+        .merge(routes)
+        .route("/_mock/faults", get(get_faults).put(put_faults))
+        .route("/_mock/requests", get(get_requests))
+        .route("/_mock/reset", post(reset))
+        .with_state(api)
+}
+
+fn content(content: Value) -> Response {
+    Json(json!({ "success": true, "message": "OK", "content": content })).into_response()
+}
+
+fn done() -> Response {
+    Json(json!({ "success": true, "message": "OK" })).into_response()
+}
+
+fn not_found(message: &str) -> Response {
+    Json(json!({ "success": true, "message": message, "content": null })).into_response()
+}
+
+// top level fields of `patch` replace the ones in `document`, like a PATCH on the api does
+fn replace_fields(document: &mut Value, patch: Value) {
+    if let (Some(document), Value::Object(patch)) = (document.as_object_mut(), patch) {
+        document.extend(patch);
+    }
+}
+
+async fn require_auth(
+    State(api): State<MockApi>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    if let Some(auth) = api.auth() {
+        let given = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok());
+
+        if given != Some(format!("Bearer {}", auth).as_str()) {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({ "success": false, "message": "Unauthorized" })),
+            )
+                .into_response();
+        }
+    }
+
+    next.run(request).await
+}
+
+// a write with an `Idempotency-Key` is applied once, repeating it answers with the first response
+async fn idempotent(
+    State(api): State<MockApi>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let key = match request
+        .headers()
+        .get("Idempotency-Key")
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(key) => key.to_string(),
+        None => return next.run(request).await,
+    };
+
+    if let Some(response) = api.state().applied_write(&key) {
+        return Json(response).into_response();
+    }
+
+    let response = next.run(request).await;
+    if !response.status().is_success() {
+        return response;
+    }
+
+    let mut body = response.into_body();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        match chunk {
+            Ok(chunk) => bytes.extend_from_slice(&chunk),
+            Err(why) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, why.to_string()).into_response()
+            }
+        }
+    }
+
+    // every route answers with a json envelope
+    let response = serde_json::from_slice::<Value>(&bytes).unwrap_or(Value::Null);
+    api.state().remember_write(key, response.clone());
+
+    Json(response).into_response()
+}
+
+async fn get_guild(State(api): State<MockApi>, Path(guild_id): Path<String>) -> Response {
+    content(api.state().guild(&guild_id).clone())
+}
+
+async fn patch_guild(
+    State(api): State<MockApi>,
+    Path(guild_id): Path<String>,
+    Json(patch): Json<Value>,
+) -> Response {
+    replace_fields(api.state().guild(&guild_id), patch);
+    done()
+}
+
+async fn delete_guild(State(api): State<MockApi>, Path(guild_id): Path<String>) -> Response {
+    api.state().delete_guild(&guild_id);
+    done()
+}
+
+async fn get_guild_members(State(api): State<MockApi>, Path(guild_id): Path<String>) -> Response {
+    content(api.state().guild_members(&guild_id))
+}
+
+async fn import_members(
+    State(api): State<MockApi>,
+    Path(guild_id): Path<String>,
+    Json(body): Json<ImportBody>,
+) -> Response {
+    let mut state = api.state();
+    for member in body.members {
+        state.import_member(
+            &guild_id,
+            &member.memberId,
+            member.xp,
+            member.streaks,
+            body.actor.clone(),
+        );
+    }
+
+    done()
+}
+
+async fn delete_guild_xp(
+    State(api): State<MockApi>,
+    Path(guild_id): Path<String>,
+    Query(query): Query<ActorQuery>,
+) -> Response {
+    api.state().delete_guild_xp(&guild_id, query.actor);
+    done()
+}
+
+async fn get_guild_premium(State(api): State<MockApi>, Path(guild_id): Path<String>) -> Response {
+    content(api.state().guild_premium(&guild_id))
+}
+
+async fn get_member(
+    State(api): State<MockApi>,
+    Path((guild_id, member_id)): Path<(String, String)>,
+) -> Response {
+    content(api.state().member(&guild_id, &member_id).clone())
+}
+
+async fn patch_member(
+    State(api): State<MockApi>,
+    Path((guild_id, member_id)): Path<(String, String)>,
+    Json(mut patch): Json<Value>,
+) -> Response {
+    let mut state = api.state();
+    let member = state.member(&guild_id, &member_id);
+
+    // merged field by field, so a cooldown written in the meantime is kept
+    for section in ["timestamps", "streaks"] {
+        if let Some(fields) = patch
+            .as_object_mut()
+            .and_then(|patch| patch.remove(section))
+        {
+            merge(&mut member[section], fields);
+        }
+    }
+    replace_fields(member, patch);
+
+    done()
+}
+
+async fn set_member_xp(
+    State(api): State<MockApi>,
+    Path((guild_id, member_id)): Path<(String, String)>,
+    Json(body): Json<XpBody>,
+) -> Response {
+    api.state().set_xp(
+        &guild_id,
+        &member_id,
+        body.xp,
+        body.userData,
+        &body.reason,
+        body.actor,
+    );
+
+    done()
+}
+
+async fn increment_member_xp(
+    State(api): State<MockApi>,
+    Path((guild_id, member_id)): Path<(String, String)>,
+    Json(body): Json<IncrementBody>,
+) -> Response {
+    content(api.state().increment(
+        &guild_id,
+        &member_id,
+        body.delta,
+        &body.reason,
+        body.userData,
+        None,
+        body.actor,
+    ))
+}
+
+async fn get_member_ledger(
+    State(api): State<MockApi>,
+    Path((guild_id, member_id)): Path<(String, String)>,
+    Query(query): Query<LedgerQuery>,
+) -> Response {
+    content(json!(api.state().ledger(
+        &guild_id,
+        &member_id,
+        query.limit.unwrap_or(25)
+    )))
+}
+
+async fn increment_batch(State(api): State<MockApi>, Json(body): Json<BatchBody>) -> Response {
+    let mut state = api.state();
+    for entry in body.increments {
+        state.increment(
+            &entry.guildId,
+            &entry.memberId,
+            entry.delta,
+            &entry.reason,
+            entry.userData,
+            entry.messageCooldown,
+            None,
+        );
+    }
+
+    done()
+}
+
+async fn get_user(State(api): State<MockApi>, Path(user_id): Path<String>) -> Response {
+    content(api.state().user(&user_id).clone())
+}
+
+async fn patch_user(
+    State(api): State<MockApi>,
+    Path(user_id): Path<String>,
+    Json(patch): Json<Value>,
+) -> Response {
+    replace_fields(api.state().user(&user_id), patch);
+    done()
+}
+
+async fn get_user_premium(State(api): State<MockApi>, Path(user_id): Path<String>) -> Response {
+    content(api.state().user_premium(&user_id))
+}
+
+async fn get_backgrounds(State(api): State<MockApi>, Path(user_id): Path<String>) -> Response {
+    content(json!(api.state().backgrounds(&user_id)))
+}
+
+async fn get_background(
+    State(api): State<MockApi>,
+    Path((user_id, background_id)): Path<(String, i64)>,
+) -> Response {
+    match api.state().background(&user_id, background_id) {
+        Some(background) => content(background),
+        None => not_found("Background not found"),
+    }
+}
+
+async fn upload_background(
+    State(api): State<MockApi>,
+    Path(user_id): Path<String>,
+    Json(body): Json<BackgroundBody>,
+) -> Response {
+    if body.format.is_empty() || body.data.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "success": false, "message": "Missing image" })),
+        )
+            .into_response();
+    }
+
+    content(api.state().add_background(&user_id))
+}
+
+async fn delete_background(
+    State(api): State<MockApi>,
+    Path((user_id, background_id)): Path<(String, i64)>,
+) -> Response {
+    api.state().delete_background(&user_id, background_id);
+    done()
+}
+
+async fn get_faults(State(api): State<MockApi>) -> Json<Faults> {
+    Json(api.faults())
+}
+
+async fn put_faults(State(api): State<MockApi>, Json(faults): Json<Faults>) -> Response {
+    api.set_faults(faults);
+    done()
+}
+
+async fn get_requests(State(api): State<MockApi>) -> Json<Vec<String>> {
+    Json(api.requests())
+}
+
+async fn reset(State(api): State<MockApi>) -> Response {
+    api.reset();
+    done()
+}
@@ -0,0 +1,309 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+
+use crate::fixtures::{Defaults, Fixtures};
+
+// same curve as the bot's `calculate_level`
+fn level(xp: u64) -> i32 {
+    ((2. * xp as f64 + 5.).sqrt() as f32 / 10.).floor() as i32
+}
+
+// objects are merged key by key, anything else in `patch` replaces what is in `base`
+pub(crate) fn merge(base: &mut Value, patch: Value) {
+    match (base, patch) {
+        (Value::Object(base), Value::Object(patch)) => {
+            for (key, value) in patch {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, patch) => *base = patch,
+    }
+}
+
+fn with_defaults(default: &Value, document: Value) -> Value {
+    let mut merged = default.clone();
+    merge(&mut merged, document);
+    merged
+}
+
+// in-memory copy of everything the api stores, unknown documents are created on first access
+pub(crate) struct MockState {
+    defaults: Defaults,
+    guilds: HashMap<String, Value>,
+    members: HashMap<(String, String), Value>,
+    users: HashMap<String, Value>,
+    guild_premium: HashMap<String, Value>,
+    user_premium: HashMap<String, Value>,
+    backgrounds: HashMap<String, Vec<Value>>,
+    next_background_id: i64,
+    ledger: HashMap<(String, String), Vec<Value>>,
+    // responses of writes sent with an `Idempotency-Key`, by key
+    applied_writes: HashMap<String, Value>,
+}
+
+impl MockState {
+    pub(crate) fn new(fixtures: &Fixtures) -> MockState {
+        let defaults = Defaults::load();
+        let fixtures = fixtures.clone();
+
+        MockState {
+            guilds: fixtures
+                .guilds
+                .into_iter()
+                .map(|(id, guild)| (id, with_defaults(&defaults.guild, guild)))
+                .collect(),
+            members: fixtures
+                .members
+                .into_iter()
+                .flat_map(|(guild_id, members)| {
+                    members
+                        .into_iter()
+                        .map(move |(member_id, member)| ((guild_id.clone(), member_id), member))
+                })
+                .map(|(key, member)| (key, with_defaults(&defaults.member, member)))
+                .collect(),
+            users: fixtures
+                .users
+                .into_iter()
+                .map(|(id, user)| (id, with_defaults(&defaults.user, user)))
+                .collect(),
+            guild_premium: fixtures
+                .guildPremium
+                .into_iter()
+                .map(|(id, premium)| (id, with_defaults(&defaults.guildPremium, premium)))
+                .collect(),
+            user_premium: fixtures
+                .userPremium
+                .into_iter()
+                .map(|(id, premium)| (id, with_defaults(&defaults.userPremium, premium)))
+                .collect(),
+            next_background_id: fixtures
+                .backgrounds
+                .values()
+                .flatten()
+                .filter_map(|background| background["id"].as_i64())
+                .max()
+                .unwrap_or(0)
+                + 1,
+            backgrounds: fixtures.backgrounds,
+            ledger: HashMap::new(),
+            applied_writes: HashMap::new(),
+            defaults,
+        }
+    }
+
+    pub(crate) fn guild(&mut self, guild_id: &str) -> &mut Value {
+        self.guilds
+            .entry(guild_id.to_string())
+            .or_insert_with(|| self.defaults.guild.clone())
+    }
+
+    pub(crate) fn delete_guild(&mut self, guild_id: &str) {
+        self.guilds.remove(guild_id);
+    }
+
+    pub(crate) fn delete_guild_xp(&mut self, guild_id: &str, actor: Option<String>) {
+        let mut reset = Vec::new();
+        for ((member_guild, member_id), member) in self.members.iter_mut() {
+            if member_guild == guild_id {
+                reset.push((member_id.clone(), member["xp"].as_u64().unwrap_or(0)));
+                member["xp"] = json!(0);
+            }
+        }
+
+        for (member_id, old_xp) in reset {
+            self.record(guild_id, &member_id, old_xp, 0, "reset", actor.clone());
+        }
+    }
+
+    pub(crate) fn guild_premium(&self, guild_id: &str) -> Value {
+        self.guild_premium
+            .get(guild_id)
+            .unwrap_or(&self.defaults.guildPremium)
+            .clone()
+    }
+
+    pub(crate) fn member(&mut self, guild_id: &str, member_id: &str) -> &mut Value {
+        self.members
+            .entry((guild_id.to_string(), member_id.to_string()))
+            .or_insert_with(|| self.defaults.member.clone())
+    }
+
+    pub(crate) fn peek_member(&self, guild_id: &str, member_id: &str) -> Option<Value> {
+        self.members
+            .get(&(guild_id.to_string(), member_id.to_string()))
+            .cloned()
+    }
+
+    // every stored member of a guild, keyed by member id
+    pub(crate) fn guild_members(&self, guild_id: &str) -> Value {
+        Value::Object(
+            self.members
+                .iter()
+                .filter(|((member_guild, _), _)| member_guild == guild_id)
+                .map(|((_, member_id), member)| (member_id.clone(), member.clone()))
+                .collect(),
+        )
+    }
+
+    pub(crate) fn import_member(
+        &mut self,
+        guild_id: &str,
+        member_id: &str,
+        xp: u64,
+        streaks: Value,
+        actor: Option<String>,
+    ) {
+        let member = self.member(guild_id, member_id);
+        let old_xp = member["xp"].as_u64().unwrap_or(0);
+
+        member["xp"] = json!(xp);
+        member["streaks"] = streaks;
+
+        self.record(guild_id, member_id, old_xp, xp, "restore", actor);
+    }
+
+    pub(crate) fn set_xp(
+        &mut self,
+        guild_id: &str,
+        member_id: &str,
+        xp: u64,
+        user_data: Value,
+        reason: &str,
+        actor: Option<String>,
+    ) {
+        let member = self.member(guild_id, member_id);
+        let old_xp = member["xp"].as_u64().unwrap_or(0);
+
+        member["xp"] = json!(xp);
+        member["userData"] = user_data;
+
+        self.record(guild_id, member_id, old_xp, xp, reason, actor);
+    }
+
+    // applies `delta` like the api does, xp never drops below 0
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn increment(
+        &mut self,
+        guild_id: &str,
+        member_id: &str,
+        delta: i64,
+        reason: &str,
+        user_data: Option<Value>,
+        message_cooldown: Option<u64>,
+        actor: Option<String>,
+    ) -> Value {
+        let member = self.member(guild_id, member_id);
+        let old_xp = member["xp"].as_u64().unwrap_or(0);
+        let new_xp = (old_xp as i64 + delta).max(0) as u64;
+
+        member["xp"] = json!(new_xp);
+        if let Some(user_data) = user_data {
+            member["userData"] = user_data;
+        }
+        if let Some(message_cooldown) = message_cooldown {
+            member["timestamps"]["message_cooldown"] = json!(message_cooldown);
+        }
+
+        self.record(guild_id, member_id, old_xp, new_xp, reason, actor);
+
+        json!({
+            "xp": new_xp,
+            "oldLevel": level(old_xp),
+            "newLevel": level(new_xp),
+        })
+    }
+
+    // newest entries first
+    pub(crate) fn ledger(&self, guild_id: &str, member_id: &str, limit: usize) -> Vec<Value> {
+        self.ledger
+            .get(&(guild_id.to_string(), member_id.to_string()))
+            .map(|entries| entries.iter().rev().take(limit).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn record(
+        &mut self,
+        guild_id: &str,
+        member_id: &str,
+        old_xp: u64,
+        new_xp: u64,
+        reason: &str,
+        actor: Option<String>,
+    ) {
+        if old_xp == new_xp {
+            return;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        self.ledger
+            .entry((guild_id.to_string(), member_id.to_string()))
+            .or_default()
+            .push(json!({
+                "delta": new_xp as i64 - old_xp as i64,
+                "xp": new_xp,
+                "reason": reason,
+                "actor": actor,
+                "timestamp": timestamp,
+            }));
+    }
+
+    pub(crate) fn applied_write(&self, key: &str) -> Option<Value> {
+        self.applied_writes.get(key).cloned()
+    }
+
+    pub(crate) fn remember_write(&mut self, key: String, response: Value) {
+        self.applied_writes.insert(key, response);
+    }
+
+    pub(crate) fn user(&mut self, user_id: &str) -> &mut Value {
+        self.users
+            .entry(user_id.to_string())
+            .or_insert_with(|| self.defaults.user.clone())
+    }
+
+    pub(crate) fn user_premium(&self, user_id: &str) -> Value {
+        self.user_premium
+            .get(user_id)
+            .unwrap_or(&self.defaults.userPremium)
+            .clone()
+    }
+
+    pub(crate) fn backgrounds(&self, user_id: &str) -> Vec<Value> {
+        self.backgrounds.get(user_id).cloned().unwrap_or_default()
+    }
+
+    pub(crate) fn background(&self, user_id: &str, background_id: i64) -> Option<Value> {
+        self.backgrounds
+            .get(user_id)?
+            .iter()
+            .find(|background| background["id"].as_i64() == Some(background_id))
+            .cloned()
+    }
+
+    // uploads are not kept, only the metadata the api would return
+    pub(crate) fn add_background(&mut self, user_id: &str) -> Value {
+        let background = json!({ "id": self.next_background_id, "custom": true, "url": null });
+        self.next_background_id += 1;
+
+        self.backgrounds
+            .entry(user_id.to_string())
+            .or_default()
+            .push(background.clone());
+        background
+    }
+
+    pub(crate) fn delete_background(&mut self, user_id: &str, background_id: i64) {
+        if let Some(backgrounds) = self.backgrounds.get_mut(user_id) {
+            backgrounds.retain(|background| background["id"].as_i64() != Some(background_id));
+        }
+    }
+}